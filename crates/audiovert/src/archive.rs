@@ -1,13 +1,20 @@
 mod _7z;
+mod cache;
+pub(crate) use self::cache::Cache;
 mod rar;
+mod tar;
 mod zip;
 
 use core::fmt;
 use core::str::FromStr;
 
+use std::fs::File;
+use std::io::Read;
 use std::path::Path;
 
 use anyhow::Result;
+use mediavert_common::archive::is_tar_gz;
+pub(crate) use mediavert_common::archive::stem;
 use relative_path::RelativePath;
 
 pub(crate) struct ArchiveErr;
@@ -17,6 +24,8 @@ pub(crate) enum Archive {
     Zip,
     Rar,
     _7z,
+    Tar,
+    TarGz,
 }
 
 impl Archive {
@@ -26,9 +35,37 @@ impl Archive {
             "zip" => Some(Archive::Zip),
             "rar" => Some(Archive::Rar),
             "7z" => Some(Archive::_7z),
+            "tar" => Some(Archive::Tar),
+            "tgz" => Some(Archive::TarGz),
             _ => None,
         }
     }
+
+    /// Detect the kind of an archive with the given extension from the magic
+    /// bytes at its start, since archives are sometimes misnamed. If they
+    /// aren't recognized the kind is determined by the extension.
+    pub(crate) fn detect(path: &Path, ext: &str) -> Result<Option<Self>> {
+        let kind = match Self::from_ext(ext) {
+            Some(kind) => kind,
+            None if is_tar_gz(path) => Archive::TarGz,
+            None => return Ok(None),
+        };
+
+        let mut magic = Vec::with_capacity(6);
+        File::open(path)?.take(6).read_to_end(&mut magic)?;
+
+        let detected = match magic.as_slice() {
+            [b'P', b'K', 3, 4, ..] | [b'P', b'K', 5, 6, ..] => Archive::Zip,
+            [b'R', b'a', b'r', b'!', 0x1a, 0x07] => Archive::Rar,
+            [b'7', b'z', 0xbc, 0xaf, 0x27, 0x1c] => Archive::_7z,
+            // Anything can be compressed with gzip, so only tar archives are
+            // assumed to be compressed.
+            [0x1f, 0x8b, ..] if matches!(kind, Archive::Tar) => Archive::TarGz,
+            _ => kind,
+        };
+
+        Ok(Some(detected))
+    }
 }
 
 impl Archive {
@@ -42,6 +79,8 @@ impl Archive {
             Self::Rar => self::rar::enumerate(path, sources),
             Self::Zip => self::zip::enumerate(path, sources),
             Self::_7z => self::_7z::enumerate(path, sources),
+            Self::Tar => self::tar::enumerate(path, false, sources),
+            Self::TarGz => self::tar::enumerate(path, true, sources),
         }
     }
 
@@ -55,6 +94,8 @@ impl Archive {
             Archive::Rar => self::rar::contents(archive_path, path),
             Archive::Zip => self::zip::contents(archive_path, path),
             Archive::_7z => self::_7z::contents(archive_path, path),
+            Archive::Tar => self::tar::contents(archive_path, false, path),
+            Archive::TarGz => self::tar::contents(archive_path, true, path),
         }
     }
 }
@@ -66,6 +107,8 @@ impl fmt::Display for Archive {
             Archive::Zip => write!(f, "zip"),
             Archive::Rar => write!(f, "rar"),
             Archive::_7z => write!(f, "7z"),
+            Archive::Tar => write!(f, "tar"),
+            Archive::TarGz => write!(f, "tar.gz"),
         }
     }
 }
@@ -79,6 +122,8 @@ impl FromStr for Archive {
             "zip" => Ok(Archive::Zip),
             "rar" => Ok(Archive::Rar),
             "7z" => Ok(Archive::_7z),
+            "tar" => Ok(Archive::Tar),
+            "tar.gz" => Ok(Archive::TarGz),
             _ => Err(ArchiveErr),
         }
     }
@@ -0,0 +1,195 @@
+use std::fs;
+#[cfg(test)]
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use anyhow::Result;
+use relative_path::RelativePath;
+
+use crate::config::{ArchiveId, SourceArchive};
+
+use super::Archive;
+use super::tar::{self, Index};
+use super::zip;
+
+/// The number of bytes of archives to keep in memory.
+const CAPACITY: u64 = 256 * 1024 * 1024;
+
+/// An in-memory cache of archives, so that reading many files out of the
+/// same archive doesn't read and parse it again for every file.
+///
+/// Zip archives and decompressed gzip-compressed tar archives are kept in
+/// memory in full, while only the locations of the files in uncompressed tar
+/// archives are kept. The cache is bounded by the total size of
+/// what's in it, where the least recently used archives are evicted first.
+/// Archives which are larger than the cache and other kinds of archives are
+/// read directly.
+pub(crate) struct Cache {
+    state: Mutex<State>,
+    /// The number of times an archive has been read into the cache.
+    #[cfg(test)]
+    loads: AtomicUsize,
+}
+
+#[derive(Default)]
+struct State {
+    size: u64,
+    /// Cached archives, from the least to the most recently used.
+    entries: Vec<Entry>,
+}
+
+struct Entry {
+    id: ArchiveId,
+    size: u64,
+    loaded: Loaded,
+}
+
+/// A cached archive, which is cheap to clone.
+#[derive(Clone)]
+enum Loaded {
+    Zip(zip::Loaded),
+    Tar(Arc<Index>),
+    TarGz(Arc<[u8]>, Arc<Index>),
+}
+
+impl Cache {
+    /// Construct a new empty cache.
+    pub(crate) fn new() -> Self {
+        Self {
+            state: Mutex::new(State::default()),
+            #[cfg(test)]
+            loads: AtomicUsize::new(0),
+        }
+    }
+
+    /// The number of times an archive has been read into the cache.
+    #[cfg(test)]
+    pub(crate) fn loads(&self) -> usize {
+        self.loads.load(Ordering::Relaxed)
+    }
+
+    /// Extract the contents of a file inside the given archive.
+    pub(crate) fn contents(
+        &self,
+        id: ArchiveId,
+        archive: &SourceArchive,
+        path: &RelativePath,
+    ) -> Result<Option<Vec<u8>>> {
+        match archive.kind {
+            Archive::Zip => self.zip(id, archive, path),
+            Archive::Tar => self.tar(id, archive, path),
+            Archive::TarGz => self.tar_gz(id, archive, path),
+            Archive::Rar | Archive::_7z => archive.kind.contents(&archive.path, path),
+        }
+    }
+
+    fn tar(
+        &self,
+        id: ArchiveId,
+        archive: &SourceArchive,
+        path: &RelativePath,
+    ) -> Result<Option<Vec<u8>>> {
+        let index = match self.get(id) {
+            Some(Loaded::Tar(index)) => index,
+            _ => {
+                let index = Arc::new(tar::index(&archive.path, false)?);
+                #[cfg(test)]
+                self.loads.fetch_add(1, Ordering::Relaxed);
+
+                if index.size <= CAPACITY {
+                    self.insert(id, index.size, Loaded::Tar(index.clone()));
+                }
+
+                index
+            }
+        };
+
+        tar::read(&archive.path, &index, path)
+    }
+
+    fn tar_gz(
+        &self,
+        id: ArchiveId,
+        archive: &SourceArchive,
+        path: &RelativePath,
+    ) -> Result<Option<Vec<u8>>> {
+        let (data, index) = match self.get(id) {
+            Some(Loaded::TarGz(data, index)) => (data, index),
+            _ => {
+                // Archives which are too large to keep decompressed are read
+                // through up to the file instead.
+                let Some(data) = tar::decompress(&archive.path, CAPACITY)? else {
+                    return archive.kind.contents(&archive.path, path);
+                };
+
+                let data = Arc::<[u8]>::from(data);
+                #[cfg(test)]
+                self.loads.fetch_add(1, Ordering::Relaxed);
+                let index = Arc::new(tar::index_from(&data[..])?);
+                let size = data.len() as u64 + index.size;
+
+                if size <= CAPACITY {
+                    self.insert(id, size, Loaded::TarGz(data.clone(), index.clone()));
+                }
+
+                (data, index)
+            }
+        };
+
+        Ok(tar::slice(&data, &index, path)?.map(<[u8]>::to_vec))
+    }
+
+    fn zip(
+        &self,
+        id: ArchiveId,
+        archive: &SourceArchive,
+        path: &RelativePath,
+    ) -> Result<Option<Vec<u8>>> {
+        // The loaded archive shares its data and central directory with the
+        // cached one, so files can be read without holding the lock.
+        let mut loaded = match self.get(id) {
+            Some(Loaded::Zip(loaded)) => loaded,
+            _ => {
+                let size = fs::metadata(&archive.path)?.len();
+
+                if size > CAPACITY {
+                    return archive.kind.contents(&archive.path, path);
+                }
+
+                let loaded = zip::load(Arc::from(fs::read(&archive.path)?))?;
+                #[cfg(test)]
+                self.loads.fetch_add(1, Ordering::Relaxed);
+                self.insert(id, size, Loaded::Zip(loaded.clone()));
+                loaded
+            }
+        };
+
+        zip::read(&mut loaded, path)
+    }
+
+    fn get(&self, id: ArchiveId) -> Option<Loaded> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let index = state.entries.iter().position(|e| e.id == id)?;
+        let entry = state.entries.remove(index);
+        let loaded = entry.loaded.clone();
+        state.entries.push(entry);
+        Some(loaded)
+    }
+
+    fn insert(&self, id: ArchiveId, size: u64, loaded: Loaded) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+
+        // Another thread might have loaded the same archive in the meantime.
+        if state.entries.iter().any(|e| e.id == id) {
+            return;
+        }
+
+        while state.size + size > CAPACITY && !state.entries.is_empty() {
+            let evicted = state.entries.remove(0);
+            state.size -= evicted.size;
+        }
+
+        state.size += size;
+        state.entries.push(Entry { id, size, loaded });
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use relative_path::RelativePath;
+use tar::Entry;
+
+/// Where the data of a file is stored in the uncompressed tar stream.
+#[derive(Clone, Copy)]
+struct Location {
+    offset: u64,
+    size: u64,
+}
+
+/// The locations of the files in a tar archive.
+///
+/// Tar archives have no central directory, so finding a file requires
+/// reading through the archive up to it. The index is built by reading
+/// through it once, after which files can be read from their offset.
+pub(super) struct Index {
+    files: HashMap<String, Location>,
+    /// An estimate of the number of bytes used by the index.
+    pub(super) size: u64,
+}
+
+pub(super) fn enumerate(
+    archive_path: &Path,
+    gzip: bool,
+    sources: &mut dyn FnMut(&RelativePath) -> Result<()>,
+) -> Result<()> {
+    let mut archive = tar::Archive::new(open(archive_path, gzip)?);
+
+    for entry in archive.entries().context("opening archive")? {
+        let entry = entry?;
+
+        if let Some(name) = name(&entry)? {
+            sources(RelativePath::new(&name))?;
+        }
+    }
+
+    Ok(())
+}
+
+pub(super) fn contents(
+    archive_path: &Path,
+    gzip: bool,
+    path: &RelativePath,
+) -> Result<Option<Vec<u8>>> {
+    let mut archive = tar::Archive::new(open(archive_path, gzip)?);
+
+    for entry in archive.entries().context("opening archive")? {
+        let mut entry = entry?;
+
+        if name(&entry)?.as_deref() != Some(path.as_str()) {
+            continue;
+        }
+
+        let mut contents = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut contents)?;
+        return Ok(Some(contents));
+    }
+
+    Ok(None)
+}
+
+/// Read through an archive to index the files in it.
+pub(super) fn index(archive_path: &Path, gzip: bool) -> Result<Index> {
+    index_from(open(archive_path, gzip)?)
+}
+
+/// Read through an uncompressed tar stream to index the files in it.
+pub(super) fn index_from(reader: impl Read) -> Result<Index> {
+    let mut archive = tar::Archive::new(reader);
+    let mut files = HashMap::new();
+    let mut size = 0;
+
+    for entry in archive.entries().context("opening archive")? {
+        let entry = entry?;
+
+        let Some(name) = name(&entry)? else {
+            continue;
+        };
+
+        let location = Location {
+            offset: entry.raw_file_position(),
+            size: entry.size(),
+        };
+
+        size += (name.len() + size_of::<Location>()) as u64;
+        files.insert(name, location);
+    }
+
+    Ok(Index { files, size })
+}
+
+/// Decompress a gzip-compressed archive into memory, unless it's larger than
+/// `limit` bytes when decompressed.
+///
+/// Compressed archives can't be seeked, so reading a file from them means
+/// decompressing everything before it. Keeping them decompressed avoids doing
+/// that again for every file.
+pub(super) fn decompress(archive_path: &Path, limit: u64) -> Result<Option<Vec<u8>>> {
+    let mut data = Vec::new();
+    open(archive_path, true)?
+        .take(limit.saturating_add(1))
+        .read_to_end(&mut data)?;
+
+    if data.len() as u64 > limit {
+        return Ok(None);
+    }
+
+    Ok(Some(data))
+}
+
+/// Read the contents of a file in an uncompressed archive at the location
+/// recorded in the index.
+pub(super) fn read(
+    archive_path: &Path,
+    index: &Index,
+    path: &RelativePath,
+) -> Result<Option<Vec<u8>>> {
+    let Some(&Location { offset, size }) = index.files.get(path.as_str()) else {
+        return Ok(None);
+    };
+
+    let mut file = File::open(archive_path)?;
+    file.seek(SeekFrom::Start(offset))?;
+
+    let mut contents = Vec::with_capacity(size as usize);
+    file.take(size).read_to_end(&mut contents)?;
+
+    if contents.len() as u64 != size {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+
+    Ok(Some(contents))
+}
+
+/// Get the contents of a file in a decompressed archive at the location
+/// recorded in the index.
+pub(super) fn slice<'a>(
+    data: &'a [u8],
+    index: &Index,
+    path: &RelativePath,
+) -> Result<Option<&'a [u8]>> {
+    let Some(&Location { offset, size }) = index.files.get(path.as_str()) else {
+        return Ok(None);
+    };
+
+    let contents = usize::try_from(offset)
+        .ok()
+        .zip(usize::try_from(offset + size).ok())
+        .and_then(|(start, end)| data.get(start..end));
+
+    let Some(contents) = contents else {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    };
+
+    Ok(Some(contents))
+}
+
+fn open(archive_path: &Path, gzip: bool) -> Result<Box<dyn Read>> {
+    let file = BufReader::new(File::open(archive_path)?);
+
+    if gzip {
+        Ok(Box::new(GzDecoder::new(file)))
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+/// Get the name of a regular file in an archive, without a leading `./`.
+fn name<R>(entry: &Entry<'_, R>) -> Result<Option<String>>
+where
+    R: Read,
+{
+    if !entry.header().entry_type().is_file() {
+        return Ok(None);
+    }
+
+    let path = entry.path()?;
+
+    let Some(name) = path.to_str() else {
+        return Ok(None);
+    };
+
+    Ok(Some(name.strip_prefix("./").unwrap_or(name).to_owned()))
+}
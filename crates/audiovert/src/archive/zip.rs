@@ -1,11 +1,15 @@
 use std::fs::File;
-use std::io::Read;
+use std::io::{Cursor, Read, Seek};
 use std::path::Path;
+use std::sync::Arc;
 
 use anyhow::{Context, Result};
 use relative_path::RelativePath;
 use zip::ZipArchive;
 
+/// A zip archive which has been read into memory.
+pub(super) type Loaded = ZipArchive<Cursor<Arc<[u8]>>>;
+
 pub(super) fn enumerate(
     archive_path: &Path,
     sources: &mut dyn FnMut(&RelativePath) -> Result<()>,
@@ -27,18 +31,25 @@ pub(super) fn contents(
 ) -> anyhow::Result<Option<Vec<u8>>> {
     let reader = File::open(archive_path)?;
     let mut archive = ZipArchive::new(reader).context("opening archive")?;
+    read(&mut archive, path)
+}
 
-    for i in 0..archive.len() {
-        let mut file = archive.by_index(i)?;
-
-        if file.name() != path.as_str() {
-            continue;
-        }
-
-        let mut contents = Vec::with_capacity(file.size() as usize);
-        file.read_to_end(&mut contents)?;
-        return Ok(Some(contents));
-    }
+/// Parse an archive which has been read into memory.
+pub(super) fn load(data: Arc<[u8]>) -> Result<Loaded> {
+    ZipArchive::new(Cursor::new(data)).context("opening archive")
+}
 
-    Ok(None)
+/// Read the contents of a file in an opened archive.
+pub(super) fn read<R>(archive: &mut ZipArchive<R>, path: &RelativePath) -> Result<Option<Vec<u8>>>
+where
+    R: Read + Seek,
+{
+    let Some(index) = archive.index_for_name(path.as_str()) else {
+        return Ok(None);
+    };
+
+    let mut file = archive.by_index(index)?;
+    let mut contents = Vec::with_capacity(file.size() as usize);
+    file.read_to_end(&mut contents)?;
+    Ok(Some(contents))
 }
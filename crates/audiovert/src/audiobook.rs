@@ -0,0 +1,304 @@
+//! Concatenating the tracks of an album into a single `.m4b` audiobook with
+//! chapters, as used by `--m4b`.
+
+use core::fmt::Write as _;
+use core::mem;
+
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use crate::config::{Config, Source};
+use crate::conversion::{ConversionResult, Outcome};
+use crate::format::Format;
+use crate::link::MaybeLink;
+use crate::meta::Parts;
+use crate::out::{Out, blank, error, info, warn};
+use crate::shell::FormatCommand;
+use crate::tasks::Tasks;
+
+/// The extension of audiobooks.
+const EXT: &str = "m4b";
+
+/// A track which becomes a chapter of an audiobook.
+pub(crate) struct Chapter {
+    pub(crate) source: Source,
+    pub(crate) parts: Parts,
+}
+
+/// The tracks of an album which are concatenated into one audiobook.
+struct Book<'a> {
+    artist: &'a str,
+    album: &'a str,
+    year: i16,
+    chapters: Vec<&'a Chapter>,
+}
+
+/// Build an audiobook for each album among the collected chapters.
+pub(crate) fn run(
+    o: &mut Out<'_>,
+    config: &Config,
+    mut tasks: Tasks,
+) -> Result<Vec<ConversionResult>> {
+    let mut results = mem::take(&mut tasks.results);
+    let mut books = BTreeMap::<_, Book<'_>>::new();
+
+    for chapter in &tasks.chapters {
+        let parts = &chapter.parts;
+
+        let artist = if parts.compilation {
+            config.layout.various_artists.as_str()
+        } else {
+            parts.artist.as_str()
+        };
+
+        books
+            .entry((artist, parts.album.as_str(), parts.year))
+            .or_insert_with(|| Book {
+                artist,
+                album: &parts.album,
+                year: parts.year,
+                chapters: Vec::new(),
+            })
+            .chapters
+            .push(chapter);
+    }
+
+    let total = books.len();
+
+    for (index, book) in books.into_values().enumerate() {
+        let start = Instant::now();
+
+        info!(
+            o,
+            "Book #{}/#{total}: {} - {} ({})",
+            index.saturating_add(1),
+            book.artist,
+            book.album,
+            book.year
+        );
+
+        let mut o = o.indent(1);
+
+        let (outcome, reason, to_path) = build(&mut o, config, &tasks, book)?;
+
+        let output_bytes = match outcome {
+            Outcome::Ok if !config.dry_run => fs::metadata(&to_path).ok().map(|m| m.len()),
+            _ => None,
+        };
+
+        results.push(ConversionResult {
+            source: to_path.parent().unwrap_or(Path::new("")).to_owned(),
+            destination: Some(to_path),
+            outcome,
+            reason,
+            input_bytes: None,
+            output_bytes,
+            elapsed: start.elapsed(),
+        });
+    }
+
+    Ok(results)
+}
+
+/// Build a single audiobook, returning its outcome and destination.
+fn build(
+    o: &mut Out<'_>,
+    config: &Config,
+    tasks: &Tasks,
+    mut book: Book<'_>,
+) -> Result<(Outcome, Option<String>, PathBuf)> {
+    book.chapters.sort_by(|a, b| {
+        let a = (a.parts.set.map(|(n, _)| n), a.parts.track, &a.parts.title);
+        let b = (b.parts.set.map(|(n, _)| n), b.parts.track, &b.parts.title);
+        a.cmp(&b)
+    });
+
+    let mut to_path = config.to_dir.clone().unwrap_or_default();
+    let sanitize = &config.layout.sanitize;
+    sanitize.push(&mut to_path, book.artist);
+    sanitize.push_file_name(&mut to_path, &format!("{} ({})", book.album, book.year))?;
+    to_path.add_extension(EXT);
+
+    let to_path = MaybeLink::new(to_path);
+    o.link("to", &to_path)?;
+    let to_path = to_path.to_path_buf();
+
+    if to_path.exists() {
+        if !config.force {
+            warn!(o, "already exists (--force to remove)");
+            let reason = "destination already exists".to_string();
+            return Ok((Outcome::Skipped, Some(reason), to_path));
+        }
+
+        blank!(o, "rm <to>");
+
+        if !config.dry_run
+            && let Err(e) = fs::remove_file(&to_path)
+        {
+            error!(o, "{e}");
+            return Ok((Outcome::Failed, Some(e.to_string()), to_path));
+        }
+    }
+
+    let mut list = String::new();
+    let mut metadata = String::new();
+    let mut offset = Duration::ZERO;
+
+    metadata.push_str(";FFMETADATA1\n");
+    _ = writeln!(metadata, "title={}", escape(book.album));
+    _ = writeln!(metadata, "album={}", escape(book.album));
+    _ = writeln!(metadata, "artist={}", escape(book.artist));
+    _ = writeln!(metadata, "album_artist={}", escape(book.artist));
+    _ = writeln!(metadata, "date={}", book.year);
+
+    for chapter in &book.chapters {
+        let Source::File { file } = &chapter.source else {
+            let path = tasks.db.to_path(&chapter.source)?;
+            let reason = format!("archive sources are not supported: {}", path.display());
+            error!(o, "{reason}");
+            return Ok((Outcome::Failed, Some(reason), to_path));
+        };
+
+        let path = tasks.db.file(*file)?;
+
+        let duration = tasks
+            .meta
+            .get(&chapter.source)
+            .map(|m| m.duration())
+            .unwrap_or_default();
+
+        if duration.is_zero() {
+            let reason = format!("could not determine duration: {}", path.display());
+            error!(o, "{reason}");
+            return Ok((Outcome::Failed, Some(reason), to_path));
+        }
+
+        let end = offset.saturating_add(duration);
+
+        if config.verbose {
+            blank!(
+                o,
+                "chapter {} at {}",
+                chapter.parts.title,
+                timestamp(offset)
+            );
+        }
+
+        _ = writeln!(
+            list,
+            "file '{}'",
+            path.display().to_string().replace('\'', "'\\''")
+        );
+        _ = writeln!(metadata);
+        _ = writeln!(metadata, "[CHAPTER]");
+        _ = writeln!(metadata, "TIMEBASE=1/1000");
+        _ = writeln!(metadata, "START={}", offset.as_millis());
+        _ = writeln!(metadata, "END={}", end.as_millis());
+        _ = writeln!(metadata, "title={}", escape(&chapter.parts.title));
+        offset = end;
+    }
+
+    blank!(
+        o,
+        "{} chapters, {} total",
+        book.chapters.len(),
+        timestamp(offset)
+    );
+
+    let part_path = to_path.with_added_extension(&config.part_ext);
+    let list_path = part_path.with_added_extension("txt");
+    let metadata_path = part_path.with_added_extension("ffmeta");
+
+    let mut command = Command::new(&config.ffmpeg);
+    command.args(["-hide_banner", "-loglevel", "error"]);
+    command.args(["-f", "concat", "-safe", "0"]);
+    command.args([OsStr::new("-i"), list_path.as_os_str()]);
+    command.args([OsStr::new("-i"), metadata_path.as_os_str()]);
+    command.args(["-map", "0:a", "-map_metadata", "1", "-map_chapters", "1"]);
+    command.args(["-c:a", "aac"]);
+    Format::Aac.bitrate(config, &mut command);
+    command.args(["-f", "ipod"]);
+    command.arg(&part_path);
+
+    let mut f = FormatCommand::new(&command);
+
+    if !config.verbose {
+        f.replace(config.ffmpeg.as_os_str(), "<ffmpeg>");
+        f.replace(
+            list_path.as_os_str(),
+            format!("<to>.{}.txt", config.part_ext),
+        );
+        f.replace(
+            metadata_path.as_os_str(),
+            format!("<to>.{}.ffmeta", config.part_ext),
+        );
+        f.replace(part_path.as_os_str(), format!("<to>.{}", config.part_ext));
+    }
+
+    if !config.make_dir(o, "audiobook", &to_path)? {
+        let reason = "failed to create audiobook directory".to_string();
+        return Ok((Outcome::Failed, Some(reason), to_path));
+    }
+
+    blank!(o, "{f}");
+
+    if config.dry_run {
+        blank!(o, "mv <to>.{} <to>", config.part_ext);
+        return Ok((Outcome::Ok, None, to_path));
+    }
+
+    let status = fs::write(&list_path, list)
+        .and_then(|()| fs::write(&metadata_path, metadata))
+        .and_then(|()| command.status());
+
+    for path in [&list_path, &metadata_path] {
+        _ = fs::remove_file(path);
+    }
+
+    let failure = match status {
+        Ok(status) if status.success() => None,
+        Ok(status) => Some(format!("ffmpeg failed: {status}")),
+        Err(e) => Some(e.to_string()),
+    };
+
+    if let Some(reason) = failure {
+        error!(o, "{reason}");
+        return Ok((Outcome::Failed, Some(reason), to_path));
+    }
+
+    blank!(o, "mv <to>.{} <to>", config.part_ext);
+
+    if let Err(e) = fs::rename(&part_path, &to_path) {
+        error!(o, "{e}");
+        return Ok((Outcome::Failed, Some(e.to_string()), to_path));
+    }
+
+    Ok((Outcome::Ok, None, to_path))
+}
+
+/// Escape a value in an ffmpeg metadata file.
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            out.push('\\');
+        }
+
+        out.push(c);
+    }
+
+    out
+}
+
+/// Format a duration as `h:mm:ss`.
+fn timestamp(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
@@ -2,10 +2,11 @@ use std::collections::HashMap;
 
 use crate::format::Format;
 
-const DEFAULT_BITRATES: [(Format, u32); 3] = [
+const DEFAULT_BITRATES: [(Format, u32); 4] = [
     (Format::Aac, Format::DEFAULT_BITRATE_AAC),
     (Format::Mp3, Format::DEFAULT_BITRATE_MP3),
     (Format::Ogg, Format::DEFAULT_BITRATE_OGG),
+    (Format::Opus, Format::DEFAULT_BITRATE_OPUS),
 ];
 
 pub(crate) struct Bitrates {
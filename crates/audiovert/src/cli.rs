@@ -1,29 +1,49 @@
 use core::cell::Cell;
+use core::mem;
 
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
 use std::ffi::{OsStr, OsString};
 use std::fs;
-use std::io::Write;
-use std::path::PathBuf;
+use std::io::{self, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
 use std::process::{Command, ExitStatus, Stdio};
+use std::sync::mpsc;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use anyhow::{self, Context, Result, bail};
 use clap::Parser;
+use lofty::picture::Picture;
+use mediavert_common::filter::{Filter, Pattern};
 use relative_path::RelativePath;
-use termcolor::{ColorChoice, StandardStream};
+use serde::Serialize;
+use termcolor::{Buffer, ColorChoice, StandardStream};
 
+use crate::audiobook;
 use crate::bitrates::Bitrates;
 use crate::condition::{Condition, FromCondition, ToCondition};
 use crate::config::{ArchiveId, Config, Db, Source};
+use crate::conversion::{ConversionResult, Outcome};
 use crate::format::Format;
-use crate::link::MaybeLink;
+use crate::gain::ReplayGain;
+use crate::link::{Link, MaybeLink};
+use crate::manifest;
+use crate::media_type::{MediaTypes, SetMediaType};
+use crate::meta::{self, DiscDirs, Layout, Meta, TagDump};
 use crate::out::{Colors, Out, blank, error, info, warn};
+use crate::progress::Progress;
+use crate::sanitize::{DEFAULT_MAX_LEN, Sanitize, SetSanitize, UnicodeForm};
 use crate::set_bit_rate::SetBitRate;
+use crate::settings;
 use crate::shell::{self, FormatCommand};
 use crate::tasks::{
-    Exists, MatchingConversion, TaskKind, Tasks, TransferKind, Trash, TrashWhat, Unsupported,
+    Exists, LowBitrate, MatchingConversion, Task, TaskKind, Tasks, TransferKind, Trash, TrashWhat,
+    Truncated, Unsupported, YearFallback,
 };
+use crate::template::Template;
 
 const PART: &str = "part";
 
@@ -34,6 +54,12 @@ pub struct Audiovert {
     /// the destination file also exists.
     #[arg(short = 'f', long)]
     force: bool,
+    /// If set, only skips existing destination files which are newer than
+    /// their source, converting the source again if it has been modified since.
+    /// By default existing destination files are always skipped unless
+    /// `--force` is used.
+    #[arg(long)]
+    skip_existing: bool,
     /// If set, enables verbose output.
     #[arg(short = 'v', long)]
     verbose: bool,
@@ -44,6 +70,14 @@ pub struct Audiovert {
     /// If set, source files are trashed after successful conversion.
     #[arg(short = 'r', long)]
     trash_source: bool,
+    /// If set, source files are deleted after they have been successfully
+    /// converted, tagged and verified if `--verify` is used. Files which are
+    /// converted to multiple formats are only deleted once all conversions
+    /// succeed. Sources are never deleted if one of their destinations
+    /// already existed, since it wasn't converted by this run. Archives are
+    /// never deleted.
+    #[arg(long, conflicts_with = "trash_source")]
+    delete_source: bool,
     /// Conversion pairs to perform, like flac=mp3 which would mean converting
     /// from flac to mp3. This also takes special values like lossless=<format>,
     /// lossy=<format> or same.
@@ -62,9 +96,46 @@ pub struct Audiovert {
     /// verbose.
     #[arg(short = 'D', long)]
     dry_run: bool,
+    /// If set, prints the destination each file would be converted or
+    /// transferred to and which files would be skipped, without making any
+    /// changes. Files with errors are reported as skipped instead of aborting.
+    #[arg(long)]
+    plan: bool,
     /// If set, continues processing files even if errors are encountered.
     #[arg(short = 'k', long)]
     keep_going: bool,
+    /// The number of files to convert in parallel. Output of each file is
+    /// printed once it's done. Defaults to the number of logical CPUs.
+    #[arg(short = 'j', long, value_name = "n")]
+    jobs: Option<NonZeroUsize>,
+    /// If set, checks that each converted file can be read back and that it
+    /// has the title, artist, album and track number of its source before
+    /// moving it into place. Files which fail are reported and left as
+    /// partial files.
+    #[arg(long)]
+    verify: bool,
+    /// If set, removes partial files which fail `--verify` instead of leaving
+    /// them for inspection.
+    #[arg(long, requires = "verify")]
+    verify_remove: bool,
+    /// If set, shows a progress bar with the number of files converted instead
+    /// of the output of each file. Output is still shown for files which fail
+    /// to convert. Progress is printed periodically if stderr isn't a
+    /// terminal.
+    #[arg(long)]
+    progress: bool,
+    /// Write a manifest of SHA-256 checksums of the files produced in this run
+    /// to the given path, in the format used by `sha256sum`. The source and
+    /// metadata of each file is included as comments. An existing manifest is
+    /// overwritten.
+    #[arg(long, value_name = "path")]
+    manifest: Option<PathBuf>,
+    /// If set, concatenates the tracks of each album into a single `.m4b`
+    /// audiobook in the `--to` directory, with a chapter for each track. The
+    /// chapters are ordered by disc and track number and are titled after
+    /// each track. This implies `--meta`.
+    #[arg(long, requires = "to", conflicts_with = "plan")]
+    m4b: bool,
     /// Output base directory for converted files.
     #[arg(short = 'o', long)]
     to: Option<PathBuf>,
@@ -77,12 +148,152 @@ pub struct Audiovert {
     /// {Artist} / {Album} ({Year}) / {Artist} - {Track Number} - {Title}.{ext}
     #[arg(long)]
     meta: bool,
+    /// If set, only checks that all files have the tags required by `--meta`
+    /// without converting anything, reporting the files which don't. Exits
+    /// with an error if any file is missing required tags.
+    #[arg(long)]
+    validate: bool,
+    /// If set, adds a top-level directory named after the genre to paths
+    /// determined by `--meta`, like:
+    ///
+    /// {Genre} / {Artist} / {Album} ({Year}) / ...
+    ///
+    /// Files without a genre are put in `Unknown Genre`.
+    #[arg(long)]
+    meta_genre: bool,
+    /// If set, uses the composer instead of the artist as the top-level
+    /// directory of paths determined by `--meta`, which suits classical music.
+    /// The performing artist is added to the end of file names.
+    ///
+    /// Files without a composer use the artist as usual.
+    #[arg(long)]
+    meta_composer: bool,
+    /// The directory used instead of the artist by `--meta` for compilations,
+    /// which are albums flagged as compilations or with an album artist like
+    /// `Various Artists`. The artist of each track is added to file names.
+    #[arg(long, default_value = "Various Artists", value_name = "name")]
+    meta_various_artists: String,
+    /// A template for paths determined by `--meta`, used instead of the default
+    /// layout. Each `/` separated part becomes a directory, and the last part
+    /// the file name, like:
+    ///
+    /// {albumartist}/{album} ({year})/{track:02} - {title}
+    ///
+    /// Available placeholders are {artist}, {albumartist}, {trackartist},
+    /// {composer}, {album}, {year}, {genre}, {disc}, {disctotal},
+    /// {discsubtitle}, {mediatype}, {track}, {tracktotal} and {title}. Numbers
+    /// can be zero-padded like {track:02}. The {artist} placeholder respects
+    /// `--meta-composer` and compilations. Parts which are empty, like {genre}
+    /// for files without a genre, are left out.
+    #[arg(long, value_name = "template")]
+    meta_template: Option<Template>,
+    /// If set, moves leading articles of artist directories determined by
+    /// `--meta` to the end, like `Beatles, The`, so that they sort by the
+    /// following word. File names keep the artist as is.
+    #[arg(long)]
+    meta_move_articles: bool,
+    /// When to add a directory for each disc in paths determined by `--meta`,
+    /// which is one of always, multiple or never. With multiple, directories
+    /// are only added for sets with more than one disc.
+    #[arg(long, default_value = "multiple", value_name = "when")]
+    meta_disc_dirs: DiscDirs,
+    /// Override the canonical form of a media type used as a prefix of disc
+    /// directories, like `Digital Media=WEB`. Media types are matched
+    /// regardless of case, and an empty replacement leaves the prefix out.
+    ///
+    /// By default variants like `Compact Disc` become `CD`, `12" Vinyl` and
+    /// `LP` become `Vinyl`, and `Digital Media` becomes `Digital`.
+    #[arg(long, value_name = "type=replacement")]
+    meta_media_type: Vec<SetMediaType>,
+    /// The leading articles moved by `--meta-move-articles`.
+    #[arg(long, value_name = "word", default_values = ["The", "A", "An"])]
+    meta_article: Vec<String>,
+    /// If set, converts artists, albums and titles in paths determined by
+    /// `--meta` to title case, like `THE WALL` to `The Wall`. Words in all caps
+    /// like `ABBA` are kept unless the whole tag is in caps.
+    #[arg(long)]
+    meta_title_case: bool,
+    /// If set, repairs tags which look like UTF-8 text wrongly decoded as
+    /// Latin-1, like `Ã©` instead of `é`, both in paths and in the tags of
+    /// converted files. Otherwise such tags are reported as errors.
+    #[arg(long)]
+    fix_encoding: bool,
+    /// If set, takes the year from a directory name like `Album (1997)` for
+    /// files which are missing a year tag.
+    #[arg(long)]
+    meta_year_from_path: bool,
+    /// Override how a character is replaced in paths determined by `--meta`,
+    /// like `/= - ` to replace `/` with ` - `, or `?=?` to keep `?`.
+    ///
+    /// By default `\` and `/` become `+`, `*` becomes `-`, and `<`, `>`, `?`,
+    /// `|` and `"` are removed. A `:` becomes ` - ` when followed by whitespace
+    /// and `-` otherwise, unless overridden.
+    #[arg(long, value_name = "char=replacement")]
+    meta_sanitize: Vec<SetSanitize>,
+    /// The unicode normalization form of paths determined by `--meta`, which
+    /// is one of nfc, nfd or none. Normalizing avoids directories which look
+    /// the same but are spelled differently. Use nfd on filesystems which
+    /// prefer decomposed characters, like HFS+.
+    #[arg(long, default_value = "nfc", value_name = "form")]
+    meta_unicode: UnicodeForm,
+    /// The maximum length in bytes of each part of paths determined by
+    /// `--meta`. Longer parts are truncated with an ellipsis.
+    #[arg(long, default_value_t = DEFAULT_MAX_LEN, value_name = "bytes")]
+    meta_max_len: usize,
+    /// If set, makes paths determined by `--meta` valid on Windows even when
+    /// running elsewhere, like when sorting onto a shared drive. Reserved names
+    /// like `CON` or `NUL` get an underscore appended and trailing dots and
+    /// spaces are removed. Always enabled on Windows.
+    #[arg(long)]
+    meta_windows_names: bool,
+    /// If set, writes ID3v2.4 tags to mp3 files instead of ID3v2.3. ID3v2.4
+    /// supports UTF-8 and multi-value frames, but isn't supported by some
+    /// older players.
+    #[arg(long)]
+    id3v24: bool,
     /// If set, dumps metadata for each file processed with `--meta`.
     #[arg(long)]
     meta_dump: bool,
+    /// If set, `--meta-dump` emits a JSON record with the tags of each file
+    /// to stdout. Human readable output is written to stderr instead.
+    #[arg(long)]
+    meta_dump_json: bool,
+    /// Only dump tag items whose key contains the given string, ignoring case,
+    /// like `--meta-dump-key title`. Can be specified multiple times.
+    #[arg(long, value_name = "key")]
+    meta_dump_key: Vec<String>,
     /// If set, uses internal metadata writer based on lofty instead of ffmpeg.
     #[arg(long)]
     meta_internal: bool,
+    /// If set, items from secondary tags, like an ID3v1 tag next to an ID3v2
+    /// tag, are merged into converted files when using `--meta-internal`.
+    /// Items in the primary tag take precedence.
+    #[arg(long)]
+    meta_all_tags: bool,
+    /// If set, embedded pictures like album art are not copied to converted
+    /// files, which makes them smaller.
+    #[arg(long)]
+    strip_art: bool,
+    /// Image files to embed as the front cover when converting files without
+    /// embedded pictures, looked for next to each file in the given order.
+    /// This requires `--meta-internal`.
+    #[arg(
+        long,
+        value_name = "name",
+        default_values = ["cover.jpg", "folder.jpg", "front.jpg", "cover.png", "folder.png", "front.png"]
+    )]
+    cover_file: Vec<String>,
+    /// If set, cover image files are never embedded (see `--cover-file`).
+    #[arg(long)]
+    no_cover_file: bool,
+    /// If set, writes the embedded front cover of converted files to a
+    /// `cover.jpg` or `cover.png` file next to them, unless one already exists.
+    #[arg(long)]
+    extract_art: bool,
+    /// If set, recomputes the ReplayGain track gain and peak of converted
+    /// files using ffmpeg instead of copying them from the source.
+    #[arg(long)]
+    replaygain: bool,
     /// If set, dumps metadata for each file processed with `--meta` that has
     /// errors.
     #[arg(long)]
@@ -94,12 +305,27 @@ pub struct Audiovert {
     /// <format>=<number> where <number> is the desired bitrate in kbps. If 0 is
     /// set, then the default bitrate for that format is used.
     ///
-    /// Default bitrates are 320kbps for mp3 and 192kbps for ogg and aac.
+    /// Default bitrates are 320kbps for mp3, 192kbps for ogg and aac, and
+    /// 128kbps for opus.
     #[arg(long)]
     bitrates: Vec<SetBitRate>,
     /// If set, forces re-encoding of the formats specified in --bitrates.
     #[arg(long)]
     force_bitrates: bool,
+    /// Lossy sources with a nominal bitrate at or below this threshold in kbps
+    /// will not be re-encoded, since that would only degrade them further.
+    /// Instead they are transferred as-is.
+    #[arg(long)]
+    min_bitrate: Option<u32>,
+    /// Sources shorter than this number of seconds are reported as errors,
+    /// since they are likely silence or truncated rips. This is also checked
+    /// by `--validate`.
+    #[arg(long, value_name = "secs")]
+    min_duration: Option<u64>,
+    /// If set, sources which fall below `--min-bitrate` are skipped entirely
+    /// instead of being transferred as-is.
+    #[arg(long)]
+    skip_low_bitrate: bool,
     /// Path to ffmpeg binary to use when performing conversions.
     #[arg(long, default_value = "ffmpeg")]
     ffmpeg_bin: PathBuf,
@@ -112,16 +338,610 @@ pub struct Audiovert {
     /// removed.
     #[arg(long, default_value = PART)]
     part_ext: String,
+    /// If set, emits a JSON record for each processed source to stdout. Human
+    /// readable output is written to stderr instead.
+    #[arg(long)]
+    json: bool,
+    /// Compare the tags of two files instead of converting, like a source and
+    /// its converted file. Exits with an error if they differ.
+    #[arg(long, num_args = 2, value_names = ["first", "second"])]
+    compare: Option<Vec<PathBuf>>,
+    /// Tag keys to ignore with `--compare`, like `EncoderSettings`. Can be
+    /// specified multiple times.
+    #[arg(long, value_name = "key")]
+    compare_ignore: Vec<String>,
+    /// Path to a TOML configuration file with defaults for `conversion`,
+    /// `meta-template`, `meta-sanitize` and `jobs`. Options on the command line
+    /// take precedence, where `--conversion` replaces the conversions in the
+    /// file while `--meta-sanitize` is applied on top of the replacements in
+    /// the file. Replacements are given as a table, like:
+    ///
+    /// [meta-sanitize]
+    /// "/" = " - "
+    ///
+    /// Defaults to `audiovert/config.toml` in the user configuration
+    /// directory, if it exists.
+    #[arg(long, value_name = "path")]
+    config: Option<PathBuf>,
+    /// Only process files whose path relative to the path they were found in
+    /// matches this pattern. Can be specified multiple times, where files
+    /// matching any of them are processed.
+    ///
+    /// Patterns are globs, where globs without a `/` match any single part of
+    /// the path, like `Disc 1` or `*.flac`, while globs with a `/` match from
+    /// the start of the path. Patterns prefixed with `re:` are regular
+    /// expressions which are searched for anywhere in the path. Files in
+    /// archives are matched as if the archive was a directory.
+    #[arg(long, value_name = "pattern")]
+    include: Vec<Pattern>,
+    /// Skip files whose path relative to the path they were found in matches
+    /// this pattern, like `*.log`. Can be specified multiple times, where
+    /// files have to match none of them to be processed. This takes precedence
+    /// over `--include`, and uses the same patterns.
+    #[arg(long, value_name = "pattern")]
+    exclude: Vec<Pattern>,
     /// Paths to process.
+    ///
+    /// Paths can be glob patterns like `'music/**/*.flac'`, which select the
+    /// matching files in the directory before the first pattern. Quote them to
+    /// keep the shell from expanding them. `*` doesn't match across
+    /// directories while `**` does, and archives are selected by their own
+    /// name rather than the files in them.
+    ///
+    /// Files found through several paths are only processed once. Explicit
+    /// paths take precedence over globs, so that output paths are relative to
+    /// the explicit path.
     paths: Vec<PathBuf>,
 }
 
-/// Entry for `audiovert`.
-///
-/// See [`crate`] documentation.
-pub fn entry(opts: &Audiovert) -> Result<()> {
-    // Current indentation level for output.
-    let indent = Cell::new(0);
+/// Entry for `audiovert`.
+///
+/// See [`crate`] documentation.
+pub fn entry(opts: &Audiovert) -> Result<()> {
+    entry_with(opts, &mut |_| {})
+}
+
+/// Entry for `audiovert` which calls `on_result` with the result of each
+/// processed source once processing has completed.
+///
+/// See [`crate`] documentation.
+pub fn entry_with(opts: &Audiovert, on_result: &mut dyn FnMut(&ConversionResult)) -> Result<()> {
+    // Current indentation level for output.
+    let indent = Cell::new(0);
+
+    let settings = match &opts.config {
+        Some(path) => settings::File::load(path, true)?,
+        None => match settings::default_path() {
+            Some(path) => settings::File::load(&path, false)?,
+            None => settings::File::default(),
+        },
+    };
+
+    let config = config(opts, &settings)?;
+
+    let cols = Colors::new();
+
+    let o = if opts.json || opts.meta_dump && opts.meta_dump_json {
+        StandardStream::stderr(ColorChoice::Auto)
+    } else {
+        StandardStream::stdout(ColorChoice::Auto)
+    };
+
+    let mut o = o.lock();
+    let mut o = Out::new(&indent, &cols, &mut o);
+
+    if let Some([first, second]) = opts.compare.as_deref() {
+        return compare(&mut o, first, second, &opts.compare_ignore);
+    }
+
+    let results = run(&mut o, &config)?;
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    for result in &results {
+        on_result(result);
+
+        if opts.json {
+            serde_json::to_writer(&mut stdout, result)?;
+            writeln!(stdout)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn compare(o: &mut Out<'_>, first: &Path, second: &Path, ignore: &[String]) -> Result<()> {
+    let a = Meta::open(first).with_context(|| first.display().to_string())?;
+    let b = Meta::open(second).with_context(|| second.display().to_string())?;
+
+    info!(o, "Comparing tags:");
+
+    {
+        let mut o = o.indent(1);
+        o.link("first", &Link::new(first)?)?;
+        o.link("second", &Link::new(second)?)?;
+    }
+
+    let differences = a.compare(o, &b, ignore)?;
+
+    if differences > 0 {
+        bail!("Found {differences} differing tags");
+    }
+
+    info!(o, "Tags are identical");
+    Ok(())
+}
+
+/// A JSON record emitted by `--meta-dump-json`.
+#[derive(Serialize)]
+struct MetaDump<'a> {
+    source: PathBuf,
+    tags: Option<Vec<TagDump<'a>>>,
+}
+
+fn run(o: &mut Out<'_>, config: &Config) -> Result<Vec<ConversionResult>> {
+    let mut tasks = Tasks::new();
+
+    config.populate(&mut tasks)?;
+
+    if tasks.filtered > 0 {
+        info!(
+            o,
+            "Filtered out {} files (--include, --exclude)", tasks.filtered
+        );
+    }
+
+    for Unsupported { source, ext } in tasks.unsupported.drain(..) {
+        warn!(o, "Unsupported extension: {ext}");
+        let mut o = o.indent(1);
+        tasks.db.dump(&mut o, &source)?;
+
+        tasks.results.push(ConversionResult {
+            source: tasks.db.to_path(&source)?,
+            destination: None,
+            outcome: Outcome::Skipped,
+            reason: Some(format!("unsupported extension: {ext}")),
+            input_bytes: tasks.db.len(&source)?,
+            output_bytes: None,
+            elapsed: Duration::ZERO,
+        });
+    }
+
+    for Truncated { source, path } in tasks.truncated.drain(..) {
+        warn!(o, "Truncated long path (--meta-max-len):");
+        let mut o = o.indent(1);
+        tasks.db.dump(&mut o, &source)?;
+        o.link("to", &path)?;
+    }
+
+    for YearFallback { source, year } in tasks.year_fallbacks.drain(..) {
+        warn!(o, "Using year {year} from path (--meta-year-from-path):");
+        let mut o = o.indent(1);
+        tasks.db.dump(&mut o, &source)?;
+    }
+
+    for LowBitrate {
+        source,
+        bitrate,
+        skipped,
+    } in tasks.low_bitrate.drain(..)
+    {
+        if skipped {
+            warn!(
+                o,
+                "Skipping low bitrate source ({bitrate}kbps, --min-bitrate):"
+            );
+        } else {
+            info!(
+                o,
+                "Not re-encoding low bitrate source ({bitrate}kbps, --min-bitrate):"
+            );
+        }
+
+        let mut o = o.indent(1);
+        tasks.db.dump(&mut o, &source)?;
+
+        if skipped {
+            tasks.results.push(ConversionResult {
+                source: tasks.db.to_path(&source)?,
+                destination: None,
+                outcome: Outcome::Skipped,
+                reason: Some(format!("low bitrate source ({bitrate}kbps)")),
+                input_bytes: tasks.db.len(&source)?,
+                output_bytes: None,
+                elapsed: Duration::ZERO,
+            });
+        }
+    }
+
+    if config.verbose {
+        for Exists { source, path } in tasks.already_exists.drain(..) {
+            warn!(o, "already exists (--force to remove):");
+            let mut o = o.indent(1);
+            tasks.db.dump(&mut o, &source)?;
+            o.link("to", &path)?;
+        }
+    }
+
+    for e in &tasks.errors {
+        error!(o, "Error:");
+        let mut o = o.indent(1);
+        tasks.db.dump(&mut o, &e.source)?;
+
+        for m in &e.messages {
+            error!(o, "{m}");
+        }
+
+        if config.meta_dump_error
+            && let Some(m) = tasks.meta.get(&e.source)
+        {
+            let mut o = o.indent(1);
+            m.dump(&mut o, &config.meta_dump_keys)?;
+        }
+    }
+
+    if config.meta_dump && config.meta_dump_json {
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+
+        for task in &tasks.tasks {
+            let record = MetaDump {
+                source: tasks.db.to_path(&task.source)?,
+                tags: tasks
+                    .meta
+                    .get(&task.source)
+                    .map(|m| m.dump_json(&config.meta_dump_keys)),
+            };
+
+            serde_json::to_writer(&mut stdout, &record)?;
+            writeln!(stdout)?;
+        }
+    } else if config.meta_dump {
+        for task in &tasks.tasks {
+            tasks.db.dump(o, &task.source)?;
+
+            if let Some(m) = tasks.meta.get(&task.source) {
+                info!(o, "Meta:");
+                let mut o = o.indent(1);
+                m.dump(&mut o, &config.meta_dump_keys)?;
+            } else {
+                info!(o, "Meta: <none>");
+            }
+        }
+    }
+
+    if config.validate {
+        if !tasks.errors.is_empty() {
+            bail!("{} files have problems with their tags", tasks.errors.len());
+        }
+
+        info!(o, "All files have the required tags");
+        return Ok(tasks.results);
+    }
+
+    if config.plan {
+        return plan(o, config, tasks);
+    }
+
+    if !tasks.errors.is_empty() && !config.keep_going {
+        bail!("Aborting due to previous errors, use --keep-going to ignore.");
+    }
+
+    if config.m4b {
+        return audiobook::run(o, config, tasks);
+    }
+
+    if config.verbose {
+        for MatchingConversion {
+            source,
+            from,
+            to_formats,
+        } in tasks.matching_conversions.drain(..)
+        {
+            let to_formats = to_formats
+                .iter()
+                .map(|f| f.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            info!(o, "Found matching conversions: {from} -> {to_formats}");
+            let mut o = o.indent(1);
+            tasks.db.dump(&mut o, &source)?;
+        }
+    }
+
+    let total = tasks.tasks.len();
+    let jobs = config.jobs.min(total).max(1);
+    let shared = Shared::default();
+
+    if jobs == 1 && !config.progress {
+        for c in &mut tasks.tasks {
+            let result = process(o, config, &tasks.db, &tasks.meta, &shared, c, total)?;
+            tasks.results.push(result);
+        }
+    } else {
+        let results = process_parallel(
+            o,
+            config,
+            &tasks.db,
+            &tasks.meta,
+            &shared,
+            &mut tasks.tasks,
+            jobs,
+        )?;
+
+        tasks.results.extend(results);
+    }
+
+    if let Some(path) = &config.manifest {
+        let mut entries = mem::take(&mut *lock(&shared.manifest));
+        entries.sort_by_key(|e| e.index);
+
+        info!(o, "Writing manifest with {} files", entries.len());
+        let mut o = o.indent(1);
+        o.link("path", &MaybeLink::new(path.clone()))?;
+
+        if !config.dry_run {
+            manifest::write(path, &entries)?;
+        }
+    }
+
+    let mut n = 0u32;
+
+    // Sources are only removed once every task converting them has completed,
+    // since a source can be converted to multiple formats.
+    let incomplete = tasks
+        .tasks
+        .iter()
+        .filter(|c| !c.is_completed())
+        .map(|c| &c.source)
+        .collect::<HashSet<_>>();
+
+    // Sources are only deleted if every task for them was performed by this
+    // run, rather than being skipped because its destination already existed.
+    let unprocessed = tasks
+        .tasks
+        .iter()
+        .filter(|c| !c.processed)
+        .map(|c| &c.source)
+        .collect::<HashSet<_>>();
+
+    let mut removed = HashSet::new();
+    let mut to_delete = Vec::new();
+
+    for c in tasks.tasks.iter().filter(|c| c.is_completed()) {
+        if !config.trash_source && !config.delete_source {
+            continue;
+        }
+
+        // NB: Trashing is meaningless for moved files.
+        if matches!(
+            c.kind,
+            TaskKind::Transfer {
+                kind: TransferKind::Move
+            }
+        ) {
+            continue;
+        }
+
+        if incomplete.contains(&c.source) || !removed.insert(&c.source) {
+            continue;
+        }
+
+        let path = match &c.source {
+            Source::Archive { .. } => continue,
+            Source::File { file } => tasks.db.file(*file)?,
+        };
+
+        if config.delete_source {
+            if !unprocessed.contains(&c.source) {
+                to_delete.push(path.clone());
+            }
+
+            continue;
+        }
+
+        let new;
+
+        let file_name = match path.file_name() {
+            Some(name) => name,
+            None => {
+                new = OsString::from(format!("file{}", n));
+                n += 1;
+                &new
+            }
+        };
+
+        tasks.to_trash.push(Trash {
+            what: TrashWhat::SourceFile,
+            path: path.clone(),
+            name: file_name.to_owned(),
+        });
+    }
+
+    for path in to_delete {
+        info!(o, "Deleting source file");
+        let mut o = o.indent(1);
+        o.link("path", &path)?;
+
+        if !config.dry_run
+            && let Err(e) = fs::remove_file(&path)
+        {
+            error!(o, "{e}");
+        }
+    }
+
+    // Ensure trash directory exists.
+    if !tasks.to_trash.is_empty() && !config.trash.is_dir() {
+        info!(o, "Creating trash directory");
+
+        let mut o = o.indent(1);
+
+        blank!(o, "path: {}", shell::path(&config.trash));
+
+        if !config.dry_run
+            && let Err(e) = fs::create_dir_all(&config.trash)
+        {
+            error!(o, "{e}");
+        }
+    }
+
+    let mut check_empty = Vec::new();
+
+    // Move files to trash.
+    for Trash { what, path, name } in tasks.to_trash.drain(..) {
+        let trash_path = MaybeLink::new(config.trash.join(&name));
+
+        info!(o, "Trashing {what}");
+        let mut o = o.indent(1);
+        o.link("from", &path)?;
+        o.link("to", &trash_path)?;
+
+        if !config.dry_run
+            && let Err(e) = fs::rename(&path, &trash_path)
+        {
+            error!(o, "{e}");
+
+            if let Some(path) = path.parent() {
+                check_empty.push(path.to_path_buf());
+            }
+        }
+    }
+
+    // Recursively check for empty directories and remove them.
+    for mut path in check_empty {
+        if !is_empty_dir(&path) {
+            continue;
+        }
+
+        info!(o, "removing empty directory:");
+        let mut o = o.indent(1);
+        blank!(o, "path: {}", shell::path(&path));
+
+        if !config.dry_run {
+            if let Err(e) = fs::remove_dir(&path) {
+                error!(o, "{e}");
+            }
+
+            path.pop();
+        } else {
+            continue;
+        }
+    }
+
+    Ok(tasks.results)
+}
+
+/// Print the planned destination of each task and which files would be
+/// skipped, without processing anything.
+fn plan(o: &mut Out<'_>, config: &Config, mut tasks: Tasks) -> Result<Vec<ConversionResult>> {
+    let total = tasks.tasks.len();
+    let mut planned = 0usize;
+    let mut skipped = tasks.results.len();
+
+    for c in &tasks.tasks {
+        let input_bytes = tasks.db.len(&c.source)?;
+
+        let (outcome, reason) = if c.is_completed() {
+            warn!(
+                o,
+                "Task #{}/#{total}: skipped, already exists (--force to remove)",
+                c.index.saturating_add(1)
+            );
+
+            skipped += 1;
+            (
+                Outcome::Skipped,
+                Some("destination already exists".to_string()),
+            )
+        } else {
+            info!(
+                o,
+                "Task #{}/#{total}: {}",
+                c.index.saturating_add(1),
+                c.kind
+            );
+
+            planned += 1;
+            (Outcome::Ok, None)
+        };
+
+        let mut o = o.indent(1);
+        tasks.db.dump(&mut o, &c.source)?;
+        o.link("to", &c.to_path)?;
+
+        tasks.results.push(ConversionResult {
+            source: tasks.db.to_path(&c.source)?,
+            destination: Some(c.to_path.to_path_buf()),
+            outcome,
+            reason,
+            input_bytes,
+            output_bytes: None,
+            elapsed: Duration::ZERO,
+        });
+    }
+
+    // Files with errors which still have a task are only skipped with
+    // `--keep-going`.
+    let with_tasks = tasks
+        .tasks
+        .iter()
+        .map(|c| &c.source)
+        .collect::<HashSet<_>>();
+
+    for e in &tasks.errors {
+        if with_tasks.contains(&e.source) {
+            continue;
+        }
+
+        skipped += 1;
+
+        tasks.results.push(ConversionResult {
+            source: tasks.db.to_path(&e.source)?,
+            destination: None,
+            outcome: Outcome::Skipped,
+            reason: Some(e.messages.join(", ")),
+            input_bytes: tasks.db.len(&e.source)?,
+            output_bytes: None,
+            elapsed: Duration::ZERO,
+        });
+    }
+
+    info!(o, "{planned} files would be processed, {skipped} skipped");
+
+    if !tasks.errors.is_empty() && !config.keep_going {
+        warn!(
+            o,
+            "{} files have errors, which aborts unless --keep-going is used",
+            tasks.errors.len()
+        );
+    }
+
+    Ok(tasks.results)
+}
+
+/// Build the configuration to use from command line options and the
+/// configuration file.
+pub(crate) fn config(opts: &Audiovert, settings: &settings::File) -> Result<Config> {
+    let mut sanitize = Sanitize::default();
+    sanitize.form = opts.meta_unicode;
+    sanitize.max_len = opts.meta_max_len;
+    sanitize.windows |= opts.meta_windows_names;
+
+    for (from, to) in settings.meta_sanitize()? {
+        sanitize.set(from, to);
+    }
+
+    for set in &opts.meta_sanitize {
+        sanitize.set(set.from, set.to.clone());
+    }
+
+    let mut media_types = MediaTypes::default();
+
+    for set in &opts.meta_media_type {
+        media_types.set(&set.from, set.to.clone());
+    }
 
     // Collection of bitrates.
     let mut bitrates = Bitrates::default();
@@ -168,24 +988,81 @@ pub fn entry(opts: &Audiovert) -> Result<()> {
     };
 
     let mut config = Config {
+        all_tags: opts.meta_all_tags,
         bitrates,
-        conversion: opts.conversion.clone(),
-        dry_run: opts.dry_run,
+        conversion: if opts.conversion.is_empty() {
+            settings.conversion()?
+        } else {
+            opts.conversion.clone()
+        },
+        cover_files: if opts.no_cover_file {
+            Vec::new()
+        } else {
+            opts.cover_file.clone()
+        },
+        delete_source: opts.delete_source,
+        dry_run: opts.dry_run || opts.plan,
         ffmpeg: opts.ffmpeg_bin.clone(),
+        fix_encoding: opts.fix_encoding,
         force: opts.force,
         forced_bitrates,
+        id3v24: opts.id3v24,
+        jobs: opts
+            .jobs
+            .or(settings.jobs)
+            .or_else(|| thread::available_parallelism().ok())
+            .map_or(1, NonZeroUsize::get),
         keep_going: opts.keep_going,
+        layout: Layout {
+            genre: opts.meta_genre,
+            composer: opts.meta_composer,
+            various_artists: opts.meta_various_artists.clone(),
+            template: match &opts.meta_template {
+                Some(template) => Some(template.clone()),
+                None => settings.meta_template()?,
+            },
+            sanitize,
+            articles: if opts.meta_move_articles {
+                opts.meta_article.clone()
+            } else {
+                Vec::new()
+            },
+            title_case: opts.meta_title_case,
+            disc_dirs: opts.meta_disc_dirs,
+            media_types,
+        },
+        m4b: opts.m4b,
+        manifest: opts.manifest.clone(),
         meta_dump_error: opts.meta_dump_error,
         meta_dump: opts.meta_dump,
+        meta_dump_json: opts.meta_dump_json,
+        meta_dump_keys: opts.meta_dump_key.clone(),
         meta_internal: opts.meta_internal,
-        meta: opts.meta,
+        meta: opts.meta || opts.validate || opts.m4b,
+        min_bitrate: opts.min_bitrate,
+        min_duration: opts.min_duration.map(Duration::from_secs),
         part_ext: opts.part_ext.clone(),
         paths: opts.paths.clone(),
+        filter: Filter {
+            include: opts.include.clone(),
+            exclude: opts.exclude.clone(),
+        },
+        plan: opts.plan,
+        progress: opts.progress,
         r#move: opts.r#move,
+        skip_existing: opts.skip_existing,
+        skip_low_bitrate: opts.skip_low_bitrate,
+        strip_art: opts.strip_art,
+        extract_art: opts.extract_art,
+        replaygain: opts.replaygain,
         to_dir: opts.to.clone(),
         trash_source: opts.trash_source,
         trash,
+        validate: opts.validate,
         verbose: opts.verbose,
+        verify: opts.verify,
+        verify_remove: opts.verify_remove,
+        year_from_path: opts.meta_year_from_path,
     };
 
     if config.paths.is_empty() {
@@ -204,106 +1081,169 @@ pub fn entry(opts: &Audiovert) -> Result<()> {
         });
     }
 
-    let cols = Colors::new();
+    Ok(config)
+}
 
-    let o = StandardStream::stdout(ColorChoice::Auto);
-    let mut o = o.lock();
-    let mut o = Out::new(&indent, &cols, &mut o);
-    run(&mut o, &config)
+/// State shared between tasks which might be processed in parallel.
+#[derive(Default)]
+struct Shared {
+    /// Cover files written by `--extract-art`.
+    covers: Mutex<HashSet<PathBuf>>,
+    /// Cover files found in source directories, keyed by directory.
+    cover_files: Mutex<HashMap<PathBuf, Option<Picture>>>,
+    /// Files produced for `--manifest`.
+    manifest: Mutex<Vec<manifest::Entry>>,
 }
 
-fn run(o: &mut Out<'_>, config: &Config) -> Result<()> {
-    let mut tasks = Tasks::new();
+/// An event sent from a worker processing tasks in parallel.
+enum Event {
+    /// A task with the given name was started.
+    Started(String),
+    /// A task was completed with buffered output.
+    Completed(usize, Buffer, Result<ConversionResult>),
+}
 
-    config.populate(&mut tasks)?;
+/// Process tasks using a pool of `jobs` threads.
+///
+/// The output of each task is buffered and written in the order of the tasks
+/// once it completes, so that it isn't interleaved. With progress enabled,
+/// output is only written for tasks which fail.
+fn process_parallel(
+    o: &mut Out<'_>,
+    config: &Config,
+    db: &Db,
+    metas: &HashMap<Source, Meta>,
+    shared: &Shared,
+    tasks: &mut [Task],
+    jobs: usize,
+) -> Result<Vec<ConversionResult>> {
+    let total = tasks.len();
+    let mut progress = config.progress.then(|| Progress::new(total));
+    let colors = o.colors();
+    let level = o.level();
+    let color = o.supports_color();
+    let started = config.progress;
+
+    let queue = Mutex::new(tasks.iter_mut().enumerate());
+    let (tx, rx) = mpsc::channel();
+
+    thread::scope(|s| -> Result<_> {
+        for _ in 0..jobs {
+            let tx = tx.clone();
+            let queue = &queue;
+
+            s.spawn(move || {
+                while let Some((n, c)) = lock(queue).next() {
+                    if started {
+                        let path = db.to_path(&c.source).unwrap_or_default();
+                        let name = path.file_name().unwrap_or(path.as_os_str());
+                        _ = tx.send(Event::Started(name.to_string_lossy().into_owned()));
+                    }
 
-    for Unsupported { source, ext } in tasks.unsupported.drain(..) {
-        warn!(o, "Unsupported extension: {ext}");
-        let mut o = o.indent(1);
-        tasks.db.dump(&mut o, &source)?;
-    }
+                    let mut buf = if color {
+                        Buffer::ansi()
+                    } else {
+                        Buffer::no_color()
+                    };
 
-    if config.verbose {
-        for Exists { source, path } in tasks.already_exists.drain(..) {
-            warn!(o, "already exists (--force to remove):");
-            let mut o = o.indent(1);
-            tasks.db.dump(&mut o, &source)?;
-            o.link("to", &path)?;
+                    let indent = Cell::new(level);
+                    let mut o = Out::new(&indent, colors, &mut buf);
+                    let result = process(&mut o, config, db, metas, shared, c, total);
+                    drop(o);
+
+                    if tx.send(Event::Completed(n, buf, result)).is_err() {
+                        break;
+                    }
+                }
+            });
         }
-    }
 
-    for e in &tasks.errors {
-        error!(o, "Error:");
-        let mut o = o.indent(1);
-        tasks.db.dump(&mut o, &e.source)?;
+        drop(tx);
 
-        for m in &e.messages {
-            error!(o, "{m}");
-        }
+        let mut results = Vec::with_capacity(total);
+        let mut pending = BTreeMap::new();
 
-        if config.meta_dump_error
-            && let Some(m) = tasks.meta.get(&e.source)
-        {
-            let mut o = o.indent(1);
-            m.dump(&mut o)?;
-        }
-    }
+        for event in rx {
+            let (n, buf, result) = match event {
+                Event::Started(name) => {
+                    if let Some(p) = progress.as_mut() {
+                        p.start(&name)?;
+                    }
 
-    if config.meta_dump {
-        for task in &tasks.tasks {
-            tasks.db.dump(o, &task.source)?;
+                    continue;
+                }
+                Event::Completed(n, buf, result) => (n, buf, result),
+            };
 
-            if let Some(m) = tasks.meta.get(&task.source) {
-                info!(o, "Meta:");
-                let mut o = o.indent(1);
-                m.dump(&mut o)?;
-            } else {
-                info!(o, "Meta: <none>");
+            if let Some(p) = progress.as_mut() {
+                p.complete()?;
             }
-        }
-    }
 
-    if !tasks.errors.is_empty() && !config.keep_going {
-        bail!("Aborting due to previous errors, use --keep-going to ignore.");
-    }
+            pending.insert(n, (buf, result));
 
-    if config.verbose {
-        for MatchingConversion {
-            source,
-            from,
-            to_formats,
-        } in tasks.matching_conversions.drain(..)
-        {
-            let to_formats = to_formats
-                .iter()
-                .map(|f| f.to_string())
-                .collect::<Vec<_>>()
-                .join(", ");
+            while let Some((buf, result)) = pending.remove(&results.len()) {
+                let failed = !matches!(&result, Ok(r) if r.outcome != Outcome::Failed);
 
-            info!(o, "Found matching conversions: {from} -> {to_formats}");
-            let mut o = o.indent(1);
-            tasks.db.dump(&mut o, &source)?;
-        }
-    }
+                if let Some(p) = progress.as_mut() {
+                    if failed {
+                        p.clear()?;
+                        o.write_buffer(&buf)?;
+                    }
+                } else {
+                    o.write_buffer(&buf)?;
+                }
 
-    let total = tasks.tasks.len();
+                results.push(result?);
+            }
+        }
 
-    for c in &mut tasks.tasks {
-        if c.is_completed() {
-            continue;
+        if let Some(p) = &mut progress {
+            p.finish()?;
         }
 
-        info!(
-            o,
-            "Task #{}/#{total}: {}",
-            c.index.saturating_add(1),
-            c.kind
-        );
-        let mut o = o.indent(1);
+        Ok(results)
+    })
+}
 
-        tasks.db.dump(&mut o, &c.source)?;
-        o.link("to", &c.to_path)?;
+/// Process a single task.
+fn process(
+    o: &mut Out<'_>,
+    config: &Config,
+    db: &Db,
+    metas: &HashMap<Source, Meta>,
+    shared: &Shared,
+    c: &mut Task,
+    total: usize,
+) -> Result<ConversionResult> {
+    let start = Instant::now();
+    let input_bytes = db.len(&c.source)?;
+
+    if c.is_completed() {
+        return Ok(ConversionResult {
+            source: db.to_path(&c.source)?,
+            destination: Some(c.to_path.to_path_buf()),
+            outcome: Outcome::Skipped,
+            reason: Some("destination already exists".to_string()),
+            input_bytes,
+            output_bytes: None,
+            elapsed: start.elapsed(),
+        });
+    }
 
+    info!(
+        o,
+        "Task #{}/#{total}: {}",
+        c.index.saturating_add(1),
+        c.kind
+    );
+    let mut o = o.indent(1);
+
+    db.dump(&mut o, &c.source)?;
+    o.link("to", &c.to_path)?;
+
+    let mut failure = None;
+
+    'task: {
         for (reason, path) in c.pre_remove.drain(..) {
             info!(o, "removing {reason}");
             let mut o = o.indent(1);
@@ -332,7 +1272,7 @@ fn run(o: &mut Out<'_>, config: &Config) -> Result<()> {
                 if !*converted {
                     let (argument, archive) = match &c.source {
                         Source::File { file } => {
-                            let file = tasks.db.file(*file)?;
+                            let file = db.file(*file)?;
                             (file.as_os_str(), None)
                         }
                         Source::Archive { archive, path } => {
@@ -348,6 +1288,20 @@ fn run(o: &mut Out<'_>, config: &Config) -> Result<()> {
                         command.args(["-map_metadata", "0"]);
                     }
 
+                    // Embedded pictures are video streams to ffmpeg.
+                    if config.strip_art {
+                        command.arg("-vn");
+                    }
+
+                    if to == Format::Mp3 {
+                        let version = if config.id3v24 { "4" } else { "3" };
+                        command.args(["-id3v2_version", version]);
+                    }
+
+                    if let Some(codec) = to.ffmpeg_codec() {
+                        command.args(["-c:a", codec]);
+                    }
+
                     to.bitrate(config, &mut command);
                     command.args(["-f", to.ffmpeg_format()]);
                     command.arg(part_path);
@@ -365,7 +1319,8 @@ fn run(o: &mut Out<'_>, config: &Config) -> Result<()> {
                     }
 
                     if !config.make_dir(&mut o, "partial", part_path)? {
-                        continue;
+                        failure = Some("failed to create partial directory".to_string());
+                        break 'task;
                     }
 
                     {
@@ -373,33 +1328,26 @@ fn run(o: &mut Out<'_>, config: &Config) -> Result<()> {
                         let mut o = o.indent(1);
 
                         if !config.dry_run {
-                            if let Some((archive, path)) = archive {
+                            let status = if let Some((archive, path)) = archive {
                                 command.stdin(Stdio::piped());
+                                write_source_to_stdin(&mut command, db, archive, path)
+                            } else {
+                                command.status().map_err(anyhow::Error::from)
+                            };
 
-                                let status = match write_source_to_stdin(
-                                    &mut command,
-                                    &tasks.db,
-                                    archive,
-                                    path,
-                                ) {
-                                    Ok(status) => status,
-                                    Err(e) => {
-                                        error!(o, "{e}");
-                                        continue;
-                                    }
-                                };
+                            let status = match status {
+                                Ok(status) => status,
+                                Err(e) => {
+                                    error!(o, "{e}");
+                                    failure = Some(e.to_string());
+                                    break 'task;
+                                }
+                            };
 
-                                *converted = status.success();
-                            } else {
-                                let status = match command.status() {
-                                    Ok(s) => s,
-                                    Err(e) => {
-                                        error!(o, "{e}");
-                                        continue;
-                                    }
-                                };
+                            *converted = status.success();
 
-                                *converted = status.success();
+                            if !*converted {
+                                failure = Some(format!("ffmpeg failed: {status}"));
                             }
                         } else {
                             *converted = true;
@@ -411,12 +1359,49 @@ fn run(o: &mut Out<'_>, config: &Config) -> Result<()> {
                     }
 
                     if !*tagged {
-                        if let Some(meta) = tasks.meta.get(&c.source) {
+                        if let Some(meta) = metas.get(&c.source) {
                             blank!(o, "tag <to>.{} ({} tags)", config.part_ext, meta.len());
 
+                            let cover = if config.strip_art
+                                || config.cover_files.is_empty()
+                                || meta.cover().is_some()
+                            {
+                                None
+                            } else {
+                                let dir = db.to_path(&c.source)?;
+                                let dir = dir.parent().unwrap_or(Path::new("")).to_owned();
+                                let mut cover_files = lock(&shared.cover_files);
+
+                                if !cover_files.contains_key(&dir) {
+                                    let picture = db
+                                        .cover_file(&c.source, &config.cover_files)
+                                        .and_then(|data| {
+                                            data.map(|data| meta::cover_picture(&data)).transpose()
+                                        });
+
+                                    let picture = match picture {
+                                        Ok(picture) => picture,
+                                        Err(e) => {
+                                            warn!(o, "Failed to read cover file: {e}");
+                                            None
+                                        }
+                                    };
+
+                                    cover_files.insert(dir.clone(), picture);
+                                }
+
+                                cover_files.get(&dir).cloned().flatten()
+                            };
+
+                            if cover.is_some() {
+                                blank!(o, "embedding cover file from source directory");
+                            }
+
                             if !config.dry_run {
-                                if let Err(e) = meta.tag_file(to, part_path) {
+                                if let Err(e) = meta.tag_file(to, part_path, config, cover.as_ref())
+                                {
                                     error!(o, "{e}");
+                                    failure = Some(e.to_string());
                                 } else {
                                     *tagged = true;
                                 }
@@ -429,9 +1414,52 @@ fn run(o: &mut Out<'_>, config: &Config) -> Result<()> {
                         }
                     }
 
+                    if *converted && *tagged && !c.moved && config.replaygain {
+                        blank!(o, "replaygain <to>.{}", config.part_ext);
+
+                        if !config.dry_run {
+                            let result = ReplayGain::analyze(&config.ffmpeg, part_path)
+                                .and_then(|gain| gain.write(to, part_path, config));
+
+                            if let Err(e) = result {
+                                error!(o, "{e}");
+                                failure = Some(e.to_string());
+                                break 'task;
+                            }
+                        }
+                    }
+
+                    if *converted && *tagged && !c.moved && config.verify {
+                        blank!(o, "verify <to>.{}", config.part_ext);
+
+                        if !config.dry_run {
+                            let result = match metas.get(&c.source) {
+                                Some(meta) => meta.verify(to, part_path),
+                                None => meta::verify_file(to, part_path).map(|_| ()),
+                            };
+
+                            if let Err(e) = result {
+                                let mut o = o.indent(1);
+                                error!(o, "{e}");
+                                failure = Some(format!("verification failed: {e}"));
+
+                                if config.verify_remove {
+                                    blank!(o, "rm <to>.{}", config.part_ext);
+
+                                    if let Err(e) = fs::remove_file(part_path) {
+                                        error!(o, "{e}");
+                                    }
+                                }
+
+                                break 'task;
+                            }
+                        }
+                    }
+
                     if *converted && *tagged && !c.moved {
                         if !config.make_dir(&mut o, "rename", &c.to_path)? {
-                            continue;
+                            failure = Some("failed to create destination directory".to_string());
+                            break 'task;
                         }
 
                         blank!(o, "mv <to>.{} <to>", config.part_ext);
@@ -445,11 +1473,14 @@ fn run(o: &mut Out<'_>, config: &Config) -> Result<()> {
                         if !config.dry_run {
                             if let Err(e) = fs::rename(part_path, &c.to_path) {
                                 error!(o, "{e}");
+                                failure = Some(e.to_string());
                             } else {
                                 c.moved = true;
+                                c.processed = true;
                             }
                         } else {
                             c.moved = true;
+                            c.processed = true;
                         }
                     }
                 }
@@ -457,131 +1488,99 @@ fn run(o: &mut Out<'_>, config: &Config) -> Result<()> {
             TaskKind::Transfer { kind } => {
                 if !c.moved {
                     if !config.make_dir(&mut o, kind, &c.to_path)? {
-                        continue;
+                        failure = Some("failed to create destination directory".to_string());
+                        break 'task;
                     }
 
                     if config.verbose {
-                        tasks.db.dump(&mut o, &c.source)?;
+                        db.dump(&mut o, &c.source)?;
                         o.link("to", &c.to_path)?;
                     } else {
                         blank!(o, "{} <from> <to>", kind.symbolic_command());
                     }
 
                     if !config.dry_run {
-                        let result = tasks.db.move_to(&c.source, &c.to_path, kind);
+                        let result = db.move_to(&c.source, &c.to_path, kind);
 
                         if let Err(e) = result {
                             error!(o, "{e}");
+                            failure = Some(e.to_string());
                         } else {
                             c.moved = true;
+                            c.processed = true;
                         }
                     } else {
                         c.moved = true;
+                        c.processed = true;
                     }
                 }
             }
         }
     }
 
-    let mut n = 0u32;
-
-    for c in tasks.tasks.iter().filter(|c| c.is_completed()) {
-        if !config.trash_source {
-            continue;
-        }
-
-        // NB: Trashing is meaningless for moved files.
-        if matches!(
-            c.kind,
-            TaskKind::Transfer {
-                kind: TransferKind::Move
-            }
-        ) {
-            continue;
-        }
-
-        let path = match &c.source {
-            Source::Archive { .. } => continue,
-            Source::File { file } => tasks.db.file(*file)?,
-        };
+    if config.extract_art
+        && c.is_completed()
+        && let Some(dir) = c.to_path.parent()
+        && let Some(cover) = metas.get(&c.source).and_then(|m| m.cover())
+        && let Some(name) = meta::cover_file_name(cover)
+    {
+        let path = dir.join(name);
 
-        let new;
+        if !path.exists() && lock(&shared.covers).insert(path.clone()) {
+            info!(o, "writing cover");
+            let mut o = o.indent(1);
+            blank!(o, "path: {}", shell::path(&path));
 
-        let file_name = match path.file_name() {
-            Some(name) => name,
-            None => {
-                new = OsString::from(format!("file{}", n));
-                n += 1;
-                &new
+            if !config.dry_run
+                && let Err(e) = fs::write(&path, cover.data())
+            {
+                error!(o, "{e}");
             }
-        };
-
-        tasks.to_trash.push(Trash {
-            what: TrashWhat::SourceFile,
-            path: path.clone(),
-            name: file_name.to_owned(),
-        });
-    }
-
-    // Ensure trash directory exists.
-    if !tasks.to_trash.is_empty() && !config.trash.is_dir() {
-        info!(o, "Creating trash directory");
-
-        let mut o = o.indent(1);
-
-        blank!(o, "path: {}", shell::path(&config.trash));
-
-        if !config.dry_run
-            && let Err(e) = fs::create_dir_all(&config.trash)
-        {
-            error!(o, "{e}");
         }
     }
 
-    let mut check_empty = Vec::new();
-
-    // Move files to trash.
-    for Trash { what, path, name } in tasks.to_trash.drain(..) {
-        let trash_path = MaybeLink::new(config.trash.join(&name));
-
-        info!(o, "Trashing {what}");
-        let mut o = o.indent(1);
-        o.link("from", &path)?;
-        o.link("to", &trash_path)?;
-
-        if !config.dry_run
-            && let Err(e) = fs::rename(&path, &trash_path)
-        {
-            error!(o, "{e}");
+    let (outcome, output_bytes) = if c.is_completed() {
+        let output_bytes = if config.dry_run {
+            None
+        } else {
+            fs::metadata(&c.to_path).ok().map(|m| m.len())
+        };
 
-            if let Some(path) = path.parent() {
-                check_empty.push(path.to_path_buf());
+        if config.manifest.is_some() && !config.dry_run {
+            match manifest::hash_file(&c.to_path) {
+                Ok(hash) => lock(&shared.manifest).push(manifest::Entry {
+                    index: c.index,
+                    source: db.to_path(&c.source)?,
+                    summary: c.summary.clone(),
+                    path: c.to_path.to_path_buf(),
+                    hash,
+                }),
+                Err(e) => {
+                    warn!(o, "Failed to hash file for manifest: {e}");
+                }
             }
         }
-    }
-
-    // Recursively check for empty directories and remove them.
-    for mut path in check_empty {
-        if !is_empty_dir(&path) {
-            continue;
-        }
 
-        info!(o, "removing empty directory:");
-        let mut o = o.indent(1);
-        blank!(o, "path: {}", shell::path(&path));
-
-        if !config.dry_run {
-            if let Err(e) = fs::remove_dir(&path) {
-                error!(o, "{e}");
-            }
+        (Outcome::Ok, output_bytes)
+    } else {
+        (Outcome::Failed, None)
+    };
 
-            path.pop();
-        } else {
-            continue;
-        }
-    }
+    Ok(ConversionResult {
+        source: db.to_path(&c.source)?,
+        destination: Some(c.to_path.to_path_buf()),
+        outcome,
+        reason: failure,
+        input_bytes,
+        output_bytes,
+        elapsed: start.elapsed(),
+    })
+}
 
-    Ok(())
+/// Lock a mutex, ignoring poisoning since the protected state is only used as
+/// a cache.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
 }
 
 fn is_empty_dir(path: &PathBuf) -> bool {
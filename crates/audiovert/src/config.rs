@@ -1,13 +1,21 @@
 use core::fmt;
 
 use std::collections::{BTreeSet, HashSet};
-use std::fs;
+use std::fs::{self, File};
+use std::io::{BufReader, Cursor};
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
 use anyhow::{Context, Result, anyhow, bail};
+use lofty::config::{ParseOptions, WriteOptions};
+use lofty::file::AudioFile;
+use lofty::mp4::{Mp4Codec, Mp4File};
+use mediavert_common::filter::Filter;
+use mediavert_common::glob::Glob;
 use relative_path::{Component, RelativePath, RelativePathBuf};
 
-use crate::archive::Archive;
+use crate::archive::{self, Archive, Cache};
+use crate::audiobook::Chapter;
 use crate::bitrates::Bitrates;
 use crate::condition::Condition;
 use crate::format::Format;
@@ -16,40 +24,97 @@ use crate::meta;
 use crate::out::{Out, blank, error, info};
 use crate::shell;
 use crate::tasks::{
-    Exists, MatchingConversion, PathError, Task, TaskKind, Tasks, TransferKind, Unsupported,
+    Exists, LowBitrate, MatchingConversion, PathError, Task, TaskKind, Tasks, TransferKind,
+    Truncated, Unsupported, YearFallback,
 };
 
 /// Configuration for conversions.
 pub(crate) struct Config {
+    pub(crate) all_tags: bool,
     pub(crate) bitrates: Bitrates,
     pub(crate) conversion: Vec<Condition>,
+    pub(crate) cover_files: Vec<String>,
+    pub(crate) delete_source: bool,
     pub(crate) dry_run: bool,
+    pub(crate) extract_art: bool,
     pub(crate) ffmpeg: PathBuf,
+    pub(crate) fix_encoding: bool,
     pub(crate) force: bool,
     pub(crate) forced_bitrates: HashSet<Format>,
+    pub(crate) id3v24: bool,
+    pub(crate) jobs: usize,
     pub(crate) keep_going: bool,
+    pub(crate) layout: meta::Layout,
+    pub(crate) m4b: bool,
+    pub(crate) manifest: Option<PathBuf>,
     pub(crate) meta_dump_error: bool,
     pub(crate) meta_dump: bool,
+    pub(crate) meta_dump_json: bool,
+    pub(crate) meta_dump_keys: Vec<String>,
     pub(crate) meta_internal: bool,
     pub(crate) meta: bool,
+    pub(crate) min_bitrate: Option<u32>,
+    pub(crate) min_duration: Option<Duration>,
     pub(crate) part_ext: String,
     pub(crate) paths: Vec<PathBuf>,
+    pub(crate) filter: Filter,
+    pub(crate) plan: bool,
+    pub(crate) progress: bool,
     pub(crate) r#move: bool,
+    pub(crate) replaygain: bool,
+    pub(crate) skip_existing: bool,
+    pub(crate) skip_low_bitrate: bool,
+    pub(crate) strip_art: bool,
     pub(crate) to_dir: Option<PathBuf>,
     pub(crate) trash_source: bool,
     pub(crate) trash: PathBuf,
+    pub(crate) validate: bool,
     pub(crate) verbose: bool,
+    pub(crate) verify: bool,
+    pub(crate) verify_remove: bool,
+    pub(crate) year_from_path: bool,
 }
 
 impl Config {
+    /// Test if an existing destination should be replaced because it's older
+    /// than its source, which is only the case with `--skip-existing`.
+    fn is_outdated(&self, db: &Db, source: &Source, to_path: &Path) -> Result<bool> {
+        if !self.skip_existing {
+            return Ok(false);
+        }
+
+        let Some(source) = db.modified(source)? else {
+            return Ok(false);
+        };
+
+        let to = fs::metadata(to_path).and_then(|m| m.modified()).ok();
+        Ok(to.is_none_or(|to| to < source))
+    }
+
     /// Populate tasks based on configuration.
     pub(crate) fn populate(&self, tasks: &mut Tasks) -> Result<()> {
         let mut meta_errors = Vec::new();
         let mut to_formats = BTreeSet::new();
         let mut sources = Vec::new();
         let mut pre_remove = Vec::new();
+        let mut seen = HashSet::new();
+        let mut truncated_paths = HashSet::new();
+
+        let mut inputs = Vec::with_capacity(self.paths.len());
+
+        for path in &self.paths {
+            let glob =
+                Glob::parse(path).with_context(|| format!("invalid glob: {}", path.display()))?;
+            inputs.push((path.as_path(), glob));
+        }
+
+        // Explicit paths take precedence over globs, so that files matched by
+        // both are converted relative to the explicit path.
+        inputs.sort_by_key(|(_, glob)| glob.is_some());
+
+        for (walk_path, glob) in &inputs {
+            let walk_path = glob.as_ref().map_or(*walk_path, Glob::base);
 
-        for walk_path in &self.paths {
             let dir = if walk_path.is_file() {
                 let Some(dir) = walk_path.parent() else {
                     // This only happens for empty arguments, so they should
@@ -71,11 +136,54 @@ impl Config {
                     continue;
                 }
 
+                if glob.as_ref().is_some_and(|glob| !glob.is_match(walked)) {
+                    continue;
+                }
+
+                // Files which are found through several inputs are only
+                // converted once.
+                let real = fs::canonicalize(walked).unwrap_or_else(|_| walked.to_path_buf());
+
+                if !seen.insert(real) {
+                    continue;
+                }
+
                 let Some(ext) = walked.extension().and_then(|s| s.to_str()) else {
                     continue;
                 };
 
-                if let Some(kind) = Archive::from_ext(ext) {
+                let relative = walked.strip_prefix(dir).unwrap_or(walked);
+
+                // Failing to read one archive shouldn't stop the others from
+                // being found.
+                let archive = match Archive::detect(walked, ext) {
+                    Ok(archive) => archive,
+                    Err(_) if self.filter.is_excluded(relative) => {
+                        tasks.filtered += 1;
+                        continue;
+                    }
+                    Err(e) => {
+                        let file = tasks.db.push_file(Link::new(walked)?);
+
+                        tasks.errors.push(PathError {
+                            source: Source::File { file },
+                            messages: vec![format!("failed to detect archive: {e:#}")],
+                        });
+
+                        continue;
+                    }
+                };
+
+                if let Some(kind) = archive {
+                    // Archives are only excluded by their own path, since
+                    // files in them are matched by include patterns below.
+                    if self.filter.is_excluded(relative) {
+                        tasks.filtered += 1;
+                        continue;
+                    }
+
+                    let mut filtered = 0;
+
                     let archive_id = tasks.db.push_archive(SourceArchive {
                         kind,
                         path: Link::new(walked)?,
@@ -83,11 +191,11 @@ impl Config {
 
                     let mut archive_path = walked.parent().unwrap_or(Path::new("")).to_path_buf();
 
-                    if let Some(file_name) = walked.file_stem() {
+                    if let Some(file_name) = archive::stem(walked) {
                         archive_path.push(file_name);
                     }
 
-                    kind.enumerate(walked, &mut |path| {
+                    let enumerated = kind.enumerate(walked, &mut |path| {
                         let path = RelativePath::new(path);
                         let mut buf = archive_path.clone();
 
@@ -107,29 +215,74 @@ impl Config {
                             true
                         };
 
-                        if ok {
-                            sources.push(Source::Archive {
-                                archive: archive_id,
-                                path: path.to_owned(),
-                            });
+                        if !ok {
+                            return Ok(());
+                        }
+
+                        // Files in archives are matched as if the archive was
+                        // a directory.
+                        if !self
+                            .filter
+                            .is_included(buf.strip_prefix(dir).unwrap_or(&buf))
+                        {
+                            filtered += 1;
+                            return Ok(());
                         }
 
+                        sources.push(Source::Archive {
+                            archive: archive_id,
+                            path: path.to_owned(),
+                        });
+
                         Ok(())
-                    })?;
+                    });
+
+                    if let Err(e) = enumerated {
+                        sources.clear();
+
+                        let file = tasks.db.push_file(Link::new(walked)?);
+
+                        tasks.errors.push(PathError {
+                            source: Source::File { file },
+                            messages: vec![format!("failed to read archive: {e:#}")],
+                        });
+
+                        continue;
+                    }
+
+                    tasks.filtered += filtered;
                 } else {
+                    if !self.filter.is_included(relative) {
+                        tasks.filtered += 1;
+                        continue;
+                    }
+
                     let file = tasks.db.push_file(Link::new(walked)?);
                     let source = Source::File { file };
                     sources.push(source);
                 }
 
                 for source in sources.drain(..) {
-                    let Some(from) = tasks.db.ext(&source)?.and_then(Format::from_ext) else {
-                        tasks.unsupported.push(Unsupported {
-                            source,
-                            ext: ext.to_string(),
-                        });
+                    // Detecting the format can involve parsing the file, which
+                    // only fails that file.
+                    let from = match tasks.db.format(&source) {
+                        Ok(Some(from)) => from,
+                        Ok(None) => {
+                            tasks.unsupported.push(Unsupported {
+                                source,
+                                ext: ext.to_string(),
+                            });
 
-                        continue;
+                            continue;
+                        }
+                        Err(e) => {
+                            tasks.errors.push(PathError {
+                                source,
+                                messages: vec![format!("{e:#}")],
+                            });
+
+                            continue;
+                        }
                     };
 
                     to_formats.clear();
@@ -148,19 +301,48 @@ impl Config {
 
                     let mut meta = None;
 
-                    let id_parts =
-                        meta::Parts::from_path(&source, &tasks.db, &mut meta_errors, &mut meta)?;
+                    let id_parts = meta::Parts::from_path(
+                        &source,
+                        &tasks.db,
+                        &mut meta_errors,
+                        &mut meta,
+                        self,
+                    )?;
 
                     if let Some(meta) = meta {
                         tasks.meta.insert(source.clone(), meta);
                     }
 
+                    if let Some(min) = self.min_duration
+                        && let Some(duration) = tasks.meta.get(&source).map(|m| m.duration())
+                        && duration < min
+                    {
+                        tasks.errors.push(PathError {
+                            source: source.clone(),
+                            messages: vec![format!(
+                                "suspiciously short track of {:.1}s (--min-duration)",
+                                duration.as_secs_f64()
+                            )],
+                        });
+                    }
+
+                    let summary = match &id_parts {
+                        Some(parts) if self.manifest.is_some() => Some(parts.summary()),
+                        _ => None,
+                    };
+
                     let meta_parts = if self.meta {
-                        let Some(id_parts) = id_parts else {
+                        let Some(mut id_parts) = id_parts else {
                             meta_errors.push(
                                 "could not extract required tags (see --meta-dump-error)"
                                     .to_string(),
                             );
+
+                            tasks.errors.push(PathError {
+                                source: source.clone(),
+                                messages: meta_errors.drain(..).collect(),
+                            });
+
                             continue;
                         };
 
@@ -171,26 +353,123 @@ impl Config {
                             });
                         }
 
+                        if self.layout.title_case {
+                            id_parts.title_case();
+                        }
+
+                        id_parts.normalize_media_type(&self.layout.media_types);
+
+                        if let Some(year) = id_parts.year_from_path() {
+                            tasks.year_fallbacks.push(YearFallback {
+                                source: source.clone(),
+                                year,
+                            });
+                        }
+
                         Some(id_parts)
                     } else {
                         None
                     };
 
+                    if self.m4b {
+                        if let Some(parts) = meta_parts {
+                            tasks.chapters.push(Chapter { source, parts });
+                        }
+
+                        continue;
+                    }
+
+                    let low_bitrate = match self.min_bitrate {
+                        Some(min) if !from.is_lossless() => tasks
+                            .meta
+                            .get(&source)
+                            .and_then(|m| m.bitrate())
+                            .filter(|&bitrate| bitrate <= min),
+                        _ => None,
+                    };
+
+                    if let Some(bitrate) = low_bitrate {
+                        let converts = to_formats
+                            .iter()
+                            .any(|&to| to != from || self.forced_bitrates.contains(&from));
+
+                        if converts {
+                            tasks.low_bitrate.push(LowBitrate {
+                                source: source.clone(),
+                                bitrate,
+                                skipped: self.skip_low_bitrate,
+                            });
+
+                            to_formats.clear();
+
+                            if !self.skip_low_bitrate {
+                                to_formats.insert(from);
+                            }
+                        }
+                    }
+
                     for &to in &to_formats {
                         debug_assert!(pre_remove.is_empty());
 
+                        let transfer = from == to
+                            && (low_bitrate.is_some() || !self.forced_bitrates.contains(&from));
+
+                        // Transferred files keep their extension, since `.m4a`
+                        // files can be in the `aac` format.
+                        let ext = match tasks.db.ext(&source)? {
+                            Some(ext) if transfer => ext.to_owned(),
+                            _ => to.ext().to_owned(),
+                        };
+
                         let to_path = if let Some(to_dir) = &self.to_dir {
                             match &meta_parts {
                                 Some(meta_parts) => {
                                     let mut to_path = to_dir.to_path_buf();
-                                    meta_parts.append_to(&mut to_path);
-                                    to_path.add_extension(to.ext());
+
+                                    let truncated =
+                                        match meta_parts.append_to(&self.layout, &mut to_path) {
+                                            Ok(truncated) => truncated,
+                                            Err(e) => {
+                                                tasks.errors.push(PathError {
+                                                    source: source.clone(),
+                                                    messages: vec![format!("{e:#}")],
+                                                });
+
+                                                continue;
+                                            }
+                                        };
+
+                                    to_path.add_extension(&ext);
+
+                                    // Truncation can make the names of different
+                                    // tracks the same, which would otherwise make
+                                    // all but the first look like they already
+                                    // exist.
+                                    if truncated && !truncated_paths.insert(to_path.clone()) {
+                                        tasks.errors.push(PathError {
+                                            source: source.clone(),
+                                            messages: vec![format!(
+                                                "truncated name is the same as another file: {}",
+                                                to_path.display()
+                                            )],
+                                        });
+
+                                        continue;
+                                    }
+
+                                    if truncated {
+                                        tasks.truncated.push(Truncated {
+                                            source: source.clone(),
+                                            path: MaybeLink::new(to_path.clone()),
+                                        });
+                                    }
+
                                     to_path
                                 }
                                 None => {
                                     let mut to_path = to_dir.clone();
                                     tasks.db.to_dir_path(&source, dir, &mut to_path)?;
-                                    to_path.set_extension(to.ext());
+                                    to_path.set_extension(&ext);
                                     to_path
                                 }
                             }
@@ -198,13 +477,50 @@ impl Config {
                             match &meta_parts {
                                 Some(meta_parts) => {
                                     let mut to_path = dir.to_path_buf();
-                                    meta_parts.append_to(&mut to_path);
-                                    to_path.add_extension(to.ext());
+
+                                    let truncated =
+                                        match meta_parts.append_to(&self.layout, &mut to_path) {
+                                            Ok(truncated) => truncated,
+                                            Err(e) => {
+                                                tasks.errors.push(PathError {
+                                                    source: source.clone(),
+                                                    messages: vec![format!("{e:#}")],
+                                                });
+
+                                                continue;
+                                            }
+                                        };
+
+                                    to_path.add_extension(&ext);
+
+                                    // Truncation can make the names of different
+                                    // tracks the same, which would otherwise make
+                                    // all but the first look like they already
+                                    // exist.
+                                    if truncated && !truncated_paths.insert(to_path.clone()) {
+                                        tasks.errors.push(PathError {
+                                            source: source.clone(),
+                                            messages: vec![format!(
+                                                "truncated name is the same as another file: {}",
+                                                to_path.display()
+                                            )],
+                                        });
+
+                                        continue;
+                                    }
+
+                                    if truncated {
+                                        tasks.truncated.push(Truncated {
+                                            source: source.clone(),
+                                            path: MaybeLink::new(to_path.clone()),
+                                        });
+                                    }
+
                                     to_path
                                 }
                                 None => {
                                     let mut to_path = tasks.db.to_path(&source)?;
-                                    to_path.set_extension(to.ext());
+                                    to_path.set_extension(&ext);
                                     to_path
                                 }
                             }
@@ -218,21 +534,27 @@ impl Config {
                         let exists;
 
                         if to_path.exists() {
-                            if !self.force {
+                            if !self.force && !self.is_outdated(&tasks.db, &source, &to_path)? {
                                 tasks.already_exists.push(Exists {
                                     source: source.clone(),
                                     path: Link::new(&to_path)?,
                                 });
                                 exists = true;
                             } else {
-                                pre_remove.push(("destination path (--force)", to_path.clone()));
+                                let reason = if self.force {
+                                    "destination path (--force)"
+                                } else {
+                                    "outdated destination path (--skip-existing)"
+                                };
+
+                                pre_remove.push((reason, to_path.clone()));
                                 exists = false;
                             }
                         } else {
                             exists = false;
                         };
 
-                        let kind = if from == to && !self.forced_bitrates.contains(&from) {
+                        let kind = if transfer {
                             TaskKind::Transfer {
                                 kind: match source {
                                     Source::File { .. } => {
@@ -270,7 +592,9 @@ impl Config {
                             source: source.clone(),
                             to_path,
                             moved: exists,
+                            processed: false,
                             pre_remove: pre_remove.drain(..).collect(),
+                            summary: summary.clone(),
                         });
                     }
                 }
@@ -280,6 +604,13 @@ impl Config {
         Ok(())
     }
 
+    /// Options to use when writing tags with lofty.
+    pub(crate) fn write_options(&self) -> WriteOptions {
+        let mut options = WriteOptions::default();
+        options.use_id3v23(!self.id3v24);
+        options
+    }
+
     /// Make directory for output file.
     pub(crate) fn make_dir(
         &self,
@@ -323,20 +654,6 @@ pub(crate) struct SourceArchive {
     pub(crate) path: Link,
 }
 
-impl SourceArchive {
-    /// Get the contents of a file inside the archive.
-    pub(crate) fn contents(&self, path: &RelativePath) -> Result<Vec<u8>> {
-        if let Some(contents) = self.kind.contents(&self.path, path)? {
-            return Ok(contents);
-        }
-
-        Err(anyhow!(
-            "not found in archive: {}: {path}",
-            self.path.display()
-        ))
-    }
-}
-
 /// Unique and internal identifier for a source file.
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub(crate) struct FileId(usize);
@@ -363,6 +680,7 @@ impl fmt::Display for ArchiveId {
 pub(crate) struct Db {
     archives: Vec<SourceArchive>,
     files: Vec<Link>,
+    cache: Cache,
 }
 
 impl Db {
@@ -371,6 +689,7 @@ impl Db {
         Db {
             archives: Vec::new(),
             files: Vec::new(),
+            cache: Cache::new(),
         }
     }
 
@@ -411,16 +730,76 @@ impl Db {
     }
 
     /// Get the contents of the source file.
+    ///
+    /// Zip archives are kept in memory, so reading many files from the same
+    /// archive doesn't have to open it again.
     pub(crate) fn archive_contents(
         &self,
         archive: ArchiveId,
         path: &RelativePath,
     ) -> Result<Vec<u8>> {
-        let Some(archive) = self.archives.get(archive.0) else {
+        let Some(source) = self.archives.get(archive.0) else {
             anyhow::bail!("invalid archive id: {archive}");
         };
 
-        archive.contents(path)
+        if let Some(contents) = self.cache.contents(archive, source, path)? {
+            return Ok(contents);
+        }
+
+        Err(anyhow!(
+            "not found in archive: {}: {path}",
+            source.path.display()
+        ))
+    }
+
+    /// Read the first cover image file out of `names` next to the given
+    /// source, like `cover.jpg` in the same directory.
+    ///
+    /// Names are matched case-insensitively for files, and exactly for entries
+    /// in archives.
+    pub(crate) fn cover_file(&self, source: &Source, names: &[String]) -> Result<Option<Vec<u8>>> {
+        match source {
+            Source::File { file } => {
+                let file = self.file(*file)?;
+
+                let Some(dir) = file.parent() else {
+                    return Ok(None);
+                };
+
+                let mut entries = Vec::new();
+
+                for e in fs::read_dir(dir)? {
+                    entries.push(e?.file_name());
+                }
+
+                for name in names {
+                    let Some(found) = entries
+                        .iter()
+                        .find(|e| e.to_str().is_some_and(|e| e.eq_ignore_ascii_case(name)))
+                    else {
+                        continue;
+                    };
+
+                    return Ok(Some(fs::read(dir.join(found))?));
+                }
+
+                Ok(None)
+            }
+            Source::Archive { archive, path } => {
+                let source = self.archive(*archive)?;
+                let dir = path.parent().unwrap_or(RelativePath::new(""));
+
+                for name in names {
+                    if let Some(contents) =
+                        self.cache.contents(*archive, source, &dir.join(name))?
+                    {
+                        return Ok(Some(contents));
+                    }
+                }
+
+                Ok(None)
+            }
+        }
     }
 
     /// Append the relative source path to the given path.
@@ -451,7 +830,7 @@ impl Db {
                     to_path.push(parent);
                 }
 
-                if let Some(file_stem) = archive.path.file_stem() {
+                if let Some(file_stem) = archive::stem(&archive.path) {
                     to_path.push(file_stem);
                 }
 
@@ -486,7 +865,7 @@ impl Db {
 
                 to_path.pop();
 
-                if let Some(stem) = archive.path.file_stem() {
+                if let Some(stem) = archive::stem(&archive.path) {
                     to_path.push(stem);
                 }
 
@@ -537,6 +916,30 @@ impl Db {
         Ok(())
     }
 
+    /// Get the size of the source in bytes.
+    ///
+    /// This is only known for regular files, since determining it for files
+    /// inside of archives requires extracting them.
+    pub(crate) fn len(&self, source: &Source) -> Result<Option<u64>> {
+        match source {
+            Source::File { file } => Ok(fs::metadata(self.file(*file)?).ok().map(|m| m.len())),
+            Source::Archive { .. } => Ok(None),
+        }
+    }
+
+    /// Get the modification time of the source.
+    ///
+    /// For files inside of archives this is the modification time of the
+    /// archive.
+    pub(crate) fn modified(&self, source: &Source) -> Result<Option<SystemTime>> {
+        let path = match source {
+            Source::File { file } => self.file(*file)?,
+            Source::Archive { archive, .. } => self.archive(*archive)?.path.as_ref(),
+        };
+
+        Ok(fs::metadata(path).and_then(|m| m.modified()).ok())
+    }
+
     /// Get the extension of the source file.
     pub(crate) fn ext<'a>(&'a self, source: &'a Source) -> Result<Option<&'a str>> {
         match source {
@@ -545,6 +948,43 @@ impl Db {
         }
     }
 
+    /// Detect the format of a source.
+    ///
+    /// This is based on the extension, except for `.m4a` files which can hold
+    /// either lossless ALAC or lossy AAC, where the codec is read from the
+    /// file.
+    pub(crate) fn format(&self, source: &Source) -> Result<Option<Format>> {
+        let Some(format) = self.ext(source)?.and_then(Format::from_ext) else {
+            return Ok(None);
+        };
+
+        if format != Format::Alac {
+            return Ok(Some(format));
+        }
+
+        let options = ParseOptions::new().read_tags(false).read_cover_art(false);
+
+        let file = match source {
+            Source::File { file } => {
+                let file = self.file(*file)?;
+                let mut reader = BufReader::new(File::open(file)?);
+                Mp4File::read_from(&mut reader, options)
+                    .with_context(|| anyhow!("{}: Reading mp4 codec", file.display()))?
+            }
+            Source::Archive { archive, path } => {
+                let contents = self.archive_contents(*archive, path)?;
+                Mp4File::read_from(&mut Cursor::new(contents), options)
+                    .with_context(|| anyhow!("{path}: Reading mp4 codec"))?
+            }
+        };
+
+        Ok(match file.properties().codec() {
+            Mp4Codec::ALAC => Some(Format::Alac),
+            Mp4Codec::AAC => Some(Format::Aac),
+            _ => None,
+        })
+    }
+
     /// Dump source information.
     pub(crate) fn dump(&self, o: &mut Out<'_>, source: &Source) -> Result<()> {
         match source {
@@ -588,3 +1028,173 @@ pub(crate) enum Source {
         path: RelativePathBuf,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::path::Path;
+
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use lofty::mp4::Mp4Codec;
+    use relative_path::RelativePath;
+    use zip::ZipWriter;
+    use zip::write::SimpleFileOptions;
+
+    use super::{Db, Source, SourceArchive};
+    use crate::archive::Archive;
+    use crate::format::Format;
+    use crate::link::Link;
+    use crate::tasks::Tasks;
+    use crate::testing;
+
+    const FILES: usize = 10;
+
+    fn name(n: usize) -> String {
+        format!("album/{n:02}.flac")
+    }
+
+    /// Read every file out of an archive, and check that the archive is only
+    /// loaded once.
+    fn read_all(kind: Archive, path: &Path) {
+        let mut db = Db::new();
+        let id = db.push_archive(SourceArchive {
+            kind,
+            path: Link::new(path).unwrap(),
+        });
+
+        for _ in 0..2 {
+            for n in 0..FILES {
+                let contents = db.archive_contents(id, RelativePath::new(&name(n)));
+                assert_eq!(contents.unwrap(), name(n).as_bytes());
+            }
+        }
+
+        assert!(
+            db.archive_contents(id, RelativePath::new("missing"))
+                .is_err()
+        );
+        assert_eq!(db.cache.loads(), 1);
+    }
+
+    fn format(name: &str, contents: &[u8]) -> Option<Format> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        fs::write(&path, contents).unwrap();
+
+        let mut db = Db::new();
+        let file = db.push_file(Link::new(&path).unwrap());
+        db.format(&Source::File { file }).unwrap()
+    }
+
+    #[test]
+    fn m4a_codec() {
+        assert_eq!(
+            format("a.m4a", &testing::mp4(Mp4Codec::ALAC)),
+            Some(Format::Alac)
+        );
+        assert_eq!(
+            format("a.m4a", &testing::mp4(Mp4Codec::AAC)),
+            Some(Format::Aac)
+        );
+        assert_eq!(format("a.flac", &testing::flac()), Some(Format::Flac));
+        assert_eq!(format("a.txt", b"hello"), None);
+    }
+
+    #[test]
+    fn zip_loaded_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("album.zip");
+
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+
+        for n in 0..FILES {
+            zip.start_file(name(n), SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(name(n).as_bytes()).unwrap();
+        }
+
+        zip.finish().unwrap();
+        read_all(Archive::Zip, &path);
+    }
+
+    #[test]
+    fn tar_gz_loaded_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("album.tar.gz");
+
+        let gz = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+        let mut tar = tar::Builder::new(gz);
+
+        for n in 0..FILES {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(name(n).len() as u64);
+            header.set_mode(0o644);
+            tar.append_data(&mut header, name(n), name(n).as_bytes())
+                .unwrap();
+        }
+
+        tar.into_inner().unwrap().finish().unwrap();
+        read_all(Archive::TarGz, &path);
+    }
+
+    #[test]
+    fn broken_m4a() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        fs::create_dir(&src).unwrap();
+        fs::write(src.join("broken.m4a"), b"not an mp4 file").unwrap();
+        fs::write(src.join("track.flac"), testing::flac()).unwrap();
+
+        let out = dir.path().join("out");
+        let config = testing::config(&[
+            "--to",
+            out.to_str().unwrap(),
+            "--conversion",
+            "flac=same",
+            src.to_str().unwrap(),
+        ]);
+
+        let mut tasks = Tasks::new();
+        config.populate(&mut tasks).unwrap();
+
+        assert_eq!(tasks.errors.len(), 1);
+        let broken = tasks.db.to_path(&tasks.errors[0].source).unwrap();
+        assert!(broken.ends_with("broken.m4a"));
+        assert!(tasks.unsupported.is_empty());
+
+        assert_eq!(tasks.tasks.len(), 1);
+        let track = tasks.db.to_path(&tasks.tasks[0].source).unwrap();
+        assert!(track.ends_with("track.flac"));
+    }
+
+    #[test]
+    fn broken_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        fs::create_dir(&src).unwrap();
+        fs::write(src.join("broken.zip"), b"PK\x03\x04").unwrap();
+        fs::write(src.join("track.flac"), testing::flac()).unwrap();
+
+        let out = dir.path().join("out");
+        let config = testing::config(&[
+            "--to",
+            out.to_str().unwrap(),
+            "--conversion",
+            "flac=same",
+            src.to_str().unwrap(),
+        ]);
+
+        let mut tasks = Tasks::new();
+        config.populate(&mut tasks).unwrap();
+
+        assert_eq!(tasks.errors.len(), 1);
+        let broken = tasks.db.to_path(&tasks.errors[0].source).unwrap();
+        assert!(broken.ends_with("broken.zip"));
+
+        assert_eq!(tasks.tasks.len(), 1);
+        let track = tasks.db.to_path(&tasks.tasks[0].source).unwrap();
+        assert!(track.ends_with("track.flac"));
+    }
+}
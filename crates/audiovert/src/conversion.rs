@@ -0,0 +1,46 @@
+//! Structured records describing the result of processing a source.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{Serialize, Serializer};
+
+/// The outcome of processing a single source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    /// The source was successfully converted or transferred.
+    Ok,
+    /// The source was intentionally not processed.
+    Skipped,
+    /// Processing the source failed.
+    Failed,
+}
+
+/// The result of processing a single source.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConversionResult {
+    /// The path of the source. For files inside of archives this is the path
+    /// the file would have if the archive was a directory.
+    pub source: PathBuf,
+    /// The destination path, if one was determined.
+    pub destination: Option<PathBuf>,
+    /// The outcome of processing the source.
+    pub outcome: Outcome,
+    /// The reason why the source was skipped or failed.
+    pub reason: Option<String>,
+    /// The size of the source in bytes, if known.
+    pub input_bytes: Option<u64>,
+    /// The size of the destination in bytes, if it was written.
+    pub output_bytes: Option<u64>,
+    /// Time spent processing the source.
+    #[serde(rename = "elapsed_secs", serialize_with = "serialize_secs")]
+    pub elapsed: Duration,
+}
+
+fn serialize_secs<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_f64(duration.as_secs_f64())
+}
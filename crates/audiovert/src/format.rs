@@ -21,9 +21,11 @@ impl Error for FormatErr {}
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub(crate) enum Format {
     Aac,
+    Alac,
     Flac,
     Mp3,
     Ogg,
+    Opus,
     Wav,
 }
 
@@ -31,18 +33,20 @@ impl Format {
     pub(crate) const DEFAULT_BITRATE_AAC: u32 = 192;
     pub(crate) const DEFAULT_BITRATE_MP3: u32 = 320;
     pub(crate) const DEFAULT_BITRATE_OGG: u32 = 192;
+    pub(crate) const DEFAULT_BITRATE_OPUS: u32 = 128;
 
     pub(crate) fn default_bitrate(&self) -> Option<u32> {
         match self {
             Format::Aac => Some(Format::DEFAULT_BITRATE_AAC),
             Format::Mp3 => Some(Format::DEFAULT_BITRATE_MP3),
             Format::Ogg => Some(Format::DEFAULT_BITRATE_OGG),
+            Format::Opus => Some(Format::DEFAULT_BITRATE_OPUS),
             _ => None,
         }
     }
 
     pub(crate) fn is_lossless(&self) -> bool {
-        matches!(self, Format::Flac | Format::Wav)
+        matches!(self, Format::Alac | Format::Flac | Format::Wav)
     }
 
     pub(crate) fn bitrate(&self, config: &Config, command: &mut Command) {
@@ -57,19 +61,32 @@ impl Format {
     pub(crate) fn ext(&self) -> &'static str {
         match self {
             Format::Aac => "aac",
+            Format::Alac => "m4a",
             Format::Flac => "flac",
             Format::Mp3 => "mp3",
             Format::Ogg => "ogg",
+            Format::Opus => "opus",
             Format::Wav => "wav",
         }
     }
 
+    /// The audio codec to request from ffmpeg, for formats where the
+    /// container does not imply one.
+    pub(crate) fn ffmpeg_codec(&self) -> Option<&'static str> {
+        match self {
+            Format::Alac => Some("alac"),
+            _ => None,
+        }
+    }
+
     pub(crate) fn ffmpeg_format(&self) -> &'static str {
         match self {
             Format::Aac => "adts",
+            Format::Alac => "ipod",
             Format::Flac => "flac",
             Format::Mp3 => "mp3",
             Format::Ogg => "ogg",
+            Format::Opus => "opus",
             Format::Wav => "wav",
         }
     }
@@ -77,9 +94,11 @@ impl Format {
     pub(crate) fn from_ext(ext: &str) -> Option<Format> {
         match ext {
             "aac" => Some(Format::Aac),
+            "m4a" => Some(Format::Alac),
             "flac" => Some(Format::Flac),
             "mp3" => Some(Format::Mp3),
             "ogg" => Some(Format::Ogg),
+            "opus" => Some(Format::Opus),
             "wav" => Some(Format::Wav),
             _ => None,
         }
@@ -0,0 +1,82 @@
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result, bail};
+use lofty::file::TaggedFileExt;
+use lofty::probe::Probe;
+use lofty::tag::{ItemKey, Tag};
+
+use crate::config::Config;
+use crate::format::Format;
+use crate::meta;
+
+/// ReplayGain values for a single track.
+pub(crate) struct ReplayGain {
+    /// The track gain in dB.
+    gain: f64,
+    /// The track peak as a linear sample value.
+    peak: f64,
+}
+
+impl ReplayGain {
+    /// Analyze the given file using the ffmpeg `replaygain` filter.
+    pub(crate) fn analyze(ffmpeg: &Path, path: &Path) -> Result<Self> {
+        let output = Command::new(ffmpeg)
+            .args(["-hide_banner", "-nostats", "-loglevel", "info", "-i"])
+            .arg(path)
+            .args(["-af", "replaygain", "-f", "null", "-"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .output()
+            .context("Failed to run ffmpeg")?;
+
+        if !output.status.success() {
+            bail!("ffmpeg failed: {}", output.status);
+        }
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        let mut gain = None;
+        let mut peak = None;
+
+        for line in stderr.lines() {
+            if let Some((_, value)) = line.split_once("track_gain = ") {
+                gain = value.trim().trim_end_matches("dB").trim().parse().ok();
+            } else if let Some((_, value)) = line.split_once("track_peak = ") {
+                peak = value.trim().parse().ok();
+            }
+        }
+
+        let (Some(gain), Some(peak)) = (gain, peak) else {
+            bail!("Missing track gain or peak in ffmpeg output");
+        };
+
+        Ok(Self { gain, peak })
+    }
+
+    /// Write the track gain and peak to the given file, replacing any existing
+    /// values.
+    ///
+    /// Album gain is left as-is, since it can't be computed from a single
+    /// track.
+    pub(crate) fn write(&self, to: Format, path: &Path, config: &Config) -> Result<()> {
+        let mut file = Probe::open(path)?
+            .set_file_type(meta::format_file_type(to))
+            .read()?;
+
+        let tag_type = file.primary_tag_type();
+
+        if file.primary_tag().is_none() {
+            file.insert_tag(Tag::new(tag_type));
+        }
+
+        let Some(tag) = file.primary_tag_mut() else {
+            bail!("No tag to write ReplayGain to");
+        };
+
+        tag.insert_text(ItemKey::ReplayGainTrackGain, format!("{:.2} dB", self.gain));
+        tag.insert_text(ItemKey::ReplayGainTrackPeak, format!("{:.6}", self.peak));
+
+        meta::save_file(&file, path, config)
+    }
+}
@@ -50,14 +50,25 @@
 #![allow(clippy::drain_collect)]
 
 mod archive;
+mod audiobook;
 mod bitrates;
 pub mod cli;
 mod condition;
 mod config;
+pub mod conversion;
 mod format;
+mod gain;
 mod link;
-mod meta;
+mod manifest;
+mod media_type;
+pub mod meta;
 mod out;
+mod progress;
+mod sanitize;
 mod set_bit_rate;
+mod settings;
 mod shell;
 mod tasks;
+mod template;
+#[cfg(test)]
+mod testing;
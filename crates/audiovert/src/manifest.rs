@@ -0,0 +1,62 @@
+use core::fmt::Write as _;
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+
+/// A file produced by a conversion, as listed in a checksum manifest.
+pub(crate) struct Entry {
+    /// The index of the task which produced the file, used to keep entries in
+    /// task order.
+    pub(crate) index: usize,
+    /// The path of the source.
+    pub(crate) source: PathBuf,
+    /// A summary of the metadata of the source.
+    pub(crate) summary: Option<String>,
+    /// The path of the produced file.
+    pub(crate) path: PathBuf,
+    /// The SHA-256 hash of the produced file in hex.
+    pub(crate) hash: String,
+}
+
+/// Compute the SHA-256 hash of a file in hex.
+pub(crate) fn hash_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+
+    let mut hex = String::with_capacity(64);
+
+    for b in hasher.finalize() {
+        _ = write!(hex, "{b:02x}");
+    }
+
+    Ok(hex)
+}
+
+/// Write a manifest in the format used by `sha256sum`, with the source and
+/// metadata of each file as comments.
+///
+/// Paths are relative to the directory of the manifest when possible, so that
+/// it can be checked with `sha256sum -c` after being moved together with the
+/// files.
+pub(crate) fn write(path: &Path, entries: &[Entry]) -> Result<()> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut out = String::new();
+
+    for e in entries {
+        _ = writeln!(out, "# source: {}", e.source.display());
+
+        if let Some(summary) = &e.summary {
+            _ = writeln!(out, "# meta: {summary}");
+        }
+
+        let relative = e.path.strip_prefix(dir).unwrap_or(&e.path);
+        _ = writeln!(out, "{}  {}", e.hash, relative.display());
+    }
+
+    fs::write(path, out).with_context(|| path.display().to_string())
+}
@@ -0,0 +1,99 @@
+use core::error::Error;
+use core::fmt;
+use core::str::FromStr;
+
+use std::collections::HashMap;
+
+/// The default canonical forms of common media types, keyed by lowercase
+/// value.
+const DEFAULT: [(&str, &str); 19] = [
+    ("cd", "CD"),
+    ("compact disc", "CD"),
+    ("cd-r", "CD"),
+    ("enhanced cd", "CD"),
+    ("hdcd", "CD"),
+    ("sacd", "SACD"),
+    ("digital", "Digital"),
+    ("digital media", "Digital"),
+    ("web", "Digital"),
+    ("vinyl", "Vinyl"),
+    ("lp", "Vinyl"),
+    ("7\" vinyl", "Vinyl"),
+    ("10\" vinyl", "Vinyl"),
+    ("12\" vinyl", "Vinyl"),
+    ("cassette", "Cassette"),
+    ("dvd", "DVD"),
+    ("dvd-audio", "DVD"),
+    ("dvd-video", "DVD"),
+    ("blu-ray", "Blu-ray"),
+];
+
+#[derive(Debug)]
+pub(crate) struct SetMediaTypeErr;
+
+impl fmt::Display for SetMediaTypeErr {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "missing '=' separator")
+    }
+}
+
+impl Error for SetMediaTypeErr {}
+
+/// Override how a media type is normalized, like `Digital Media=WEB`.
+#[derive(Clone)]
+pub(crate) struct SetMediaType {
+    pub(crate) from: String,
+    pub(crate) to: String,
+}
+
+impl FromStr for SetMediaType {
+    type Err = SetMediaTypeErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((from, to)) = s.rsplit_once('=') else {
+            return Err(SetMediaTypeErr);
+        };
+
+        Ok(SetMediaType {
+            from: from.trim().to_owned(),
+            to: to.trim().to_owned(),
+        })
+    }
+}
+
+/// Canonical forms of media types, so that disc directories get consistent
+/// prefixes.
+pub(crate) struct MediaTypes {
+    map: HashMap<String, String>,
+}
+
+impl MediaTypes {
+    /// Override the canonical form of a media type, which is matched
+    /// regardless of case.
+    pub(crate) fn set(&mut self, from: &str, to: String) {
+        self.map.insert(from.to_lowercase(), to);
+    }
+
+    /// Normalize a media type, returning `None` if it maps to an empty value.
+    ///
+    /// Unknown media types are kept as they are.
+    pub(crate) fn normalize(&self, s: &str) -> Option<String> {
+        let Some(to) = self.map.get(&s.to_lowercase()) else {
+            return Some(s.to_owned());
+        };
+
+        (!to.is_empty()).then(|| to.clone())
+    }
+}
+
+impl Default for MediaTypes {
+    fn default() -> Self {
+        Self {
+            map: DEFAULT
+                .into_iter()
+                .map(|(from, to)| (from.to_owned(), to.to_owned()))
+                .collect(),
+        }
+    }
+}
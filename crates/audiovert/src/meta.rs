@@ -1,36 +1,206 @@
+//! Organizing music files by their metadata.
+//!
+//! This exposes the naming logic used by `--meta`, so that paths can be
+//! planned from tags without running a conversion:
+//!
+//! ```
+//! use std::path::Path;
+//!
+//! use audiovert::meta::{self, Parts};
+//!
+//! let mut parts = Parts::new("Pink Floyd", "The Wall", "Hey You", 1979, 1);
+//! parts.set = Some((2, 2));
+//!
+//! let path = meta::plan_path(&parts, "mp3")?;
+//! assert_eq!(
+//!     path,
+//!     Path::new("Pink Floyd/The Wall (1979)/02/Pink Floyd - The Wall - 01 - Hey You.mp3")
+//! );
+//! # Ok::<_, anyhow::Error>(())
+//! ```
+
+use core::error::Error;
+use core::fmt;
 use core::str::FromStr;
+use core::time::Duration;
 
 use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
 use std::io::Cursor;
 use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{Result, bail};
 use jiff::civil::Date;
-use lofty::config::WriteOptions;
 use lofty::file::{AudioFile, FileType, TaggedFile, TaggedFileExt};
+use lofty::id3::v2::Id3v2Tag;
+use lofty::picture::{MimeType, Picture, PictureType};
 use lofty::probe::Probe;
-use lofty::tag::{ItemKey, ItemValue, Tag, TagItem, TagType};
+use lofty::tag::{ItemKey, ItemValue, Tag, TagExt, TagItem, TagType};
+use serde::Serialize;
 
-use crate::config::{Db, Source};
+use crate::config::{Config, Db, Source};
 use crate::format::Format;
-use crate::out::{Out, blank, info};
-
-pub(crate) struct Parts {
-    year: i16,
-    artist: String,
-    album: String,
-    track: u32,
-    title: String,
-    media_type: Option<String>,
-    set: Option<(u32, u32)>,
+use crate::media_type::MediaTypes;
+use crate::out::{Out, blank, info, warn};
+use crate::sanitize::Sanitize;
+use crate::template::{Field, Template, Value};
+
+#[derive(Debug)]
+pub(crate) struct DiscDirsErr;
+
+impl fmt::Display for DiscDirsErr {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected one of always, multiple or never")
+    }
+}
+
+impl Error for DiscDirsErr {}
+
+/// When to add a directory for each disc of a set.
+#[derive(Clone, Copy, Default)]
+pub(crate) enum DiscDirs {
+    /// Whenever the disc number is known, even for single-disc sets.
+    Always,
+    /// Only if the set has more than one disc.
+    #[default]
+    Multiple,
+    /// Never, putting all tracks of a set in the album directory.
+    Never,
+}
+
+impl FromStr for DiscDirs {
+    type Err = DiscDirsErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "always" => Ok(DiscDirs::Always),
+            "multiple" => Ok(DiscDirs::Multiple),
+            "never" => Ok(DiscDirs::Never),
+            _ => Err(DiscDirsErr),
+        }
+    }
+}
+
+/// Options for how output paths are laid out from metadata.
+pub(crate) struct Layout {
+    /// Add a top-level directory named after the genre.
+    pub(crate) genre: bool,
+    /// Use the composer instead of the artist as the top-level directory,
+    /// adding the performing artist to the end of file names.
+    pub(crate) composer: bool,
+    /// The directory to use instead of the artist for compilations.
+    pub(crate) various_artists: String,
+    /// A template to use instead of the default layout.
+    pub(crate) template: Option<Template>,
+    /// How characters are sanitized in path components.
+    pub(crate) sanitize: Sanitize,
+    /// Leading articles to move to the end of artist directories, like
+    /// `Beatles, The`. Empty if articles shouldn't be moved.
+    pub(crate) articles: Vec<String>,
+    /// Convert artists, albums and titles to title case.
+    pub(crate) title_case: bool,
+    /// When to add a directory for each disc.
+    pub(crate) disc_dirs: DiscDirs,
+    /// Canonical forms of media types used in disc directories.
+    pub(crate) media_types: MediaTypes,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            genre: false,
+            composer: false,
+            various_artists: String::from("Various Artists"),
+            template: None,
+            sanitize: Sanitize::default(),
+            articles: Vec::new(),
+            title_case: false,
+            disc_dirs: DiscDirs::default(),
+            media_types: MediaTypes::default(),
+        }
+    }
+}
+
+/// Tag-derived fields used to plan the path of a file.
+///
+/// Construct it using [`Parts::new`] with the required fields, and set the
+/// optional fields as needed.
+pub struct Parts {
+    /// The release year.
+    pub year: i16,
+    /// The artist, which is the album artist if available.
+    pub artist: String,
+    /// The album title.
+    pub album: String,
+    /// The track number.
+    pub track: u32,
+    /// The total number of tracks, used to pad track numbers.
+    pub track_total: Option<u32>,
+    /// The track title.
+    pub title: String,
+    /// The genre.
+    pub genre: Option<String>,
+    /// The composer.
+    pub composer: Option<String>,
+    /// The album artist as tagged.
+    pub album_artist: Option<String>,
+    /// The artist of the track as tagged, which is added to file names of
+    /// compilations.
+    pub track_artist: Option<String>,
+    /// If the album is a compilation, which puts it in the various artists
+    /// directory.
+    pub compilation: bool,
+    /// The original media type, like `CD` or `Vinyl`, which prefixes disc
+    /// directories.
+    pub media_type: Option<String>,
+    /// The disc number and the total number of discs in the set.
+    pub set: Option<(u32, u32)>,
+    /// The subtitle of the disc, which is added to disc directories.
+    pub set_subtitle: Option<String>,
+    /// If the year was taken from the source path instead of tags.
+    year_from_path: bool,
 }
 
 impl Parts {
+    /// Construct parts from the fields which are required to plan a path.
+    pub fn new(
+        artist: impl Into<String>,
+        album: impl Into<String>,
+        title: impl Into<String>,
+        year: i16,
+        track: u32,
+    ) -> Self {
+        Self {
+            year,
+            artist: artist.into(),
+            album: album.into(),
+            track,
+            track_total: None,
+            title: title.into(),
+            genre: None,
+            composer: None,
+            album_artist: None,
+            track_artist: None,
+            compilation: false,
+            media_type: None,
+            set: None,
+            set_subtitle: None,
+            year_from_path: false,
+        }
+    }
+
+    /// Extract parts from the given source.
+    ///
+    /// If `year_from_path` is configured, the year is taken from a directory
+    /// name like `Album (1997)` as a last resort if it's missing from the
+    /// tags.
     pub(crate) fn from_path(
         source: &Source,
         db: &Db,
         errors: &mut Vec<String>,
         tagged: &mut Option<Meta>,
+        config: &Config,
     ) -> Result<Option<Self>> {
         let file: TaggedFile = match source {
             Source::File { file } => {
@@ -125,6 +295,18 @@ impl Parts {
             track = parse {
                 TrackNumber = 1,
             },
+            track_total = parse {
+                TrackTotal = 1,
+            },
+            genre = text {
+                Genre = 1,
+            },
+            composer = text {
+                Composer = 1,
+            },
+            compilation = flag {
+                FlagCompilation = 1,
+            },
             media_type = text {
                 OriginalMediaType = 1,
             },
@@ -134,6 +316,9 @@ impl Parts {
             disc_total = parse {
                 DiscTotal = 1,
             },
+            set_subtitle = text {
+                SetSubtitle = 1,
+            },
         }
 
         fn text(value: &ItemValue) -> Option<&str> {
@@ -141,6 +326,14 @@ impl Parts {
             (!s.is_empty()).then_some(s)
         }
 
+        fn flag(value: &ItemValue) -> Option<bool> {
+            match value.text()?.trim() {
+                "1" => Some(true),
+                "0" => Some(false),
+                s => s.parse().ok(),
+            }
+        }
+
         fn year_like(value: &ItemValue) -> Option<i16> {
             let s = value.text()?;
             let s = s.trim();
@@ -153,6 +346,23 @@ impl Parts {
                 return Some(year);
             }
 
+            // Timestamps like `1997-06-01T00:00:00Z`, where the date is taken
+            // as written.
+            if let Some((date, _)) = s.split_once('T')
+                && let Ok(date) = date.parse::<Date>()
+            {
+                return Some(date.year());
+            }
+
+            // Partial dates like `1997-06` or `1997/06`.
+            if let Some((year, month)) = s.split_once(['-', '/'])
+                && year.len() == 4
+                && month.len() <= 2
+                && month.parse::<u8>().is_ok_and(|m| (1..=12).contains(&m))
+            {
+                return year.parse::<i16>().ok();
+            }
+
             None
         }
 
@@ -164,8 +374,16 @@ impl Parts {
             T::from_str(s).ok()
         }
 
+        let year_fallback = if year.value.is_none() && config.year_from_path {
+            db.to_path(source)?
+                .parent()
+                .and_then(|dir| dir.iter().rev().find_map(|c| find_year(c.to_str()?)))
+        } else {
+            None
+        };
+
         let mut value = || {
-            if year.value.is_none() {
+            if year.value.is_none() && year_fallback.is_none() {
                 errors.push("missing year".to_string());
             }
 
@@ -185,30 +403,91 @@ impl Parts {
                 errors.push("missing track number".to_string());
             }
 
+            let album_artist = tag.get_string(&ItemKey::AlbumArtist).map(str::trim);
+            let track_artist = tag.get_string(&ItemKey::TrackArtist).map(str::trim);
+
+            // Compilations are either flagged, or have a "Various Artists" like
+            // album artist.
+            let compilation = compilation.value.unwrap_or_default()
+                || album_artist.is_some_and(is_various_artists);
+
             let set = match (disc_number.value, disc_total.value) {
                 (Some(n), Some(total)) => Some((n, total)),
                 _ => None,
             };
 
             Some(Self {
-                year: year.value?,
+                year: year.value.or(year_fallback)?,
                 artist: artist.value?.to_owned(),
                 album: album.value?.to_owned(),
                 track: track.value?,
+                track_total: track_total.value,
                 title: title.value?.to_owned(),
+                genre: genre.value.map(str::to_owned),
+                composer: composer.value.map(str::to_owned),
+                album_artist: album_artist.filter(|s| !s.is_empty()).map(str::to_owned),
+                track_artist: track_artist.filter(|s| !s.is_empty()).map(str::to_owned),
+                compilation,
                 media_type: media_type.value.map(str::to_owned),
                 set,
+                set_subtitle: set_subtitle.value.map(str::to_owned),
+                year_from_path: year.value.is_none(),
             })
         };
 
-        Ok(value())
+        let mut parts = value();
+
+        if let Some(parts) = &mut parts {
+            parts.repair_encoding(config.fix_encoding, errors);
+        }
+
+        Ok(parts)
     }
 
-    /// Append parts to a buffer.
-    pub(crate) fn append_to(&self, path: &mut PathBuf) {
+    /// Check text for probable mojibake, repairing it if `fix` is set and
+    /// reporting it as an error otherwise.
+    fn repair_encoding(&mut self, fix: bool, errors: &mut Vec<String>) {
+        let required = [
+            ("artist", &mut self.artist),
+            ("album", &mut self.album),
+            ("title", &mut self.title),
+        ];
+
+        let optional = [
+            ("genre", &mut self.genre),
+            ("composer", &mut self.composer),
+            ("album artist", &mut self.album_artist),
+            ("track artist", &mut self.track_artist),
+            ("media type", &mut self.media_type),
+            ("disc subtitle", &mut self.set_subtitle),
+        ];
+
+        let optional = optional
+            .into_iter()
+            .filter_map(|(name, s)| Some((name, s.as_mut()?)));
+
+        for (name, s) in required.into_iter().chain(optional) {
+            let Some(repaired) = repair_mojibake(s) else {
+                continue;
+            };
+
+            if fix {
+                *s = repaired;
+            } else {
+                errors.push(format!(
+                    "{name} {s:?} looks wrongly decoded, probably {repaired:?} (see --fix-encoding)"
+                ));
+            }
+        }
+    }
+
+    /// Append parts to a buffer, returning `true` if any component was
+    /// truncated to fit the maximum length.
+    pub(crate) fn append_to(&self, layout: &Layout, path: &mut PathBuf) -> Result<bool> {
         use core::fmt::Write;
 
         let mut s = String::new();
+        let mut truncated = false;
 
         macro_rules! s {
             ($($arg:tt)*) => {{
@@ -218,11 +497,51 @@ impl Parts {
             }};
         }
 
-        push_sanitized(path, s!("{}", self.artist));
-        push_sanitized(path, s!("{} ({})", &self.album, self.year));
+        // The artist used for the directory and the start of the file name,
+        // and the performing artist if it should be added at the end.
+        let (artist, performer) = match &self.composer {
+            _ if self.compilation => (layout.various_artists.as_str(), None),
+            Some(composer) if layout.composer && *composer != self.artist => {
+                (composer.as_str(), Some(self.artist.as_str()))
+            }
+            Some(composer) if layout.composer => (composer.as_str(), None),
+            _ => (self.artist.as_str(), None),
+        };
+
+        if let Some(template) = &layout.template {
+            let components = template.render(|field| self.value(field, artist));
+            let last = components.len().saturating_sub(1);
+
+            for (n, component) in components.iter().enumerate() {
+                truncated |= if n == last {
+                    layout.sanitize.push_file_name(path, component)?
+                } else {
+                    layout.sanitize.push(path, component)
+                };
+            }
+
+            return Ok(truncated);
+        }
+
+        if layout.genre {
+            truncated |= layout
+                .sanitize
+                .push(path, self.genre.as_deref().unwrap_or("Unknown Genre"));
+        }
+
+        truncated |= layout
+            .sanitize
+            .push(path, &move_article(artist, &layout.articles));
+        truncated |= layout
+            .sanitize
+            .push(path, s!("{} ({})", &self.album, self.year));
 
         if let Some((n, total)) = self.set
-            && total > 1
+            && match layout.disc_dirs {
+                DiscDirs::Always => true,
+                DiscDirs::Multiple => total > 1,
+                DiscDirs::Never => false,
+            }
         {
             s.clear();
 
@@ -232,92 +551,254 @@ impl Parts {
             }
 
             _ = write!(s, "{n:02}");
-            push_sanitized(path, &s);
-        }
-
-        push_sanitized(
-            path,
-            s!(
-                "{} - {} - {:02} - {}",
-                self.artist,
-                self.album,
-                self.track,
-                &self.title
-            ),
-        );
+
+            if let Some(subtitle) = &self.set_subtitle {
+                _ = write!(s, " - {subtitle}");
+            }
+
+            truncated |= layout.sanitize.push(path, &s);
+        }
+
+        s.clear();
+
+        // Pad track numbers to the width of the total, so that albums with
+        // 100 or more tracks still sort correctly.
+        let width = self
+            .track_total
+            .map_or(2, |total| total.to_string().len().max(2));
+
+        _ = write!(s, "{artist} - {} - {:0width$} - ", self.album, self.track);
+
+        // Compilations include the artist of each track so they can still be
+        // told apart.
+        if self.compilation
+            && let Some(track_artist) = &self.track_artist
+        {
+            _ = write!(s, "{track_artist} - ");
+        }
+
+        s.push_str(&self.title);
+
+        if let Some(performer) = performer {
+            _ = write!(s, " ({performer})");
+        }
+
+        truncated |= layout.sanitize.push_file_name(path, &s)?;
+        Ok(truncated)
+    }
+
+    /// Convert artists, the album and the title to title case.
+    pub(crate) fn title_case(&mut self) {
+        let optional = [&mut self.album_artist, &mut self.track_artist];
+
+        for s in [&mut self.artist, &mut self.album, &mut self.title]
+            .into_iter()
+            .chain(optional.into_iter().flatten())
+        {
+            *s = title_case(s);
+        }
+    }
+
+    /// Normalize the media type to its canonical form.
+    pub(crate) fn normalize_media_type(&mut self, media_types: &MediaTypes) {
+        if let Some(media_type) = &self.media_type {
+            self.media_type = media_types.normalize(media_type);
+        }
+    }
+
+    /// A one-line summary of the parts, like `Artist - Album (1997) - 01 -
+    /// Title`.
+    pub(crate) fn summary(&self) -> String {
+        format!(
+            "{} - {} ({}) - {:02} - {}",
+            self.artist, self.album, self.year, self.track, self.title
+        )
+    }
+
+    /// The year if it was taken from the source path instead of tags.
+    pub(crate) fn year_from_path(&self) -> Option<i16> {
+        self.year_from_path.then_some(self.year)
+    }
+
+    /// Get the value of a template field, where `artist` is the artist
+    /// selected by the layout.
+    fn value<'a>(&'a self, field: Field, artist: &'a str) -> Value<'a> {
+        fn text(value: Option<&str>) -> Value<'_> {
+            value.map_or(Value::None, Value::Text)
+        }
+
+        match field {
+            Field::Artist => Value::Text(artist),
+            Field::AlbumArtist => Value::Text(self.album_artist.as_deref().unwrap_or(&self.artist)),
+            Field::TrackArtist => Value::Text(self.track_artist.as_deref().unwrap_or(&self.artist)),
+            Field::Composer => text(self.composer.as_deref()),
+            Field::Album => Value::Text(&self.album),
+            Field::Year => u32::try_from(self.year).map_or(Value::None, Value::Number),
+            Field::Genre => text(self.genre.as_deref()),
+            Field::Disc => self.set.map_or(Value::None, |(n, _)| Value::Number(n)),
+            Field::DiscTotal => self
+                .set
+                .map_or(Value::None, |(_, total)| Value::Number(total)),
+            Field::DiscSubtitle => text(self.set_subtitle.as_deref()),
+            Field::MediaType => text(self.media_type.as_deref()),
+            Field::Track => Value::Number(self.track),
+            Field::TrackTotal => self.track_total.map_or(Value::None, Value::Number),
+            Field::Title => Value::Text(&self.title),
+        }
     }
 }
 
-fn push_sanitized(path: &mut PathBuf, s: &str) {
-    path.push(sanitize(s).as_ref());
+/// Plan the relative path of a file with the given extension, using the
+/// default layout of `--meta`.
+///
+/// The path is `<artist>/<album> (<year>)/<file>`, with a disc directory like
+/// `CD 01` for sets with more than one disc. Characters which aren't valid in
+/// file names are replaced and long components are truncated.
+///
+/// Fails if a valid file name can't be planned, like when the path would be too
+/// long for Windows.
+pub fn plan_path(parts: &Parts, ext: &str) -> Result<PathBuf> {
+    let mut path = PathBuf::new();
+    parts.append_to(&Layout::default(), &mut path)?;
+    path.add_extension(ext);
+    Ok(path)
 }
 
-fn sanitize(s: &str) -> Cow<'_, str> {
-    let mut out = String::new();
+/// Convert a string to title case, like `the wall` or `THE WALL` to `The
+/// Wall`.
+///
+/// Small words like `of` are kept lowercase unless they come first, and words
+/// in all caps like `ABBA` are kept as is unless the whole string is in caps.
+fn title_case(s: &str) -> String {
+    const SMALL: [&str; 12] = [
+        "a", "an", "and", "at", "by", "for", "in", "of", "on", "or", "the", "to",
+    ];
 
-    let rest = 'normalize: {
-        for (n, c) in s.char_indices() {
-            match c {
-                ':' => {
-                    out.push_str(&s[..n]);
-                    break 'normalize &s[n..];
-                }
-                c => {
-                    if map(c).is_some() {
-                        out.push_str(&s[..n]);
-                        break 'normalize &s[n..];
-                    }
-                }
-            }
+    let all_caps = !s.chars().any(char::is_lowercase);
+
+    let mut out = String::with_capacity(s.len());
+
+    for (n, word) in s.split(' ').enumerate() {
+        if n > 0 {
+            out.push(' ');
         }
 
-        return Cow::Borrowed(s);
-    };
+        let letters = word.chars().filter(|c| c.is_alphabetic()).count();
 
-    fn map(c: char) -> Option<&'static str> {
-        match c {
-            '\\' => Some("+"),
-            '/' => Some("+"),
-            '<' => Some(""),
-            '>' => Some(""),
-            '?' => Some(""),
-            '*' => Some("-"),
-            '|' => Some(""),
-            '"' => Some(""),
-            _ => None,
-        }
-    }
-
-    let mut last_whitespace = false;
-    let mut it = rest.chars();
-
-    while let Some(c) = it.next() {
-        match c {
-            ':' => {
-                if it.clone().next().is_some_and(|c| c.is_whitespace()) {
-                    out.push_str(" - ");
-                    it.next();
-                } else {
-                    out.push('-');
-                }
-            }
-            c => {
-                if let Some(repl) = map(c) {
-                    out.push_str(repl);
-                    continue;
-                }
+        if !all_caps && letters > 1 && !word.chars().any(char::is_lowercase) {
+            out.push_str(word);
+            continue;
+        }
 
-                if last_whitespace && c.is_whitespace() {
-                    continue;
-                }
+        let word = if all_caps {
+            word.to_lowercase()
+        } else {
+            word.to_owned()
+        };
+
+        if n > 0 && SMALL.iter().any(|small| small.eq_ignore_ascii_case(&word)) {
+            out.push_str(&word.to_lowercase());
+            continue;
+        }
 
+        // Capitalize the first letter, which might come after punctuation like
+        // in `(live)`.
+        let mut capitalized = false;
+
+        for c in word.chars() {
+            if !capitalized && c.is_alphabetic() {
+                out.extend(c.to_uppercase());
+                capitalized = true;
+            } else {
                 out.push(c);
-                last_whitespace = c.is_whitespace();
             }
         }
     }
 
-    Cow::Owned(out)
+    out
+}
+
+/// Move a leading article like `The` to the end, so that `The Beatles` becomes
+/// `Beatles, The`.
+fn move_article<'a>(s: &'a str, articles: &[String]) -> Cow<'a, str> {
+    for article in articles {
+        let Some(head) = s.get(..article.len()) else {
+            continue;
+        };
+
+        if !head.eq_ignore_ascii_case(article) {
+            continue;
+        }
+
+        let rest = &s[article.len()..];
+
+        if !rest.starts_with(char::is_whitespace) {
+            continue;
+        }
+
+        let rest = rest.trim_start();
+
+        if rest.is_empty() {
+            continue;
+        }
+
+        return Cow::Owned(format!("{rest}, {head}"));
+    }
+
+    Cow::Borrowed(s)
+}
+
+/// Repair text which was decoded as Latin-1 even though it is UTF-8, like `Ã©`
+/// instead of `é`.
+///
+/// This returns `None` unless the text is valid UTF-8 when encoded as Latin-1,
+/// which is unlikely for text which isn't mojibake.
+fn repair_mojibake(s: &str) -> Option<String> {
+    // Only characters which are UTF-8 lead bytes can start a sequence.
+    if !s.chars().any(|c| matches!(c, '\u{c2}'..='\u{f4}')) {
+        return None;
+    }
+
+    let bytes = s
+        .chars()
+        .map(|c| u8::try_from(u32::from(c)).ok())
+        .collect::<Option<Vec<u8>>>()?;
+
+    let repaired = String::from_utf8(bytes).ok()?;
+    (repaired != s).then_some(repaired)
+}
+
+/// Find a year like `1997` in a path component, which is four digits not
+/// surrounded by other digits.
+fn find_year(s: &str) -> Option<i16> {
+    let bytes = s.as_bytes();
+
+    for (n, window) in bytes.windows(4).enumerate() {
+        if !window.iter().all(u8::is_ascii_digit) {
+            continue;
+        }
+
+        let before = n.checked_sub(1).and_then(|n| bytes.get(n));
+        let after = bytes.get(n + 4);
+
+        if before.is_some_and(u8::is_ascii_digit) || after.is_some_and(u8::is_ascii_digit) {
+            continue;
+        }
+
+        let year = s[n..n + 4].parse::<i16>().ok()?;
+
+        if (1000..=2999).contains(&year) {
+            return Some(year);
+        }
+    }
+
+    None
+}
+
+/// Test if an album artist indicates a compilation.
+fn is_various_artists(s: &str) -> bool {
+    let s = s.trim();
+    s.eq_ignore_ascii_case("various artists") || s.eq_ignore_ascii_case("various")
 }
 
 pub(super) struct Meta {
@@ -330,13 +811,140 @@ impl Meta {
         self.file.tags().iter().map(|tag| tag.item_count()).sum()
     }
 
-    /// Dump tags to output.
-    pub(crate) fn dump(&self, o: &mut Out<'_>) -> Result<()> {
+    /// Get the nominal audio bitrate of the file in kbps, if known.
+    pub(crate) fn bitrate(&self) -> Option<u32> {
+        self.file.properties().audio_bitrate()
+    }
+
+    /// Get the duration of the audio.
+    pub(crate) fn duration(&self) -> Duration {
+        self.file.properties().duration()
+    }
+
+    /// Get the front cover picture, or the first picture if there is no front
+    /// cover.
+    pub(crate) fn cover(&self) -> Option<&Picture> {
+        let tag = self.file.primary_tag()?;
+
+        tag.get_picture_type(PictureType::CoverFront)
+            .or_else(|| tag.pictures().first())
+    }
+
+    /// Read metadata from the file at the given path.
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        Ok(Self {
+            file: lofty::read_from_path(path)?,
+        })
+    }
+
+    /// Verify that a converted file can be read as the given format and that
+    /// it has the essential tags of this file.
+    pub(crate) fn verify(&self, to: Format, path: &Path) -> Result<()> {
+        const KEYS: [ItemKey; 4] = [
+            ItemKey::TrackTitle,
+            ItemKey::TrackArtist,
+            ItemKey::AlbumTitle,
+            ItemKey::TrackNumber,
+        ];
+
+        let file = verify_file(to, path)?;
+
+        let Some(expected) = self.file.primary_tag() else {
+            return Ok(());
+        };
+
+        let Some(tag) = file.primary_tag().or(file.first_tag()) else {
+            bail!("Converted file has no tags");
+        };
+
+        for key in KEYS {
+            if expected.get(&key).is_some() && tag.get(&key).is_none() {
+                bail!("Converted file is missing {}", key_name(&key));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compare the items of the primary tag with those of another file,
+    /// returning the number of differences.
+    ///
+    /// Keys are compared by name so that tags of different types can be
+    /// compared, and keys whose name is in `ignore` are skipped.
+    pub(crate) fn compare(
+        &self,
+        o: &mut Out<'_>,
+        other: &Meta,
+        ignore: &[String],
+    ) -> Result<usize> {
+        fn items(meta: &Meta, ignore: &[String]) -> BTreeMap<String, Vec<String>> {
+            let mut items = BTreeMap::<_, Vec<_>>::new();
+
+            for item in meta.file.primary_tag().into_iter().flat_map(Tag::items) {
+                let key = key_name(item.key());
+
+                if ignore.iter().any(|i| i.eq_ignore_ascii_case(&key)) {
+                    continue;
+                }
+
+                let value = match item.value() {
+                    ItemValue::Text(text) => format!("{text:?}"),
+                    ItemValue::Locator(link) => format!("link {link:?}"),
+                    ItemValue::Binary(data) => format!("{} bytes", data.len()),
+                };
+
+                items.entry(key.into_owned()).or_default().push(value);
+            }
+
+            items
+        }
+
+        let a = items(self, ignore);
+        let b = items(other, ignore);
+
+        let mut differences = 0;
+
+        for key in a.keys().chain(b.keys()).collect::<BTreeSet<_>>() {
+            match (a.get(key), b.get(key)) {
+                (Some(a), None) => {
+                    warn!(o, "{key}: only in first");
+                    let mut o = o.indent(1);
+
+                    for value in a {
+                        blank!(o, "{value}");
+                    }
+                }
+                (None, Some(b)) => {
+                    warn!(o, "{key}: only in second");
+                    let mut o = o.indent(1);
+
+                    for value in b {
+                        blank!(o, "{value}");
+                    }
+                }
+                (Some(a), Some(b)) if a != b => {
+                    warn!(o, "{key}: differs");
+                    let mut o = o.indent(1);
+                    blank!(o, "first: {}", a.join(", "));
+                    blank!(o, "second: {}", b.join(", "));
+                }
+                _ => continue,
+            }
+
+            differences += 1;
+        }
+
+        Ok(differences)
+    }
+
+    /// Dump tags, only including items whose key contains one of `keys` if
+    /// it's non-empty.
+    pub(crate) fn dump(&self, o: &mut Out<'_>, keys: &[String]) -> Result<()> {
         for tag in self.file.tags() {
             info!(o, "tag: {}", repr_tag_type(tag.tag_type()));
             let mut o = o.indent(1);
 
-            for item in tag.items() {
+            for item in tag.items().filter(|item| matches_keys(item, keys)) {
                 dump_tag_item(&mut o, item)?;
             }
         }
@@ -344,7 +952,46 @@ impl Meta {
         Ok(())
     }
 
-    pub(crate) fn tag_file(&self, to: Format, path: &Path) -> Result<()> {
+    /// Dump tags in a form which can be serialized, like to JSON, filtered
+    /// the same way as [`Meta::dump`].
+    pub(crate) fn dump_json(&self, keys: &[String]) -> Vec<TagDump<'_>> {
+        let mut tags = Vec::new();
+
+        for tag in self.file.tags() {
+            let items = tag
+                .items()
+                .filter(|item| matches_keys(item, keys))
+                .map(|item| ItemDump {
+                    key: key_name(item.key()).into_owned(),
+                    value: match item.value() {
+                        ItemValue::Text(text) => ValueDump::Text { text },
+                        ItemValue::Locator(link) => ValueDump::Locator { link },
+                        ItemValue::Binary(data) => ValueDump::Binary { bytes: data.len() },
+                    },
+                })
+                .collect();
+
+            tags.push(TagDump {
+                tag_type: repr_tag_type(tag.tag_type()),
+                items,
+            });
+        }
+
+        tags
+    }
+
+    /// Copy tags to the file at the given path, including embedded pictures
+    /// unless `strip_art` is configured.
+    ///
+    /// The `cover` is embedded as the front cover if the source has no
+    /// pictures of its own.
+    pub(crate) fn tag_file(
+        &self,
+        to: Format,
+        path: &Path,
+        config: &Config,
+        cover: Option<&Picture>,
+    ) -> Result<()> {
         // First try to copy tags immediately.
         let Some(source_tag) = self.file.primary_tag() else {
             return Ok(());
@@ -362,8 +1009,20 @@ impl Meta {
         'done: {
             // Primary method: try to insert the primary tag directly if it is
             // identical to the source tag type.
-            if source_tag.tag_type() == tag_type {
-                existing.insert_tag(source_tag.clone());
+            if source_tag.tag_type() == tag_type && !config.fix_encoding && !config.all_tags {
+                let mut tag = source_tag.clone();
+
+                if config.strip_art {
+                    while tag.picture_count() > 0 {
+                        tag.remove_picture(0);
+                    }
+                } else if let Some(cover) = cover
+                    && tag.picture_count() == 0
+                {
+                    tag.push_picture(cover.clone());
+                }
+
+                existing.insert_tag(tag);
                 break 'done;
             }
 
@@ -371,22 +1030,221 @@ impl Meta {
             // tags to be skipped.
             let mut tag = Tag::new(tag_type);
 
-            for item in source_tag.items() {
-                tag.insert(item.clone());
+            // Secondary tags, like an ID3v1 tag next to an ID3v2 tag.
+            let secondary = self
+                .file
+                .tags()
+                .iter()
+                .filter(|t| config.all_tags && t.tag_type() != source_tag.tag_type());
+
+            let items = source_tag.items().map(|item| (true, item)).chain(
+                secondary
+                    .clone()
+                    .flat_map(|t| t.items().map(|item| (false, item))),
+            );
+
+            for (primary, item) in items {
+                // Keys which weren't recognized by the source tag reader
+                // would otherwise be dropped.
+                let mut item = match known_key(item.key()) {
+                    Some(key) => TagItem::new(key, item.value().clone()),
+                    None => item.clone(),
+                };
+
+                if config.fix_encoding
+                    && let Some(repaired) = item.value().text().and_then(repair_mojibake)
+                {
+                    let mut repaired = TagItem::new(item.key().clone(), ItemValue::Text(repaired));
+                    repaired.set_lang(*item.lang());
+                    repaired.set_description(item.description().to_owned());
+                    item = repaired;
+                }
+
+                // Secondary tags only fill in what the primary tag is missing.
+                if !primary && tag.get(item.key()).is_some() {
+                    continue;
+                }
+
+                // Comments and lyrics can have one entry per language, which
+                // shouldn't replace each other.
+                if matches!(item.key(), ItemKey::Comment | ItemKey::Lyrics) {
+                    tag.push(item);
+                } else if tag_type == TagType::Id3v2
+                    && *item.key() == ItemKey::MusicBrainzRecordingId
+                {
+                    // ID3v2 stores the recording identifier in a `UFID` frame
+                    // rather than as a text frame, so lofty doesn't consider
+                    // it mapped and would drop it.
+                    tag.insert_unchecked(item);
+                } else {
+                    tag.insert(item);
+                }
+            }
+
+            // Pictures are not items, so they have to be copied separately.
+            if !config.strip_art {
+                for picture in source_tag.pictures() {
+                    tag.push_picture(picture.clone());
+                }
+
+                if tag.picture_count() == 0 {
+                    for picture in secondary.flat_map(Tag::pictures) {
+                        tag.push_picture(picture.clone());
+                    }
+                }
+
+                if let Some(cover) = cover
+                    && tag.picture_count() == 0
+                {
+                    tag.push_picture(cover.clone());
+                }
             }
 
             existing.insert_tag(tag);
         };
 
-        let mut options = WriteOptions::default();
-        options.use_id3v23(true);
-        existing.save_to_path(path, options)?;
-        Ok(())
+        save_file(&existing, path, config)
+    }
+}
+
+/// Save the tags of a file.
+///
+/// Generic ID3v2 tags are converted to ID3v2 tags first since lofty otherwise
+/// drops the MusicBrainz recording identifier, which is stored in a `UFID`
+/// frame. When writing ID3v2.3 it would also drop dates and write pictures
+/// without a description in a way it can't read back.
+pub(crate) fn save_file(file: &TaggedFile, path: &Path, config: &Config) -> Result<()> {
+    let options = config.write_options();
+
+    for tag in file.tags() {
+        if tag.tag_type() != TagType::Id3v2 {
+            tag.save_to_path(path, options)?;
+            continue;
+        }
+
+        let mut tag = tag.clone();
+
+        let pictures = if config.id3v24 {
+            0
+        } else {
+            tag.picture_count() as usize
+        };
+
+        for n in 0..pictures {
+            if let Some(picture) = tag.pictures().get(n)
+                && picture.description().is_none()
+            {
+                let mut picture = picture.clone();
+                picture.set_description(Some(String::new()));
+                tag.set_picture(n, picture);
+            }
+        }
+
+        Id3v2Tag::from(tag).save_to_path(path, options)?;
+    }
+
+    Ok(())
+}
+
+/// Map keys which the tag reader didn't recognize to their known keys so that
+/// they can be written to any tag type. This covers spellings like lowercase
+/// `replaygain_track_gain` in an ID3 `TXXX` frame, `UNSYNCEDLYRICS` in Vorbis
+/// comments, or MusicBrainz identifiers written with the naming convention of
+/// another tag type.
+fn known_key(key: &ItemKey) -> Option<ItemKey> {
+    let ItemKey::Unknown(name) = key else {
+        return None;
+    };
+
+    const KEYS: [(&str, ItemKey); 23] = [
+        ("REPLAYGAIN_TRACK_GAIN", ItemKey::ReplayGainTrackGain),
+        ("REPLAYGAIN_TRACK_PEAK", ItemKey::ReplayGainTrackPeak),
+        ("REPLAYGAIN_ALBUM_GAIN", ItemKey::ReplayGainAlbumGain),
+        ("REPLAYGAIN_ALBUM_PEAK", ItemKey::ReplayGainAlbumPeak),
+        ("LYRICS", ItemKey::Lyrics),
+        ("UNSYNCEDLYRICS", ItemKey::Lyrics),
+        ("UNSYNCED LYRICS", ItemKey::Lyrics),
+        ("COMMENT", ItemKey::Comment),
+        ("COMMENTS", ItemKey::Comment),
+        ("MUSICBRAINZ_TRACKID", ItemKey::MusicBrainzRecordingId),
+        ("MUSICBRAINZ_RELEASETRACKID", ItemKey::MusicBrainzTrackId),
+        ("MUSICBRAINZ_ALBUMID", ItemKey::MusicBrainzReleaseId),
+        (
+            "MUSICBRAINZ_RELEASEGROUPID",
+            ItemKey::MusicBrainzReleaseGroupId,
+        ),
+        ("MUSICBRAINZ_ARTISTID", ItemKey::MusicBrainzArtistId),
+        (
+            "MUSICBRAINZ_ALBUMARTISTID",
+            ItemKey::MusicBrainzReleaseArtistId,
+        ),
+        ("MUSICBRAINZ_WORKID", ItemKey::MusicBrainzWorkId),
+        ("MusicBrainz Track Id", ItemKey::MusicBrainzRecordingId),
+        ("MusicBrainz Release Track Id", ItemKey::MusicBrainzTrackId),
+        ("MusicBrainz Album Id", ItemKey::MusicBrainzReleaseId),
+        (
+            "MusicBrainz Release Group Id",
+            ItemKey::MusicBrainzReleaseGroupId,
+        ),
+        ("MusicBrainz Artist Id", ItemKey::MusicBrainzArtistId),
+        (
+            "MusicBrainz Album Artist Id",
+            ItemKey::MusicBrainzReleaseArtistId,
+        ),
+        ("MusicBrainz Work Id", ItemKey::MusicBrainzWorkId),
+    ];
+
+    KEYS.into_iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, key)| key)
+}
+
+/// A serializable dump of a tag.
+#[derive(Serialize)]
+pub(crate) struct TagDump<'a> {
+    #[serde(rename = "type")]
+    tag_type: &'static str,
+    items: Vec<ItemDump<'a>>,
+}
+
+/// A serializable dump of a tag item.
+#[derive(Serialize)]
+struct ItemDump<'a> {
+    key: String,
+    #[serde(flatten)]
+    value: ValueDump<'a>,
+}
+
+/// A serializable dump of the value of a tag item, where binary data is only
+/// represented by its length.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum ValueDump<'a> {
+    Text { text: &'a str },
+    Locator { link: &'a str },
+    Binary { bytes: usize },
+}
+
+/// The name of a key, like `TrackTitle`, or the raw key if it's unknown.
+fn key_name(key: &ItemKey) -> Cow<'_, str> {
+    match key {
+        ItemKey::Unknown(key) => Cow::Borrowed(key),
+        key => Cow::Owned(format!("{key:?}")),
     }
 }
 
+/// Test if the key of an item contains one of the given strings, ignoring case.
+fn matches_keys(item: &TagItem, keys: &[String]) -> bool {
+    if keys.is_empty() {
+        return true;
+    }
+
+    let name = key_name(item.key()).to_lowercase();
+    keys.iter().any(|key| name.contains(&key.to_lowercase()))
+}
+
 fn dump_tag_item(o: &mut Out<'_>, item: &TagItem) -> Result<()> {
-    info!(o, "{:?}:", item.key());
+    info!(o, "{}:", key_name(item.key()));
     let mut o = o.indent(1);
 
     match item.value() {
@@ -404,12 +1262,47 @@ fn dump_tag_item(o: &mut Out<'_>, item: &TagItem) -> Result<()> {
     Ok(())
 }
 
-fn format_file_type(format: Format) -> FileType {
+/// Verify that a converted file can be read as the given format and has
+/// audio.
+pub(crate) fn verify_file(to: Format, path: &Path) -> Result<TaggedFile> {
+    let file = Probe::open(path)?
+        .set_file_type(format_file_type(to))
+        .read()?;
+
+    if file.properties().duration().is_zero() {
+        bail!("Converted file has no audio");
+    }
+
+    Ok(file)
+}
+
+/// Parse the contents of a cover image file into a front cover picture.
+pub(crate) fn cover_picture(data: &[u8]) -> Result<Picture> {
+    let mut picture = Picture::from_reader(&mut Cursor::new(data))?;
+    picture.set_pic_type(PictureType::CoverFront);
+    Ok(picture)
+}
+
+/// Get the file name to use for a cover picture based on its mime type.
+pub(crate) fn cover_file_name(picture: &Picture) -> Option<&'static str> {
+    match picture.mime_type()? {
+        MimeType::Jpeg => Some("cover.jpg"),
+        MimeType::Png => Some("cover.png"),
+        MimeType::Gif => Some("cover.gif"),
+        MimeType::Bmp => Some("cover.bmp"),
+        MimeType::Tiff => Some("cover.tiff"),
+        _ => None,
+    }
+}
+
+pub(crate) fn format_file_type(format: Format) -> FileType {
     match format {
         Format::Aac => FileType::Aac,
+        Format::Alac => FileType::Mp4,
         Format::Flac => FileType::Flac,
         Format::Mp3 => FileType::Mpeg,
         Format::Ogg => FileType::Vorbis,
+        Format::Opus => FileType::Opus,
         Format::Wav => FileType::Wav,
     }
 }
@@ -426,3 +1319,135 @@ fn repr_tag_type(ty: TagType) -> &'static str {
         _ => "Unknown",
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use lofty::config::{ParseOptions, WriteOptions};
+    use lofty::file::{AudioFile, FileType, TaggedFileExt};
+    use lofty::id3::v2::{Frame, Id3v2Tag};
+    use lofty::mp4::Mp4Codec;
+    use lofty::mpeg::MpegFile;
+    use lofty::ogg::VorbisComments;
+    use lofty::probe::Probe;
+    use lofty::tag::{Accessor, ItemKey, Tag, TagExt, TagType};
+
+    use super::Meta;
+    use crate::format::Format;
+    use crate::testing;
+
+    /// Write a file with the given tag to the directory.
+    fn write(dir: &Path, name: &str, contents: &[u8], tag: Option<&Tag>) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+
+        if let Some(tag) = tag {
+            tag.save_to_path(&path, WriteOptions::default()).unwrap();
+        }
+
+        path
+    }
+
+    #[test]
+    fn flac_to_alac() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut tag = Tag::new(TagType::VorbisComments);
+        tag.set_artist(String::from("Artist"));
+        tag.set_album(String::from("Album"));
+        tag.set_title(String::from("Title"));
+        tag.set_track(3);
+        tag.set_track_total(12);
+
+        let from = write(dir.path(), "from.flac", &testing::flac(), Some(&tag));
+        let to = write(dir.path(), "to.m4a", &testing::mp4(Mp4Codec::ALAC), None);
+
+        let config = testing::config(&[]);
+        let meta = Meta::open(&from).unwrap();
+        meta.tag_file(Format::Alac, &to, &config, None).unwrap();
+
+        let file = Probe::open(&to).unwrap().read().unwrap();
+        assert_eq!(file.file_type(), FileType::Mp4);
+
+        let tag = file.tag(TagType::Mp4Ilst).expect("mp4 ilst tag");
+        assert_eq!(tag.artist().as_deref(), Some("Artist"));
+        assert_eq!(tag.album().as_deref(), Some("Album"));
+        assert_eq!(tag.title().as_deref(), Some("Title"));
+        assert_eq!(tag.track(), Some(3));
+        assert_eq!(tag.track_total(), Some(12));
+        assert!(tag.get(&ItemKey::TrackArtist).is_some());
+
+        meta.verify(Format::Alac, &to).unwrap();
+    }
+
+    #[test]
+    fn flac_to_mp3_musicbrainz() {
+        const RELEASE: &str = "5b11f4ce-a62d-471e-81fc-a69a8278c7da";
+        const RECORDING: &str = "b1a9c0e9-d987-4042-ae91-78d6a3267d69";
+        const RELEASE_GROUP: &str = "1e9f2f31-2d1b-44f7-8c1e-0d3b4f1c2a9e";
+        const ARTIST: &str = "65f4f0c5-ef9e-490c-aee3-909e7ae6b2ab";
+
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut comments = VorbisComments::default();
+        comments.set_artist(String::from("Artist"));
+        comments.set_title(String::from("Title"));
+        comments.push(String::from("MUSICBRAINZ_ALBUMID"), String::from(RELEASE));
+        comments.push(String::from("MUSICBRAINZ_TRACKID"), String::from(RECORDING));
+        comments.push(
+            String::from("MUSICBRAINZ_RELEASEGROUPID"),
+            String::from(RELEASE_GROUP),
+        );
+        comments.push(String::from("MUSICBRAINZ_ARTISTID"), String::from(ARTIST));
+
+        let from = write(dir.path(), "from.flac", &testing::flac(), None);
+        comments
+            .save_to_path(&from, WriteOptions::default())
+            .unwrap();
+        let meta = Meta::open(&from).unwrap();
+
+        for args in [&[][..], &["--id3v24"]] {
+            let to = write(dir.path(), "to.mp3", &testing::mp3(), None);
+
+            let config = testing::config(args);
+            meta.tag_file(Format::Mp3, &to, &config, None).unwrap();
+            meta.tag_file(Format::Mp3, &to, &config, None).unwrap();
+
+            let file = Probe::open(&to).unwrap().read().unwrap();
+            assert_eq!(file.file_type(), FileType::Mpeg);
+
+            let tag = file.tag(TagType::Id3v2).expect("id3v2 tag");
+            assert_eq!(tag.artist().as_deref(), Some("Artist"));
+            assert_eq!(tag.title().as_deref(), Some("Title"));
+            assert_eq!(
+                tag.get_string(&ItemKey::MusicBrainzReleaseId),
+                Some(RELEASE)
+            );
+            assert_eq!(
+                tag.get_string(&ItemKey::MusicBrainzRecordingId),
+                Some(RECORDING)
+            );
+            assert_eq!(
+                tag.get_string(&ItemKey::MusicBrainzReleaseGroupId),
+                Some(RELEASE_GROUP)
+            );
+            assert_eq!(tag.get_string(&ItemKey::MusicBrainzArtistId), Some(ARTIST));
+
+            // Other tools read the identifiers from the frames Picard writes.
+            let mut file = fs::File::open(&to).unwrap();
+            let mpeg = MpegFile::read_from(&mut file, ParseOptions::new()).unwrap();
+            let id3v2: &Id3v2Tag = mpeg.id3v2().expect("id3v2 tag");
+            assert_eq!(id3v2.get_user_text("MusicBrainz Album Id"), Some(RELEASE));
+            assert!(id3v2.into_iter().any(|frame| matches!(
+                frame,
+                Frame::UniqueFileIdentifier(ufid)
+                    if ufid.owner == "http://musicbrainz.org"
+                        && ufid.identifier == RECORDING.as_bytes()
+            )));
+
+            meta.verify(Format::Mp3, &to).unwrap();
+        }
+    }
+}
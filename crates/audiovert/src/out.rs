@@ -3,6 +3,7 @@ use core::fmt;
 
 use std::io;
 
+use termcolor::Buffer;
 use termcolor::ColorSpec;
 use termcolor::HyperlinkSpec;
 use termcolor::WriteColor;
@@ -84,6 +85,28 @@ impl<'a> Out<'a> {
         }
     }
 
+    /// The colors used by this output.
+    pub(crate) fn colors(&self) -> &'a Colors {
+        self.c
+    }
+
+    /// The current indentation level.
+    pub(crate) fn level(&self) -> usize {
+        self.indent.get()
+    }
+
+    /// Test if this output supports colors.
+    pub(crate) fn supports_color(&self) -> bool {
+        self.o.supports_color()
+    }
+
+    /// Write output which was buffered separately.
+    pub(crate) fn write_buffer(&mut self, buf: &Buffer) -> io::Result<()> {
+        self.o.write_all(buf.as_slice())?;
+        self.o.flush()?;
+        Ok(())
+    }
+
     pub(crate) fn blank(&mut self, m: impl fmt::Display) -> io::Result<()> {
         self.prefix()?;
         writeln!(self.o, "{m}")?;
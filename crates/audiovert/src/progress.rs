@@ -0,0 +1,104 @@
+use std::io::{self, IsTerminal, Stderr, Write};
+use std::time::{Duration, Instant};
+
+/// How often progress is printed when stderr isn't a terminal.
+const INTERVAL: Duration = Duration::from_secs(5);
+
+/// The width of the progress bar in characters.
+const BAR_WIDTH: usize = 30;
+
+/// The maximum number of characters of the current file name to show.
+const NAME_WIDTH: usize = 50;
+
+/// Progress of a batch of tasks, printed to stderr.
+///
+/// If stderr is a terminal this is a single line which is redrawn as tasks
+/// complete, otherwise a line is printed periodically.
+pub(crate) struct Progress {
+    total: usize,
+    done: usize,
+    current: String,
+    tty: bool,
+    drawn: bool,
+    last: Option<Instant>,
+    o: Stderr,
+}
+
+impl Progress {
+    pub(crate) fn new(total: usize) -> Self {
+        let o = io::stderr();
+
+        Self {
+            total,
+            done: 0,
+            current: String::new(),
+            tty: o.is_terminal(),
+            drawn: false,
+            last: None,
+            o,
+        }
+    }
+
+    /// Mark a task as started, showing its name.
+    pub(crate) fn start(&mut self, name: &str) -> io::Result<()> {
+        self.current.clear();
+        self.current.extend(name.chars().take(NAME_WIDTH));
+        self.draw()
+    }
+
+    /// Mark a task as completed.
+    pub(crate) fn complete(&mut self) -> io::Result<()> {
+        self.done = self.done.saturating_add(1).min(self.total);
+        self.draw()
+    }
+
+    /// Clear the progress line so that other output can be written.
+    pub(crate) fn clear(&mut self) -> io::Result<()> {
+        if self.drawn {
+            let mut o = self.o.lock();
+            o.write_all(b"\r\x1b[2K")?;
+            o.flush()?;
+            self.drawn = false;
+        }
+
+        Ok(())
+    }
+
+    /// Finish the progress, clearing the progress line.
+    pub(crate) fn finish(&mut self) -> io::Result<()> {
+        self.clear()?;
+
+        if !self.tty && self.last.is_some() {
+            writeln!(self.o, "Progress: {}/{} done", self.done, self.total)?;
+        }
+
+        Ok(())
+    }
+
+    fn draw(&mut self) -> io::Result<()> {
+        let mut o = self.o.lock();
+
+        if !self.tty {
+            if self.last.is_some_and(|last| last.elapsed() < INTERVAL) {
+                return Ok(());
+            }
+
+            self.last = Some(Instant::now());
+            writeln!(o, "Progress: {}/{} {}", self.done, self.total, self.current)?;
+            return Ok(());
+        }
+
+        let filled = (self.done * BAR_WIDTH).checked_div(self.total).unwrap_or(0);
+
+        write!(o, "\r\x1b[2K[")?;
+
+        for n in 0..BAR_WIDTH {
+            o.write_all(if n < filled { b"=" } else { b" " })?;
+        }
+
+        write!(o, "] {}/{} {}", self.done, self.total, self.current)?;
+        o.flush()?;
+        self.drawn = true;
+        Ok(())
+    }
+}
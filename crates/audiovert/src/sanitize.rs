@@ -0,0 +1,524 @@
+use core::error::Error;
+use core::fmt;
+use core::str::FromStr;
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::{Result, bail};
+use unicode_normalization::{UnicodeNormalization, is_nfc, is_nfd};
+
+/// The default maximum length of a path component in bytes, which is the limit
+/// of most filesystems.
+pub(crate) const DEFAULT_MAX_LEN: usize = 255;
+
+/// Room left in file names for the extension and the partial file extension.
+const FILE_NAME_RESERVE: usize = 16;
+
+/// The maximum length of a full path on Windows.
+const WINDOWS_MAX_PATH: usize = 260;
+
+/// Device names which can't be used as file names on Windows, regardless of
+/// extension.
+const WINDOWS_RESERVED: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Appended to truncated components.
+const ELLIPSIS: &str = "…";
+
+/// The default replacements for characters which are not allowed or
+/// problematic in file names.
+const DEFAULT: [(char, &str); 8] = [
+    ('\\', "+"),
+    ('/', "+"),
+    ('<', ""),
+    ('>', ""),
+    ('?', ""),
+    ('*', "-"),
+    ('|', ""),
+    ('"', ""),
+];
+
+#[derive(Debug)]
+pub(crate) enum SetSanitizeErr {
+    MissingSeparator,
+    NotSingleCharacter,
+}
+
+impl fmt::Display for SetSanitizeErr {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingSeparator => write!(f, "missing '=' separator"),
+            Self::NotSingleCharacter => write!(f, "expected a single character before '='"),
+        }
+    }
+}
+
+impl Error for SetSanitizeErr {}
+
+#[derive(Debug)]
+pub(crate) struct UnicodeFormErr;
+
+impl fmt::Display for UnicodeFormErr {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected one of nfc, nfd or none")
+    }
+}
+
+impl Error for UnicodeFormErr {}
+
+/// The unicode normalization form to use for path components.
+#[derive(Clone, Copy, Default)]
+pub(crate) enum UnicodeForm {
+    /// Composed characters, like `é` as a single character.
+    #[default]
+    Nfc,
+    /// Decomposed characters, like `e` followed by a combining accent.
+    Nfd,
+    /// Leave characters as they are in the metadata.
+    None,
+}
+
+impl FromStr for UnicodeForm {
+    type Err = UnicodeFormErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nfc" => Ok(UnicodeForm::Nfc),
+            "nfd" => Ok(UnicodeForm::Nfd),
+            "none" => Ok(UnicodeForm::None),
+            _ => Err(UnicodeFormErr),
+        }
+    }
+}
+
+/// Override how a single character is sanitized, like `/= - `.
+#[derive(Clone)]
+pub(crate) struct SetSanitize {
+    pub(crate) from: char,
+    pub(crate) to: String,
+}
+
+impl FromStr for SetSanitize {
+    type Err = SetSanitizeErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut it = s.chars();
+
+        let (Some(from), Some('=')) = (it.next(), it.next()) else {
+            return Err(if s.contains('=') {
+                SetSanitizeErr::NotSingleCharacter
+            } else {
+                SetSanitizeErr::MissingSeparator
+            });
+        };
+
+        Ok(SetSanitize {
+            from,
+            to: it.as_str().to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for SetSanitize {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.from, self.to)
+    }
+}
+
+/// Character replacements used to turn metadata into path components.
+pub(crate) struct Sanitize {
+    map: HashMap<char, String>,
+    /// Override for `:`, which otherwise becomes ` - ` when followed by
+    /// whitespace and `-` when not.
+    colon: Option<String>,
+    /// The unicode normalization form to apply.
+    pub(crate) form: UnicodeForm,
+    /// The maximum length of a path component in bytes.
+    pub(crate) max_len: usize,
+    /// Make components valid on Windows, by escaping reserved device names and
+    /// trimming trailing dots and spaces. Enabled by default on Windows.
+    pub(crate) windows: bool,
+}
+
+impl Sanitize {
+    /// Override the replacement of a single character.
+    pub(crate) fn set(&mut self, from: char, to: String) {
+        if from == ':' {
+            self.colon = Some(to);
+        } else {
+            self.map.insert(from, to);
+        }
+    }
+
+    /// Push a sanitized path component, returning `true` if it was truncated.
+    pub(crate) fn push(&self, path: &mut PathBuf, s: &str) -> bool {
+        push_truncated(path, &self.component(s), self.max_len)
+    }
+
+    /// Push a sanitized file name without an extension, returning `true` if it
+    /// was truncated.
+    ///
+    /// This leaves room for extensions, and on Windows also keeps the full path
+    /// within the maximum path length. Fails if there's no room left for any
+    /// part of the name, since every file in the directory would otherwise
+    /// get the same name.
+    pub(crate) fn push_file_name(&self, path: &mut PathBuf, s: &str) -> Result<bool> {
+        let mut max = self.max_len.saturating_sub(FILE_NAME_RESERVE);
+
+        if self.windows {
+            let used = path.as_os_str().len() + 1 + FILE_NAME_RESERVE;
+            max = max.min(WINDOWS_MAX_PATH.saturating_sub(used));
+        }
+
+        let s = self.component(s);
+
+        if s.len() > max && max <= ELLIPSIS.len() {
+            bail!(
+                "no room for a file name in {}, since the path is too long for Windows",
+                path.display()
+            );
+        }
+
+        Ok(push_truncated(path, &s, max))
+    }
+
+    /// Sanitize a string into a path component.
+    fn component<'a>(&self, s: &'a str) -> Cow<'a, str> {
+        let s = self.sanitize(s);
+
+        if self.windows {
+            return Cow::Owned(windows_safe(&s));
+        }
+
+        s
+    }
+
+    fn sanitize<'a>(&self, s: &'a str) -> Cow<'a, str> {
+        let normalized = match self.form {
+            UnicodeForm::Nfc if !is_nfc(s) => s.nfc().collect::<String>(),
+            UnicodeForm::Nfd if !is_nfd(s) => s.nfd().collect::<String>(),
+            _ => return self.replace(s),
+        };
+
+        Cow::Owned(self.replace(&normalized).into_owned())
+    }
+
+    fn replace<'a>(&self, s: &'a str) -> Cow<'a, str> {
+        let mut out = String::new();
+
+        let rest = 'normalize: {
+            let mut last_whitespace = false;
+
+            for (n, c) in s.char_indices() {
+                if c == ':'
+                    || (last_whitespace && c.is_whitespace())
+                    || self.map.get(&c).is_some_and(|repl| repl.chars().ne([c]))
+                {
+                    out.push_str(&s[..n]);
+                    break 'normalize &s[n..];
+                }
+
+                last_whitespace = c.is_whitespace();
+            }
+
+            return Cow::Borrowed(s);
+        };
+
+        let mut last_whitespace = out.ends_with(char::is_whitespace);
+        let mut it = rest.chars();
+
+        while let Some(c) = it.next() {
+            let repl = match c {
+                ':' => match &self.colon {
+                    Some(repl) => Some(repl.as_str()),
+                    None => {
+                        if it.clone().next().is_some_and(|c| c.is_whitespace()) {
+                            out.push_str(if last_whitespace { "- " } else { " - " });
+                            last_whitespace = true;
+                            it.next();
+                        } else {
+                            out.push('-');
+                            last_whitespace = false;
+                        }
+
+                        continue;
+                    }
+                },
+                c => self.map.get(&c).map(String::as_str),
+            };
+
+            if let Some(mut repl) = repl {
+                if last_whitespace {
+                    repl = repl.trim_start();
+                }
+
+                out.push_str(repl);
+
+                if let Some(c) = repl.chars().last() {
+                    last_whitespace = c.is_whitespace();
+                }
+
+                continue;
+            }
+
+            if last_whitespace && c.is_whitespace() {
+                continue;
+            }
+
+            out.push(c);
+            last_whitespace = c.is_whitespace();
+        }
+
+        Cow::Owned(out)
+    }
+}
+
+impl Default for Sanitize {
+    fn default() -> Self {
+        Self {
+            map: DEFAULT
+                .into_iter()
+                .map(|(c, repl)| (c, repl.to_owned()))
+                .collect(),
+            colon: None,
+            form: UnicodeForm::default(),
+            max_len: DEFAULT_MAX_LEN,
+            windows: cfg!(windows),
+        }
+    }
+}
+
+/// Trim trailing dots and spaces which Windows silently strips, and append an
+/// underscore to reserved device names like `CON` or `NUL.txt`.
+fn windows_safe(s: &str) -> String {
+    let s = s.trim_end_matches(['.', ' ']);
+
+    if s.is_empty() {
+        return String::from("_");
+    }
+
+    let (stem, rest) = s.split_at(s.find('.').unwrap_or(s.len()));
+
+    if WINDOWS_RESERVED
+        .iter()
+        .any(|name| stem.trim_end().eq_ignore_ascii_case(name))
+    {
+        return format!("{stem}_{rest}");
+    }
+
+    s.to_owned()
+}
+
+/// Push a path component, truncating it to `max` bytes and returning `true` if
+/// it was truncated.
+fn push_truncated(path: &mut PathBuf, s: &str, max: usize) -> bool {
+    if s.len() <= max {
+        path.push(s);
+        return false;
+    }
+
+    path.push(truncate(s, max));
+    true
+}
+
+/// Truncate a string to at most `max` bytes including an ellipsis, preferably
+/// cutting at a word boundary.
+///
+/// If there's no room for the ellipsis, the string is cut without one.
+fn truncate(s: &str, max: usize) -> String {
+    if max <= ELLIPSIS.len() {
+        let mut end = max.min(s.len());
+
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        return s[..end].to_owned();
+    }
+
+    let mut end = max.saturating_sub(ELLIPSIS.len()).min(s.len());
+
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    let head = &s[..end];
+
+    // Only cut at a word boundary if it doesn't lose too much.
+    let head = match head.rfind(char::is_whitespace) {
+        Some(n) if n >= end / 2 => &head[..n],
+        _ => head,
+    };
+
+    let mut out = head.trim_end().to_owned();
+    out.push_str(ELLIPSIS);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{Sanitize, truncate};
+
+    const FORBIDDEN: [char; 9] = ['\\', '/', '<', '>', '?', '*', '|', '"', ':'];
+
+    fn sanitize(s: &str) -> String {
+        Sanitize::default().sanitize(s).into_owned()
+    }
+
+    /// Every string of up to four characters from an alphabet of forbidden
+    /// characters, whitespace and letters.
+    fn strings() -> impl Iterator<Item = String> {
+        const ALPHABET: [char; 14] = [
+            'a', 'b', ' ', '\t', ':', '\\', '/', '<', '>', '?', '*', '|', '"', 'é',
+        ];
+
+        (0..=4u32).flat_map(|len| {
+            (0..ALPHABET.len().pow(len)).map(move |mut n| {
+                let mut s = String::new();
+
+                for _ in 0..len {
+                    s.push(ALPHABET[n % ALPHABET.len()]);
+                    n /= ALPHABET.len();
+                }
+
+                s
+            })
+        })
+    }
+
+    #[test]
+    fn no_forbidden_characters() {
+        for s in strings() {
+            let out = sanitize(&s);
+
+            assert!(
+                !out.contains(FORBIDDEN),
+                "{s:?} sanitized to {out:?} which contains a forbidden character"
+            );
+        }
+    }
+
+    #[test]
+    fn whitespace_collapses() {
+        for s in strings() {
+            let out = sanitize(&s);
+            let mut chars = out.chars();
+            let mut last = chars.next();
+
+            for c in chars {
+                assert!(
+                    !(c.is_whitespace() && last.is_some_and(char::is_whitespace)),
+                    "{s:?} sanitized to {out:?} which contains a run of whitespace"
+                );
+
+                last = Some(c);
+            }
+        }
+
+        assert_eq!(sanitize("a  b"), "a b");
+        assert_eq!(sanitize("a \t b: c"), "a b - c");
+        assert_eq!(sanitize("a < b"), "a b");
+        assert_eq!(sanitize("a :  b"), "a - b");
+    }
+
+    #[test]
+    fn colon_followed_by_whitespace() {
+        assert_eq!(sanitize("Title: Subtitle"), "Title - Subtitle");
+        assert_eq!(sanitize("a: b: c"), "a - b - c");
+    }
+
+    #[test]
+    fn inner_colon() {
+        assert_eq!(sanitize("12:34"), "12-34");
+        assert_eq!(sanitize("a:b:c"), "a-b-c");
+        assert_eq!(sanitize("a::b"), "a--b");
+    }
+
+    #[test]
+    fn leading_and_trailing_colons() {
+        assert_eq!(sanitize(":a"), "-a");
+        assert_eq!(sanitize(": a"), " - a");
+        assert_eq!(sanitize("a:"), "a-");
+        assert_eq!(sanitize("a: "), "a - ");
+        assert_eq!(sanitize(":"), "-");
+        assert_eq!(sanitize("::"), "--");
+    }
+
+    #[test]
+    fn forbidden_characters() {
+        assert_eq!(sanitize("AC/DC"), "AC+DC");
+        assert_eq!(sanitize("a\\b"), "a+b");
+        assert_eq!(sanitize("Why?"), "Why");
+        assert_eq!(sanitize("<\"a\"|b>"), "ab");
+        assert_eq!(sanitize("a*b"), "a-b");
+    }
+
+    #[test]
+    fn overrides() {
+        let mut sanitize = Sanitize::default();
+        sanitize.set(':', String::from("_"));
+        sanitize.set('/', String::from(" & "));
+
+        assert_eq!(sanitize.sanitize("a: b"), "a_ b");
+        assert_eq!(sanitize.sanitize("AC/DC"), "AC & DC");
+        assert_eq!(sanitize.sanitize("AC / DC"), "AC & DC");
+    }
+
+    #[test]
+    fn truncate_within_max() {
+        for s in [
+            "Hey You",
+            "a b c d e f",
+            "ÅÄÖ åäö",
+            "…………",
+            "abcdefghijklmnop",
+        ] {
+            for max in 0..s.len() + 4 {
+                let out = truncate(s, max);
+                assert!(out.len() <= max, "{s:?} truncated to {max} as {out:?}");
+            }
+        }
+
+        assert_eq!(truncate("Hey You", 0), "");
+        assert_eq!(truncate("Hey You", 2), "He");
+        assert_eq!(truncate("Hey You", 6), "Hey…");
+    }
+
+    #[test]
+    fn long_windows_base() {
+        let sanitize = Sanitize {
+            windows: true,
+            ..Sanitize::default()
+        };
+
+        let name = "Artist - Album - 01 - Title";
+
+        // No room is left for the name, so all tracks would get the same name.
+        for len in [240, 243, 250, 259, 260, 300] {
+            let mut path = PathBuf::from("a".repeat(len));
+            assert!(sanitize.push_file_name(&mut path, name).is_err(), "{len}");
+        }
+
+        // Short names still fit.
+        let mut path = PathBuf::from("a".repeat(241));
+        assert!(!sanitize.push_file_name(&mut path, "01").unwrap());
+        assert!(path.ends_with("01"));
+
+        // Otherwise names are truncated to keep the path within 260 bytes,
+        // leaving room for the extension.
+        let base = "a".repeat(230);
+        let mut path = PathBuf::from(&base);
+        assert!(sanitize.push_file_name(&mut path, name).unwrap());
+        assert_eq!(path, PathBuf::from(&base).join("Artist -…"));
+        assert!(path.as_os_str().len() + 16 <= 260);
+    }
+}
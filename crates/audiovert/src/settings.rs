@@ -0,0 +1,111 @@
+//! Defaults loaded from a TOML configuration file, which are overridden by
+//! options on the command line.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+
+use crate::condition::Condition;
+use crate::template::Template;
+
+/// The contents of a configuration file, where every field is optional.
+///
+/// ```toml
+/// conversion = ["lossless=mp3", "lossy=same"]
+/// meta-template = "{albumartist}/{album} ({year})/{track:02} - {title}"
+/// jobs = 4
+///
+/// [meta-sanitize]
+/// "/" = " - "
+/// "?" = "?"
+/// ```
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct File {
+    /// The default for `--conversion`.
+    conversion: Vec<String>,
+    /// The default for `--meta-template`.
+    meta_template: Option<String>,
+    /// Replacements applied before those of `--meta-sanitize`.
+    meta_sanitize: BTreeMap<String, String>,
+    /// The default for `--jobs`.
+    pub(crate) jobs: Option<NonZeroUsize>,
+}
+
+impl File {
+    /// Load a configuration file. Files which don't exist are only an error
+    /// if `required` is set.
+    pub(crate) fn load(path: &Path, required: bool) -> Result<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => {
+                return Ok(Self::default());
+            }
+            Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+        };
+
+        toml::from_str(&contents).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    /// Parse the conversions in the file.
+    pub(crate) fn conversion(&self) -> Result<Vec<Condition>> {
+        let mut conversion = Vec::with_capacity(self.conversion.len());
+
+        for c in &self.conversion {
+            conversion.push(
+                c.parse()
+                    .with_context(|| format!("conversion `{c}` in configuration"))?,
+            );
+        }
+
+        Ok(conversion)
+    }
+
+    /// Parse the template in the file.
+    pub(crate) fn meta_template(&self) -> Result<Option<Template>> {
+        let Some(template) = &self.meta_template else {
+            return Ok(None);
+        };
+
+        let template = template
+            .parse()
+            .with_context(|| format!("meta-template `{template}` in configuration"))?;
+
+        Ok(Some(template))
+    }
+
+    /// Get the character replacements in the file.
+    pub(crate) fn meta_sanitize(&self) -> Result<Vec<(char, String)>> {
+        let mut sanitize = Vec::with_capacity(self.meta_sanitize.len());
+
+        for (from, to) in &self.meta_sanitize {
+            let mut chars = from.chars();
+
+            let (Some(c), None) = (chars.next(), chars.next()) else {
+                bail!("meta-sanitize key `{from}` in configuration must be a single character");
+            };
+
+            sanitize.push((c, to.clone()));
+        }
+
+        Ok(sanitize)
+    }
+}
+
+/// The default path of the configuration file.
+pub(crate) fn default_path() -> Option<PathBuf> {
+    let mut path = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => env::home_dir()?.join(".config"),
+    };
+
+    path.push("audiovert");
+    path.push("config.toml");
+    Some(path)
+}
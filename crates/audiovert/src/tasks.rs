@@ -3,18 +3,27 @@ use core::fmt;
 use std::collections::HashMap;
 use std::ffi::OsString;
 
+use crate::audiobook::Chapter;
 use crate::config::{Db, Source};
+use crate::conversion::ConversionResult;
 use crate::format::Format;
 use crate::link::{Link, MaybeLink};
 use crate::meta::Meta;
 
 pub(crate) struct Tasks {
+    pub(crate) chapters: Vec<Chapter>,
     pub(crate) errors: Vec<PathError>,
     pub(crate) matching_conversions: Vec<MatchingConversion>,
     pub(crate) tasks: Vec<Task>,
     pub(crate) to_trash: Vec<Trash>,
     pub(crate) already_exists: Vec<Exists>,
     pub(crate) unsupported: Vec<Unsupported>,
+    /// The number of files left out by `--include` and `--exclude`.
+    pub(crate) filtered: usize,
+    pub(crate) low_bitrate: Vec<LowBitrate>,
+    pub(crate) year_fallbacks: Vec<YearFallback>,
+    pub(crate) truncated: Vec<Truncated>,
+    pub(crate) results: Vec<ConversionResult>,
     pub(crate) db: Db,
     pub(crate) meta: HashMap<Source, Meta>,
 }
@@ -22,12 +31,18 @@ pub(crate) struct Tasks {
 impl Tasks {
     pub(crate) fn new() -> Self {
         Self {
+            chapters: Vec::new(),
             errors: Vec::new(),
             matching_conversions: Vec::new(),
             tasks: Vec::new(),
             to_trash: Vec::new(),
             already_exists: Vec::new(),
             unsupported: Vec::new(),
+            filtered: 0,
+            low_bitrate: Vec::new(),
+            year_fallbacks: Vec::new(),
+            truncated: Vec::new(),
+            results: Vec::new(),
             db: Db::new(),
             meta: HashMap::new(),
         }
@@ -105,6 +120,18 @@ impl fmt::Display for TaskKind {
     }
 }
 
+/// A source whose destination path had components truncated.
+pub(crate) struct Truncated {
+    pub(crate) source: Source,
+    pub(crate) path: MaybeLink,
+}
+
+/// A source where the year was taken from its path instead of tags.
+pub(crate) struct YearFallback {
+    pub(crate) source: Source,
+    pub(crate) year: i16,
+}
+
 /// A collection of errors associated with a particular path.
 pub(crate) struct PathError {
     pub(crate) source: Source,
@@ -118,7 +145,12 @@ pub(crate) struct Task {
     pub(crate) source: Source,
     pub(crate) to_path: MaybeLink,
     pub(crate) moved: bool,
+    /// Whether the task was converted or transferred by this run, as opposed
+    /// to being completed because its destination already existed.
+    pub(crate) processed: bool,
     pub(crate) pre_remove: Vec<(&'static str, MaybeLink)>,
+    /// A summary of the metadata of the source, written to `--manifest`.
+    pub(crate) summary: Option<String>,
 }
 
 impl Task {
@@ -161,3 +193,12 @@ pub(crate) struct Unsupported {
     pub(crate) source: Source,
     pub(crate) ext: String,
 }
+
+/// A lossy source which falls below the `--min-bitrate` threshold.
+pub(crate) struct LowBitrate {
+    pub(crate) source: Source,
+    /// The nominal bitrate of the source in kbps.
+    pub(crate) bitrate: u32,
+    /// Whether the source was skipped rather than transferred as-is.
+    pub(crate) skipped: bool,
+}
@@ -0,0 +1,237 @@
+use core::error::Error;
+use core::fmt;
+use core::str::FromStr;
+
+#[derive(Debug)]
+pub(crate) enum TemplateErr {
+    UnknownField(String),
+    BadWidth(String),
+    Unclosed,
+    Unopened,
+    Empty,
+}
+
+impl fmt::Display for TemplateErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateErr::UnknownField(name) => {
+                write!(f, "unknown placeholder `{{{name}}}`, expected one of: ")?;
+
+                for (n, field) in Field::ALL.iter().enumerate() {
+                    if n > 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    write!(f, "{{{}}}", field.name())?;
+                }
+
+                Ok(())
+            }
+            TemplateErr::BadWidth(spec) => {
+                write!(f, "bad format `{spec}`, expected something like `02`")
+            }
+            TemplateErr::Unclosed => write!(f, "unclosed `{{`, use `{{{{` for a literal `{{`"),
+            TemplateErr::Unopened => write!(f, "unopened `}}`, use `}}}}` for a literal `}}`"),
+            TemplateErr::Empty => write!(f, "template is empty"),
+        }
+    }
+}
+
+impl Error for TemplateErr {}
+
+/// A field which can be used as a placeholder in a template.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Field {
+    Artist,
+    AlbumArtist,
+    TrackArtist,
+    Composer,
+    Album,
+    Year,
+    Genre,
+    Disc,
+    DiscTotal,
+    DiscSubtitle,
+    MediaType,
+    Track,
+    TrackTotal,
+    Title,
+}
+
+impl Field {
+    const ALL: [Field; 14] = [
+        Field::Artist,
+        Field::AlbumArtist,
+        Field::TrackArtist,
+        Field::Composer,
+        Field::Album,
+        Field::Year,
+        Field::Genre,
+        Field::Disc,
+        Field::DiscTotal,
+        Field::DiscSubtitle,
+        Field::MediaType,
+        Field::Track,
+        Field::TrackTotal,
+        Field::Title,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Field::Artist => "artist",
+            Field::AlbumArtist => "albumartist",
+            Field::TrackArtist => "trackartist",
+            Field::Composer => "composer",
+            Field::Album => "album",
+            Field::Year => "year",
+            Field::Genre => "genre",
+            Field::Disc => "disc",
+            Field::DiscTotal => "disctotal",
+            Field::DiscSubtitle => "discsubtitle",
+            Field::MediaType => "mediatype",
+            Field::Track => "track",
+            Field::TrackTotal => "tracktotal",
+            Field::Title => "title",
+        }
+    }
+}
+
+/// The value of a field when rendering a template.
+pub(crate) enum Value<'a> {
+    Text(&'a str),
+    Number(u32),
+    None,
+}
+
+#[derive(Clone, Debug)]
+enum Piece {
+    Literal(String),
+    Field { field: Field, width: usize },
+}
+
+/// A template for output paths, like `{artist}/{album} ({year})/{track:02} -
+/// {title}`.
+///
+/// Each `/` separated component of the template becomes one component of the
+/// output path.
+#[derive(Clone, Debug)]
+pub(crate) struct Template {
+    components: Vec<Vec<Piece>>,
+}
+
+impl Template {
+    /// Render the template into path components using the given field
+    /// values.
+    ///
+    /// Components which are empty after rendering, like `{genre}` for a file
+    /// without a genre, are left out.
+    pub(crate) fn render<'a>(&self, mut value: impl FnMut(Field) -> Value<'a>) -> Vec<String> {
+        use core::fmt::Write;
+
+        let mut out = Vec::new();
+
+        for pieces in &self.components {
+            let mut s = String::new();
+
+            for piece in pieces {
+                match piece {
+                    Piece::Literal(literal) => {
+                        s.push_str(literal);
+                    }
+                    Piece::Field { field, width } => match value(*field) {
+                        Value::Text(text) => {
+                            s.push_str(text);
+                        }
+                        Value::Number(n) => {
+                            _ = write!(s, "{n:0width$}");
+                        }
+                        Value::None => {}
+                    },
+                }
+            }
+
+            let s = s.trim();
+
+            if !s.is_empty() {
+                out.push(s.to_owned());
+            }
+        }
+
+        out
+    }
+}
+
+impl FromStr for Template {
+    type Err = TemplateErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut components = Vec::new();
+
+        for component in s.split('/') {
+            let mut pieces = Vec::new();
+            let mut literal = String::new();
+            let mut it = component.chars();
+
+            while let Some(c) = it.next() {
+                match c {
+                    '{' if it.as_str().starts_with('{') => {
+                        it.next();
+                        literal.push('{');
+                    }
+                    '}' if it.as_str().starts_with('}') => {
+                        it.next();
+                        literal.push('}');
+                    }
+                    '{' => {
+                        let rest = it.as_str();
+                        let end = rest.find('}').ok_or(TemplateErr::Unclosed)?;
+                        let (name, spec) = match rest[..end].split_once(':') {
+                            Some((name, spec)) => (name, Some(spec)),
+                            None => (&rest[..end], None),
+                        };
+
+                        let field = Field::ALL
+                            .into_iter()
+                            .find(|f| f.name() == name)
+                            .ok_or_else(|| TemplateErr::UnknownField(name.to_owned()))?;
+
+                        let width = match spec {
+                            Some(spec) => spec
+                                .parse()
+                                .map_err(|_| TemplateErr::BadWidth(spec.to_owned()))?,
+                            None => 0,
+                        };
+
+                        if !literal.is_empty() {
+                            pieces.push(Piece::Literal(literal.clone()));
+                            literal.clear();
+                        }
+
+                        pieces.push(Piece::Field { field, width });
+                        it = rest[end + 1..].chars();
+                    }
+                    '}' => {
+                        return Err(TemplateErr::Unopened);
+                    }
+                    c => {
+                        literal.push(c);
+                    }
+                }
+            }
+
+            if !literal.is_empty() {
+                pieces.push(Piece::Literal(literal));
+            }
+
+            if !pieces.is_empty() {
+                components.push(pieces);
+            }
+        }
+
+        if components.is_empty() {
+            return Err(TemplateErr::Empty);
+        }
+
+        Ok(Self { components })
+    }
+}
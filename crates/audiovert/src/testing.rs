@@ -0,0 +1,136 @@
+//! Helpers to build small audio files and configurations in tests.
+
+use clap::Parser;
+use lofty::mp4::Mp4Codec;
+
+use crate::cli::{self, Audiovert};
+use crate::config::Config;
+use crate::settings;
+
+/// Build a configuration from the given command line arguments.
+pub(crate) fn config(args: &[&str]) -> Config {
+    let opts = Audiovert::parse_from(["audiovert", "--trash", "trash"].iter().chain(args));
+    cli::config(&opts, &settings::File::default()).expect("valid configuration")
+}
+
+/// Build an mp4 atom with the given identifier and contents.
+fn atom(ident: &[u8; 4], contents: &[&[u8]]) -> Vec<u8> {
+    let len = 8 + contents.iter().map(|c| c.len()).sum::<usize>();
+    let mut out = Vec::with_capacity(len);
+    out.extend_from_slice(&u32::try_from(len).unwrap().to_be_bytes());
+    out.extend_from_slice(ident);
+
+    for c in contents {
+        out.extend_from_slice(c);
+    }
+
+    out
+}
+
+/// A minimal mp4 file with one second of audio in a single track using the
+/// given codec, which is either ALAC or AAC.
+pub(crate) fn mp4(codec: Mp4Codec) -> Vec<u8> {
+    const SAMPLE_RATE: u32 = 44100;
+
+    let ftyp = atom(b"ftyp", &[b"M4A ", &[0; 4], b"M4A mp42isom"]);
+
+    let mut mvhd = vec![0; 100];
+    mvhd[12..16].copy_from_slice(&SAMPLE_RATE.to_be_bytes());
+    mvhd[16..20].copy_from_slice(&SAMPLE_RATE.to_be_bytes());
+    mvhd[20..24].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+    mvhd[96..100].copy_from_slice(&2u32.to_be_bytes());
+    let mvhd = atom(b"mvhd", &[&mvhd]);
+
+    let mut mdhd = vec![0; 24];
+    mdhd[12..16].copy_from_slice(&SAMPLE_RATE.to_be_bytes());
+    mdhd[16..20].copy_from_slice(&SAMPLE_RATE.to_be_bytes());
+    let mdhd = atom(b"mdhd", &[&mdhd]);
+
+    let hdlr = atom(b"hdlr", &[&[0; 8], b"soun", &[0; 13]]);
+
+    // The audio sample entry shared by both codecs, with reserved fields,
+    // the data reference index, two channels, 16 bit samples and the sample
+    // rate as a 16.16 fixed point number.
+    let mut audio = vec![0; 28];
+    audio[7] = 1;
+    audio[17] = 2;
+    audio[19] = 16;
+    audio[24..28].copy_from_slice(&(SAMPLE_RATE << 16).to_be_bytes());
+
+    let entry = match codec {
+        Mp4Codec::ALAC => {
+            let mut config = vec![0; 28];
+            config[4..8].copy_from_slice(&4096u32.to_be_bytes());
+            config[9] = 16;
+            config[10] = 40;
+            config[11] = 10;
+            config[12] = 14;
+            config[13] = 2;
+            config[14..16].copy_from_slice(&255u16.to_be_bytes());
+            config[20..24].copy_from_slice(&1_411_200u32.to_be_bytes());
+            config[24..28].copy_from_slice(&SAMPLE_RATE.to_be_bytes());
+            atom(b"alac", &[&audio, &atom(b"alac", &[&config])])
+        }
+        Mp4Codec::AAC => atom(b"mp4a", &[&audio]),
+        codec => panic!("unsupported codec {codec:?}"),
+    };
+
+    let stsd = atom(b"stsd", &[&[0; 4], &1u32.to_be_bytes(), &entry]);
+    let stts = atom(
+        b"stts",
+        &[
+            &[0; 4],
+            &1u32.to_be_bytes(),
+            &1u32.to_be_bytes(),
+            &SAMPLE_RATE.to_be_bytes(),
+        ],
+    );
+    let stbl = atom(b"stbl", &[&stsd, &stts]);
+    let minf = atom(b"minf", &[&stbl]);
+    let mdia = atom(b"mdia", &[&mdhd, &hdlr, &minf]);
+    let trak = atom(b"trak", &[&mdia]);
+    let moov = atom(b"moov", &[&mvhd, &trak]);
+    let mdat = atom(b"mdat", &[&[0; 1024]]);
+
+    [ftyp, moov, mdat].concat()
+}
+
+/// A minimal flac file with one second of silence described by its stream
+/// info, but without any audio frames.
+pub(crate) fn flac() -> Vec<u8> {
+    const SAMPLE_RATE: u64 = 44100;
+
+    let mut info = Vec::with_capacity(34);
+    info.extend_from_slice(&4096u16.to_be_bytes());
+    info.extend_from_slice(&4096u16.to_be_bytes());
+    info.extend_from_slice(&[0; 6]);
+
+    // Sample rate (20 bits), channels - 1 (3 bits), bits per sample - 1 (5
+    // bits) and the total number of samples (36 bits).
+    let packed = SAMPLE_RATE << 44 | 1 << 41 | 15 << 36 | SAMPLE_RATE;
+    info.extend_from_slice(&packed.to_be_bytes());
+    info.extend_from_slice(&[0; 16]);
+
+    let mut out = b"fLaC".to_vec();
+    // A STREAMINFO block, followed by the last block which is PADDING since
+    // lofty expects the stream info not to be the last block.
+    out.push(0x00);
+    out.extend_from_slice(&u32::try_from(info.len()).unwrap().to_be_bytes()[1..]);
+    out.extend_from_slice(&info);
+    out.extend_from_slice(&[0x81, 0, 0, 16]);
+    out.extend_from_slice(&[0; 16]);
+    out.extend_from_slice(&[0; 1024]);
+    out
+}
+
+/// A minimal mp3 file made up of silent MPEG-1 Layer III frames.
+pub(crate) fn mp3() -> Vec<u8> {
+    // 128 kbit/s at 44.1 kHz in joint stereo without padding, which makes
+    // every frame 144 * 128000 / 44100 = 417 bytes long.
+    const HEADER: [u8; 4] = [0xff, 0xfb, 0x90, 0x44];
+    const FRAME: usize = 417;
+
+    let mut frame = vec![0; FRAME];
+    frame[..4].copy_from_slice(&HEADER);
+    frame.repeat(20)
+}
@@ -0,0 +1,107 @@
+use core::fmt::Write;
+
+/// Description of a key binding.
+pub(crate) struct Binding {
+    /// The keys which trigger the binding.
+    pub(crate) keys: &'static str,
+    /// What the binding does.
+    pub(crate) action: &'static str,
+    /// Whether the binding is shown as a hint in the title line.
+    pub(crate) hint: bool,
+}
+
+const fn hint(keys: &'static str, action: &'static str) -> Binding {
+    Binding {
+        keys,
+        action,
+        hint: true,
+    }
+}
+
+const fn key(keys: &'static str, action: &'static str) -> Binding {
+    Binding {
+        keys,
+        action,
+        hint: false,
+    }
+}
+
+/// Bindings for the catalogs view.
+pub(crate) const CATALOGS: &[Binding] = &[
+    key("↑/k ↓/j", "move"),
+    key("gg/G", "jump to top/bottom"),
+    key("PgUp/PgDn", "move a page"),
+    key("Ctrl-u/Ctrl-d", "move half a page"),
+    hint("Enter/o/→", "select"),
+    key("Tab", "next unpicked"),
+    hint("a/A", "pick largest"),
+    hint("Delete", "clear"),
+    hint("c", "clear all"),
+    hint("/", "search"),
+    hint("f", "toggle unpicked only"),
+    hint("e", "show problems"),
+    hint("0-9", "jump"),
+    key("u", "undo last pick"),
+    key("w", "toggle wrap-around"),
+    key("x", "execute"),
+    hint("?", "help"),
+    hint("Esc/q", "quit"),
+];
+
+/// Bindings for the books view.
+pub(crate) const BOOKS: &[Binding] = &[
+    key("↑/k ↓/j", "move"),
+    key("gg/G", "jump to top/bottom"),
+    key("PgUp/PgDn", "move a page"),
+    key("Ctrl-u/Ctrl-d", "move half a page"),
+    hint("Enter/o", "pick"),
+    key("Ctrl-o", "open directory"),
+    hint("/", "search"),
+    hint("s", "sort"),
+    key("p", "toggle relative paths"),
+    key("u", "undo last pick"),
+    key("w", "toggle wrap-around"),
+    hint("?", "help"),
+    hint("Esc/q/←", "go back"),
+];
+
+/// Bindings for the name view.
+pub(crate) const NAME: &[Binding] = &[
+    key("↑/k ↓/j", "move"),
+    key("w", "toggle wrap-around"),
+    hint("Enter", "select"),
+    hint("Esc/q/←", "go back"),
+];
+
+/// Bindings for the confirmation view.
+pub(crate) const CONFIRM: &[Binding] = &[
+    key("←/h →/l", "choose"),
+    key("Enter/o", "confirm choice"),
+    key("y", "continue"),
+    key("Esc/q/n", "go back"),
+];
+
+/// Bindings for the problems view.
+pub(crate) const PROBLEMS: &[Binding] = &[
+    key("↑/k ↓/j", "scroll"),
+    key("PgUp/PgDn", "scroll a page"),
+    key("Ctrl-u/Ctrl-d", "scroll half a page"),
+    hint("?", "help"),
+    hint("Esc/q/e", "go back"),
+];
+
+/// Format the hints of the given bindings for use in a title line.
+pub(crate) fn hints(bindings: &[Binding]) -> String {
+    let mut s = String::new();
+
+    for (n, b) in bindings.iter().filter(|b| b.hint).enumerate() {
+        s.push_str(if n == 0 { " (" } else { ", " });
+        _ = write!(s, "{} to {}", b.keys, b.action);
+    }
+
+    if !s.is_empty() {
+        s.push(')');
+    }
+
+    s
+}
@@ -3,29 +3,58 @@ use core::iter;
 use core::str::FromStr;
 
 use std::borrow::Cow;
-use std::collections::BTreeMap;
-use std::fs;
-use std::io::{Cursor, Write as _};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Cursor, IsTerminal, Write as _};
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
-use std::rc::Rc;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
 
 use anyhow::{Context, Result, anyhow};
 use clap::Parser;
-use ignore::Walk;
+use ignore::WalkBuilder;
 use language_tags::LanguageTag;
+use mediavert_common::archive::{is_tar_gz, stem as archive_stem};
+use mediavert_common::filter::{Filter, Pattern};
+use mediavert_common::glob::Glob;
 use regex::Regex;
-use termcolor::{ColorSpec, StandardStream, WriteColor};
+use termcolor::{BufferWriter, ColorSpec, StandardStream, WriteColor};
 use zip::write::SimpleFileOptions;
-use zip::{CompressionMethod, ZipWriter};
-
-use crate::{App, Book, Catalog, Page, State};
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::epub;
+use crate::format::Format;
+use crate::layout::{Layout, Parts};
+use crate::natural;
+use crate::pdf;
+use crate::picks::{self, Picks};
+use crate::plan::Plan;
+use crate::settings;
+use crate::state;
+use crate::theme::{self, Theme};
+use crate::transform::{Content, Grayscale, Report, Resized, Split, Transform};
+use crate::{App, Book, Catalog, Extra, Page, Source, State};
 
 /// A tool to perform batch conversion of books.
 #[derive(Parser)]
 pub struct Bookvert {
     /// Output directory to write to.
+    ///
+    /// Archives in this directory named like the books written to it, such as
+    /// `{name}001.cbz`, aren't picked up as books when scanning.
     #[arg(long, default_value = ".")]
     out: PathBuf,
+    /// The output format to produce: `cbz`, `pdf`, `epub`, or `auto` to pick
+    /// between `pdf` and `cbz` for each book based on `--pdf-max-pages`.
+    /// Defaults to `cbz`.
+    #[arg(long)]
+    output: Option<Output>,
+    /// With `--output auto`, books with at most this many pages are written as
+    /// `pdf`, and larger books as `cbz`.
+    #[arg(long, default_value_t = 50)]
+    pdf_max_pages: usize,
     /// Rename output files to this name. This is necessary if we are converting
     /// a series. Otherwise the directory name will be used.
     #[arg(long)]
@@ -45,15 +74,116 @@ pub struct Bookvert {
     /// - `-p fix' will match *any* book that contains the string `fix`.
     #[arg(long, short = 'p', verbatim_doc_comment)]
     pick: Vec<String>,
+    /// Pick the book with the most pages for catalogs which are not picked
+    /// by `--pick`, with ties broken by the largest total size.
+    #[arg(long)]
+    auto: bool,
+    /// Print the plan of which book to convert for each catalog as JSON and
+    /// exit without converting anything.
+    #[arg(long)]
+    export_plan: bool,
+    /// Pick books according to a plan previously printed with
+    /// `--export-plan`, matching catalogs by number and books by name.
+    ///
+    /// If standard output is not a terminal, books are converted without
+    /// opening the interactive interface.
+    #[arg(long, value_name = "path")]
+    plan: Option<PathBuf>,
+    /// The number of books to convert in parallel. Output of each book is
+    /// printed once it's done. Defaults to the number of logical CPUs.
+    #[arg(short = 'j', long, value_name = "n")]
+    jobs: Option<NonZeroUsize>,
     /// Overwrite existing files.
     #[arg(long, short = 'f')]
     force: bool,
+    /// Skip books whose output already exists and is newer than every page,
+    /// while books whose output is older than any of their pages are
+    /// converted again. This is overridden by `--force`.
+    #[arg(long)]
+    skip_existing: bool,
     /// Non-interactive mode: errors out if a choice is required.
     #[arg(long, short = 'n')]
     noninteractive: bool,
     /// Verbose output.
     #[arg(long, short = 'v')]
     verbose: bool,
+    /// Don't restore picks made in a previous interactive session, or save the
+    /// picks made in this one.
+    #[arg(long)]
+    no_persist_picks: bool,
+    /// Path to a TOML file overriding the colors of the interactive interface.
+    ///
+    /// Defaults to `bookvert/theme.toml` in the user configuration directory,
+    /// if it exists.
+    #[arg(long, value_name = "path")]
+    theme: Option<PathBuf>,
+    /// Path to a TOML configuration file with defaults for `output`, `jobs`
+    /// and `theme`, which are overridden by the corresponding options. A
+    /// relative `theme` is relative to the configuration file.
+    ///
+    /// Defaults to `bookvert/config.toml` in the user configuration
+    /// directory, if it exists.
+    #[arg(long, value_name = "path")]
+    config: Option<PathBuf>,
+    /// Wrap around when moving past the first or last item of a list in the
+    /// interactive interface. This can be toggled with `w`.
+    #[arg(long)]
+    wrap: bool,
+    /// Recompress page images to lossy WebP before packaging them. Pages which
+    /// would grow are kept as they are. PDF can't embed WebP images, so they
+    /// are decoded again for `pdf` output which gains nothing from this.
+    #[arg(long)]
+    webp: bool,
+    /// The quality from 0 to 100 to use with `--webp`.
+    #[arg(long, default_value_t = 80.0, requires = "webp")]
+    webp_quality: f32,
+    /// Embed pages in `pdf` output as JPEGs of at most this quality from 1 to
+    /// 100 to control the size of the output. JPEGs which are already of a
+    /// lower quality are embedded as they are. The size of the output is
+    /// reported against the size of the pages.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u8).range(1..=100))]
+    jpeg_quality: Option<u8>,
+    /// Losslessly recompress PNG pages with stronger compression and the
+    /// smallest color type which holds their pixels. Pages which wouldn't
+    /// shrink are kept as they are.
+    #[arg(long)]
+    optimize_png: bool,
+    /// Downscale pages whose longest side exceeds this number of pixels,
+    /// preserving their aspect ratio. Smaller pages are left untouched.
+    /// Resized pages are listed, also with `--dry-run`.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    max_dimension: Option<u32>,
+    /// Convert color pages to grayscale, which reduces the size of black and
+    /// white comics. With `auto` pages which are substantially colored, like
+    /// covers, are kept in color, while `always` converts every page.
+    #[arg(long, value_name = "mode", num_args = 0..=1, default_missing_value = "auto")]
+    grayscale: Option<Grayscale>,
+    /// Split double-page spreads into two pages, which is easier to read on
+    /// small screens. The halves are ordered according to `--rtl`.
+    #[arg(long)]
+    split_spreads: bool,
+    /// The ratio of width to height above which a page is considered a spread
+    /// by `--split-spreads`.
+    #[arg(
+        long,
+        value_name = "ratio",
+        default_value_t = 1.0,
+        requires = "split_spreads"
+    )]
+    split_ratio: f32,
+    /// Don't split the cover with `--split-spreads`, which is commonly a
+    /// wraparound of the front and back cover.
+    #[arg(long, requires = "split_spreads")]
+    split_skip_cover: bool,
+    /// Trim uniform white or black margins from pages, which is common on
+    /// scanned pages. Pages where almost everything would be trimmed are left
+    /// as they are.
+    #[arg(long)]
+    trim: bool,
+    /// How much the brightness of a pixel from 0 to 255 may differ from the
+    /// margin to be trimmed by `--trim`.
+    #[arg(long, value_name = "N", default_value_t = 24, requires = "trim")]
+    trim_tolerance: u8,
     /// Perform a trial run with no changes made.
     #[arg(long)]
     dry_run: bool,
@@ -63,6 +193,64 @@ pub struct Bookvert {
     /// Only include series numbers matching these predicates.
     #[arg(long)]
     include: Vec<From>,
+    /// Detect books which appear in more than one catalog, by comparing their
+    /// name, page count and total size.
+    #[arg(long)]
+    dedupe_across_catalogs: bool,
+    /// Skip books which are duplicates of a book already converted for an
+    /// earlier catalog. Implies `--dedupe-across-catalogs`.
+    #[arg(long)]
+    skip_duplicates: bool,
+    /// Remove pages which are byte-for-byte identical to the page before them.
+    /// Such pages are always reported, but only removed with this option.
+    #[arg(long)]
+    dedup: bool,
+    /// A regular expression matching the file name of the front cover, without
+    /// its extension. The first matching page is moved to the front of the
+    /// book, and is used as the cover of `epub` output. If no page matches,
+    /// the first page is used.
+    #[arg(long, value_name = "regex", default_value = r"(?i)\bcover\b|^0+$")]
+    cover: String,
+    /// Follow symlinked directories when scanning for books. Books which are
+    /// reachable through more than one path are only included once.
+    #[arg(long)]
+    follow_catalog_symlinks: bool,
+    /// Only include files matching any of these patterns, which are matched
+    /// against the path of a file relative to the directory it was found in.
+    ///
+    /// Patterns prefixed with `re:` are regular expressions, like
+    /// `re:(?i)chapter`, which are searched for anywhere in the path. Other
+    /// patterns are globs. Globs without a `/`, like `*.png` or `Extras`,
+    /// match any component of the path, while globs with a `/`, like
+    /// `Series/Chapter 1`, match the start of it. Archives are books of their
+    /// own, so they're only matched against `--exclude-path`.
+    #[arg(long, value_name = "pattern")]
+    include_path: Vec<Pattern>,
+    /// Leave out files matching any of these patterns, even if they match
+    /// `--include-path`. Patterns are written like for `--include-path`.
+    #[arg(long, value_name = "pattern")]
+    exclude_path: Vec<Pattern>,
+    /// Derive metadata from the directories books are stored in, using a
+    /// pattern like `{author}/{series}/{volume}` which is matched against the
+    /// end of the path of each book. The last component is the book itself,
+    /// and components which are not of interest are matched with `*`.
+    ///
+    /// The volume is used as the title of `pdf` and `epub` output, while the
+    /// author and series are used unless `--author` or `--series` are given.
+    #[arg(long, value_name = "pattern")]
+    path_layout: Option<Layout>,
+    /// Keep the original file names of pages in `cbz` output, instead of
+    /// naming them sequentially like `001.jpg`. Pages which were converted to
+    /// another format get the extension of that format.
+    #[arg(long)]
+    keep_page_names: bool,
+    /// Include `.nfo` and `.txt` files stored with a book in `cbz` output.
+    /// Other files which aren't pages are only reported.
+    #[arg(long)]
+    bundle_extras: bool,
+    /// Don't include ComicInfo.xml in `cbz` output.
+    #[arg(long)]
+    no_comic_info: bool,
     /// Series for ComicInfo.xml metadata.
     #[arg(long)]
     series: Option<String>,
@@ -84,10 +272,29 @@ pub struct Bookvert {
     /// Manga reading direction: "Yes", "No", or "YesAndRightToLeft".
     #[arg(long)]
     manga: Option<Manga>,
+    /// Mark books as read from right to left, like manga, without reordering
+    /// their pages. This sets `Manga` to `YesAndRightToLeft` in ComicInfo.xml,
+    /// the page progression direction of `epub` output and the reading
+    /// direction of `pdf` output.
+    #[arg(long, conflicts_with = "manga")]
+    rtl: bool,
     /// Summary/description for ComicInfo.xml metadata.
     #[arg(long)]
     summary: Option<String>,
     /// Directories to convert.
+    ///
+    /// Paths can be glob patterns like `'comics/**/*.jpg'`, which select the
+    /// matching files in the directory before the first pattern. Quote them to
+    /// keep the shell from expanding them. `*` doesn't match across
+    /// directories while `**` does, and archives are selected by their own
+    /// name rather than the files in them. Matching files are still grouped
+    /// into books by the directory they're in, so a glob which matches files
+    /// in several directories selects pages from several books rather than
+    /// making one book out of them.
+    ///
+    /// Files found through several paths are only included once. Explicit
+    /// paths take precedence over globs, so that paths are displayed relative
+    /// to the explicit path.
     path: Vec<PathBuf>,
 }
 
@@ -123,6 +330,35 @@ impl fmt::Display for Manga {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+enum Output {
+    Auto,
+    Format(Format),
+}
+
+impl Output {
+    /// Select the format to use for the given book.
+    fn select(&self, book: &Book, pdf_max_pages: usize) -> Format {
+        match *self {
+            Output::Auto if book.pages.len() <= pdf_max_pages => Format::Pdf,
+            Output::Auto => Format::Cbz,
+            Output::Format(format) => format,
+        }
+    }
+}
+
+impl FromStr for Output {
+    type Err = anyhow::Error;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(Output::Auto),
+            s => Ok(Output::Format(s.parse()?)),
+        }
+    }
+}
+
 enum To {
     First,
     Last,
@@ -135,7 +371,7 @@ enum To {
 
 impl To {
     /// Picks a book from the list according to the strategy.
-    fn pick(&self, books: &[Rc<Book>]) -> Option<usize> {
+    fn pick(&self, books: &[Arc<Book>]) -> Option<usize> {
         match *self {
             To::First if !books.is_empty() => Some(0),
             To::Last => books.len().checked_sub(1),
@@ -347,7 +583,17 @@ fn translate(input: &str) -> &str {
     input
 }
 
+/// Get the translated and lowercased extension of a path.
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(translate)
+        .map(|e| e.to_lowercase())
+}
+
 pub fn entry(opts: &Bookvert) -> Result<()> {
+    let settings = Settings::resolve(opts)?;
+
     let mut warn: ColorSpec = ColorSpec::new();
     warn.set_fg(Some(termcolor::Color::Yellow));
 
@@ -371,10 +617,69 @@ pub fn entry(opts: &Bookvert) -> Result<()> {
         skip.push(re);
     }
 
+    let cover =
+        Regex::new(&opts.cover).with_context(|| anyhow!("Parsing cover regex '{}'", opts.cover))?;
+
+    if !(0.0..=100.0).contains(&opts.webp_quality) {
+        return Err(anyhow!(
+            "WebP quality must be between 0 and 100, got {}",
+            opts.webp_quality
+        ));
+    }
+
+    if opts.split_ratio.is_nan() || opts.split_ratio <= 0.0 {
+        return Err(anyhow!(
+            "Spread ratio must be greater than 0, got {}",
+            opts.split_ratio
+        ));
+    }
+
+    let transform = Transform {
+        webp: opts.webp.then_some(opts.webp_quality),
+        max_dimension: opts.max_dimension,
+        grayscale: opts.grayscale,
+        split: opts.split_spreads.then_some(Split {
+            ratio: opts.split_ratio,
+            rtl: opts.rtl,
+            skip_cover: opts.split_skip_cover,
+        }),
+        trim: opts.trim.then_some(opts.trim_tolerance),
+        optimize_png: opts.optimize_png,
+    };
+
     let mut files = Vec::new();
+    let mut archives = Vec::new();
+    let mut extras = Vec::new();
+    let mut seen = HashSet::new();
+    let mut filtered = 0usize;
+    let mut earlier_output = 0usize;
+
+    // Books written to the output directory by an earlier run are archives,
+    // which would otherwise be picked up as books of their own.
+    let out = opts.out.canonicalize().ok();
+
+    let filter = Filter {
+        include: opts.include_path.clone(),
+        exclude: opts.exclude_path.clone(),
+    };
+
+    let mut inputs = Vec::with_capacity(opts.path.len());
 
     for path in &opts.path {
-        for p in Walk::new(path) {
+        let glob = Glob::parse(path).with_context(|| format!("Invalid glob {}", path.display()))?;
+        inputs.push((path.as_path(), glob));
+    }
+
+    // Explicit paths take precedence over globs, since files matched by both
+    // are only included through the first one.
+    inputs.sort_by_key(|(_, glob)| glob.is_some());
+
+    for (path, glob) in &inputs {
+        let root = glob.as_ref().map_or(*path, Glob::base);
+        let mut walk = WalkBuilder::new(root);
+        walk.follow_links(opts.follow_catalog_symlinks);
+
+        for p in walk.build() {
             let entry = p?;
 
             let Some(ty) = entry.file_type() else {
@@ -384,33 +689,93 @@ pub fn entry(opts: &Bookvert) -> Result<()> {
             if ty.is_file() {
                 let path = entry.into_path();
 
-                let ext = path
-                    .extension()
-                    .and_then(|e| e.to_str())
-                    .map(translate)
-                    .map(|e| e.to_lowercase());
-
-                let Some(ext) = ext else {
+                if glob.as_ref().is_some_and(|glob| !glob.is_match(&path)) {
                     continue;
+                }
+
+                let ext = extension(&path);
+                let archive = archive_kind(&path, ext.as_deref());
+
+                let relative = path.strip_prefix(root).unwrap_or(&path);
+
+                let included = if archive.is_some() {
+                    !filter.is_excluded(relative)
+                } else {
+                    filter.is_included(relative)
                 };
 
-                if !matches!(ext.as_str(), ext!()) {
+                if !included {
+                    filtered += 1;
                     continue;
                 }
 
-                files.push((path, ext));
+                if archive.is_some() && is_output(&path, out.as_deref(), opts.name.as_deref()) {
+                    earlier_output += 1;
+                    continue;
+                }
+
+                // Files which resolve to the same real path, such as through
+                // symlinked directories or overlapping arguments, are only
+                // included once.
+                let real = path
+                    .canonicalize()
+                    .with_context(|| anyhow!("{}: Failed to canonicalize", path.display()))?;
+
+                if !seen.insert(real) {
+                    continue;
+                }
+
+                match ext {
+                    _ if let Some(kind) = archive => archives.push((path, kind)),
+                    Some(ext) if matches!(ext.as_str(), ext!()) => files.push((path, ext)),
+                    // Other files are reported if they're stored with a book.
+                    _ => extras.push(path),
+                }
             }
         }
     }
 
-    files.sort();
+    // Pages are numbered in the order they are found, so this uses a natural
+    // order where `page2.jpg` comes before `page10.jpg`.
+    files.sort_by(|(a, _), (b, _)| natural::compare_paths(a, b));
 
     let o = StandardStream::stdout(termcolor::ColorChoice::Auto);
     let mut o = o.lock();
 
+    // Notices go to stderr, since stdout is used by `--export-plan`.
+    let e = StandardStream::stderr(termcolor::ColorChoice::Auto);
+    let mut e = e.lock();
+
+    if earlier_output > 0 {
+        e.set_color(&warn)?;
+        write!(e, "[skipped] ")?;
+        e.reset()?;
+        writeln!(
+            e,
+            "{earlier_output} archives in --out which were written by an earlier run"
+        )?;
+    }
+
+    if filtered > 0 {
+        e.set_color(&ok)?;
+        write!(e, "[filtered] ")?;
+        e.reset()?;
+        writeln!(
+            e,
+            "{filtered} files left out by --include-path and --exclude-path"
+        )?;
+    }
+
     let mut books_by_path = BTreeMap::<&Path, _>::new();
     let mut by_number = BTreeMap::<_, Vec<_>>::new();
-    let mut state = State::default();
+    let mut state = State {
+        dedupe: opts.dedupe_across_catalogs || opts.skip_duplicates,
+        roots: inputs
+            .iter()
+            .map(|(path, glob)| glob.as_ref().map_or(*path, Glob::base).to_path_buf())
+            .collect(),
+        ..State::default()
+    };
 
     for (from, ext) in &files {
         let Some(dir) = from.parent() else {
@@ -430,21 +795,113 @@ pub fn entry(opts: &Bookvert) -> Result<()> {
             name: name.to_string(),
             pages: Vec::new(),
             numbers: numbers(name).collect(),
+            duplicates: Vec::new(),
+            cover: None,
+            extras: Vec::new(),
         });
 
+        let metadata = fs::metadata(from)
+            .with_context(|| anyhow!("{}: Failed to get metadata", from.display()))?;
+
         book.pages.push(Page {
+            source: Source::File,
             path: from.to_owned(),
             name: format!("p{:03}.{ext}", book.pages.len()),
-            metadata: fs::metadata(from)
-                .with_context(|| anyhow!("{}: Failed to get metadata", from.display()))?,
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+            number: from
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| numbers(s).last()),
+            dimensions: None,
         });
     }
 
-    for (_, book) in books_by_path {
-        let book = Rc::new(book);
+    for path in extras {
+        if let Some(book) = path.parent().and_then(|dir| books_by_path.get_mut(dir)) {
+            book.extras.push(Extra {
+                source: Source::File,
+                path,
+            });
+        }
+    }
+
+    for (path, kind) in &archives {
+        let Some(name) = archive_stem(path).and_then(|n| n.to_str()) else {
+            continue;
+        };
+
+        if skip.iter().any(|re| re.is_match(name)) {
+            continue;
+        }
+
+        let book = match archive_book(path, name, *kind) {
+            Ok(book) => book,
+            Err(e) => {
+                state.problem(path, format!("Failed to read archive: {e:#}"));
+                continue;
+            }
+        };
+
+        if book.pages.is_empty() {
+            state.problem(path, "No images in archive, book is ignored");
+            continue;
+        }
+
+        books_by_path.insert(path, book);
+    }
+
+    for (_, mut book) in books_by_path {
+        // Pages which aren't readable images are reported when they're
+        // packaged.
+        for page in &mut book.pages {
+            page.dimensions = page.read_dimensions().ok();
+        }
+
+        // Duplicates found before a page failed to read are still reported.
+        if let Err(e) = book.find_duplicates() {
+            state.problem(
+                &book.dir,
+                format!("Failed to check for duplicate pages: {e:#}"),
+            );
+        }
+
+        if !book.duplicates.is_empty() {
+            state.problem(
+                &book.dir,
+                format!(
+                    "Duplicate pages {}",
+                    file_names(&book.duplicates).join(", ")
+                ),
+            );
+        }
+
+        if opts.dedup {
+            book.remove_duplicates();
+        }
+
+        book.find_cover(&cover);
+
+        let book = Arc::new(book);
 
         state.names.insert(book.name.clone());
 
+        let missing = book.missing_pages();
+
+        if !missing.is_empty() {
+            state.problem(
+                &book.dir,
+                format!("Missing pages {}", state::format_ranges(&missing)),
+            );
+        }
+
+        if book.numbers.is_empty() {
+            state.problem(
+                &book.dir,
+                "No catalog number in directory name, book is ignored",
+            );
+        }
+
         for &n in &book.numbers {
             by_number.entry(n).or_default().push(book.clone());
         }
@@ -475,6 +932,10 @@ pub fn entry(opts: &Bookvert) -> Result<()> {
             catalog.picked = picker.pick(&catalog);
         }
 
+        if opts.auto && catalog.picked.is_none() {
+            catalog.picked = catalog.largest();
+        }
+
         state.catalogs.push(catalog);
     }
 
@@ -495,7 +956,32 @@ pub fn entry(opts: &Bookvert) -> Result<()> {
         }
     }
 
-    if opts.noninteractive {
+    if let Some(path) = &opts.plan {
+        let plan = Plan::load(path)?;
+
+        for (number, book) in plan.apply(&mut state) {
+            e.set_color(&warn)?;
+            write!(e, "[warning] ")?;
+            e.reset()?;
+            writeln!(
+                e,
+                "{}: No book named {} in catalog {number:03}, skipping",
+                path.display(),
+                escape(book)
+            )?;
+        }
+    }
+
+    let noninteractive =
+        opts.noninteractive || (opts.plan.is_some() && !io::stdout().is_terminal());
+
+    if opts.export_plan {
+        serde_json::to_writer_pretty(&mut o, &Plan::new(&state))?;
+        writeln!(o)?;
+        return Ok(());
+    }
+
+    if noninteractive {
         let mut is_error = false;
 
         if state.name.is_none() {
@@ -551,98 +1037,697 @@ pub fn entry(opts: &Bookvert) -> Result<()> {
             return Err(anyhow!("Aborting due to non-interactive errors."));
         }
     } else {
-        let mut app = App::default();
+        let persist = if opts.no_persist_picks {
+            None
+        } else {
+            // Picks are keyed by the directories which were walked, since
+            // input paths might be globs.
+            let paths = state
+                .roots
+                .iter()
+                .map(|root| {
+                    root.canonicalize()
+                        .with_context(|| anyhow!("{}: Not persisting picks", root.display()))
+                })
+                .collect::<Result<Vec<_>>>();
+
+            match paths {
+                Ok(paths) => picks::default_path().map(|path| (path, paths)),
+                Err(e) => {
+                    o.set_color(&warn)?;
+                    write!(o, "[warning] ")?;
+                    o.reset()?;
+                    writeln!(o, "{e:#}")?;
+                    None
+                }
+            }
+        };
+
+        if let Some((path, paths)) = &persist {
+            match Picks::load(path) {
+                Ok(Some(picks)) => {
+                    picks.apply(paths, &mut state);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    o.set_color(&warn)?;
+                    write!(o, "[warning] ")?;
+                    o.reset()?;
+                    writeln!(o, "{e:#}")?;
+                }
+            }
+        }
+
+        let theme = match &settings.theme {
+            Some(path) => Theme::load(path)?
+                .with_context(|| anyhow!("{}: Theme does not exist", path.display()))?,
+            None => match theme::default_path().map(|path| Theme::load(&path)) {
+                Some(Ok(Some(theme))) => theme,
+                Some(Ok(None)) | None => Theme::default(),
+                Some(Err(e)) => {
+                    o.set_color(&warn)?;
+                    write!(o, "[warning] ")?;
+                    o.reset()?;
+                    writeln!(o, "{e:#}")?;
+                    Theme::default()
+                }
+            },
+        };
+
+        let mut app = App::new(theme, opts.wrap);
 
         if !app.run(&mut state)? {
             return Err(anyhow!("Aborting due to user cancellation."));
         }
+
+        if let Some((path, paths)) = persist
+            && let Err(e) = Picks::new(paths, &state).save(&path)
+        {
+            o.set_color(&warn)?;
+            write!(o, "[warning] ")?;
+            o.reset()?;
+            writeln!(o, "{e:#}")?;
+        }
     }
 
-    let name = state.name.context("No name specified for catalog")?;
+    let name = state
+        .name
+        .as_deref()
+        .context("No name specified for catalog")?;
+
+    let mut converted = BTreeMap::new();
+    let mut jobs = Vec::new();
 
-    for c in &state.catalogs {
-        let Some(book) = c.selected() else {
+    // Which books are skipped as duplicates depends on the order of catalogs,
+    // so it's decided before any book is converted.
+    for (index, catalog) in state.catalogs.iter().enumerate() {
+        let Some(book) = catalog.selected() else {
             continue;
         };
 
-        let mut target = opts.out.clone();
-        target.push(format!("{name}{:03}", c.number));
-        target.add_extension("cbz");
+        let mut converted_as = None;
+
+        if opts.skip_duplicates {
+            match converted.get(&book.dedupe_key()) {
+                Some(&first) => converted_as = Some(first),
+                None => {
+                    converted.insert(book.dedupe_key(), catalog.number);
+                }
+            }
+        }
+
+        jobs.push(Job {
+            index,
+            catalog,
+            book,
+            converted_as,
+        });
+    }
+
+    let convert = Convert {
+        opts,
+        settings: &settings,
+        state: &state,
+        name,
+        transform: &transform,
+        warn: &warn,
+        ok: &ok,
+        dirs: Mutex::default(),
+    };
+
+    let parallelism = settings
+        .jobs
+        .or_else(|| thread::available_parallelism().ok())
+        .map_or(1, NonZeroUsize::get)
+        .min(jobs.len());
+
+    if parallelism <= 1 {
+        for job in &jobs {
+            convert.book(&mut o, job)?;
+        }
+
+        return Ok(());
+    }
+
+    convert.parallel(&mut o, &jobs, parallelism)
+}
+
+/// Options resolved from the command line and the configuration file.
+struct Settings {
+    output: Output,
+    jobs: Option<NonZeroUsize>,
+    theme: Option<PathBuf>,
+}
+
+impl Settings {
+    /// Load the configuration file and resolve options against it, where
+    /// options on the command line take precedence.
+    fn resolve(opts: &Bookvert) -> Result<Self> {
+        let file = match &opts.config {
+            Some(path) => settings::File::load(path, true)?,
+            None => match settings::default_path() {
+                Some(path) => settings::File::load(&path, false)?,
+                None => settings::File::default(),
+            },
+        };
+
+        let output = match (opts.output, &file.output) {
+            (Some(output), _) => output,
+            (None, Some(output)) => output
+                .parse()
+                .with_context(|| anyhow!("Parsing output '{output}' in configuration"))?,
+            (None, None) => Output::Format(Format::Cbz),
+        };
+
+        Ok(Self {
+            output,
+            jobs: opts.jobs.or(file.jobs),
+            theme: opts.theme.clone().or(file.theme),
+        })
+    }
+}
+
+/// A book selected for conversion.
+struct Job<'a> {
+    /// The index of the catalog of the book.
+    index: usize,
+    catalog: &'a Catalog,
+    book: &'a Book,
+    /// The number of an earlier catalog this book was already converted as
+    /// part of, with `--skip-duplicates`.
+    converted_as: Option<u32>,
+}
+
+/// Shared state for converting books.
+struct Convert<'a> {
+    opts: &'a Bookvert,
+    settings: &'a Settings,
+    state: &'a State,
+    name: &'a str,
+    transform: &'a Transform,
+    warn: &'a ColorSpec,
+    ok: &'a ColorSpec,
+    /// Output directories which have been created.
+    dirs: Mutex<HashSet<PathBuf>>,
+}
+
+impl Convert<'_> {
+    /// Convert books using a pool of threads.
+    ///
+    /// The output of each book is buffered and written in the order of the
+    /// books once it completes, so that it isn't interleaved.
+    fn parallel(
+        &self,
+        o: &mut impl WriteColor,
+        jobs: &[Job<'_>],
+        parallelism: usize,
+    ) -> Result<()> {
+        let writer = BufferWriter::stdout(termcolor::ColorChoice::Auto);
+        let queue = Mutex::new(jobs.iter().enumerate());
+        let (tx, rx) = mpsc::channel();
+
+        thread::scope(|s| -> Result<()> {
+            for _ in 0..parallelism {
+                let tx = tx.clone();
+                let queue = &queue;
+                let writer = &writer;
+
+                s.spawn(move || {
+                    loop {
+                        let next = queue.lock().unwrap_or_else(PoisonError::into_inner).next();
+
+                        let Some((n, job)) = next else {
+                            break;
+                        };
+
+                        let mut buf = writer.buffer();
+                        let result = self.book(&mut buf, job);
+
+                        if tx.send((n, buf, result)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+
+            drop(tx);
+
+            let mut written = 0;
+            let mut pending = BTreeMap::new();
+
+            for (n, buf, result) in rx {
+                pending.insert(n, (buf, result));
+
+                while let Some((buf, result)) = pending.remove(&written) {
+                    o.flush()?;
+                    writer.print(&buf)?;
+                    result?;
+                    written += 1;
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Convert a single book.
+    fn book(&self, o: &mut impl WriteColor, job: &Job<'_>) -> Result<()> {
+        let Job {
+            catalog: c, book, ..
+        } = *job;
 
-        let color = if opts.dry_run { &warn } else { &ok };
+        let duplicates = self.state.duplicates(job.index);
+
+        if !duplicates.is_empty() {
+            let numbers = duplicates
+                .iter()
+                .map(|n| format!("{n:03}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            o.set_color(self.warn)?;
+            write!(o, "[duplicate]")?;
+            o.reset()?;
+            writeln!(
+                o,
+                " {:03}: {} is also selected in {numbers}",
+                c.number,
+                escape(&book.name)
+            )?;
+        }
+
+        let missing = book.missing_pages();
+
+        if !missing.is_empty() {
+            o.set_color(self.warn)?;
+            write!(o, "[missing]")?;
+            o.reset()?;
+            writeln!(
+                o,
+                " {:03}: {} is missing pages {}",
+                c.number,
+                escape(&book.name),
+                state::format_ranges(&missing)
+            )?;
+        }
+
+        if !book.duplicates.is_empty() {
+            o.set_color(self.warn)?;
+            write!(o, "[duplicate-pages]")?;
+            o.reset()?;
+
+            let action = if self.opts.dedup {
+                "removed"
+            } else {
+                "--dedup to remove"
+            };
+
+            writeln!(
+                o,
+                " {:03}: {} has pages identical to the page before them: {} ({action})",
+                c.number,
+                escape(&book.name),
+                file_names(&book.duplicates).join(", ")
+            )?;
+        }
+
+        if !book.extras.is_empty() {
+            o.set_color(self.warn)?;
+            write!(o, "[extra]")?;
+            o.reset()?;
+            writeln!(
+                o,
+                " {:03}: {} contains files which aren't pages: {}",
+                c.number,
+                escape(&book.name),
+                file_names(book.extras.iter().map(|e| &e.path)).join(", ")
+            )?;
+        }
+
+        if let Some(dimensions) = book.dimensions()
+            && !dimensions.outliers.is_empty()
+        {
+            let (width, height) = dimensions.typical;
+
+            let outliers = dimensions
+                .outliers
+                .iter()
+                .map(|(page, (w, h))| {
+                    let name = page.path.file_name().unwrap_or_default();
+                    format!("{} {w}x{h}", name.to_string_lossy())
+                })
+                .collect::<Vec<_>>();
+
+            o.set_color(self.warn)?;
+            write!(o, "[dimensions]")?;
+            o.reset()?;
+            writeln!(
+                o,
+                " {:03}: {} has pages which differ from {width}x{height}: {}",
+                c.number,
+                escape(&book.name),
+                outliers.join(", ")
+            )?;
+        }
+
+        if let Some(first) = job.converted_as {
+            o.set_color(self.warn)?;
+            write!(o, "  [skip] ")?;
+            o.reset()?;
+            writeln!(o, "already converted as part of {first:03}")?;
+            return Ok(());
+        }
+
+        let format = self.settings.output.select(book, self.opts.pdf_max_pages);
+
+        let mut target = self.opts.out.clone();
+        target.push(format!("{}{:03}", self.name, c.number));
+        target.add_extension(format.ext());
+
+        let color = if self.opts.dry_run {
+            self.warn
+        } else {
+            self.ok
+        };
         o.set_color(color)?;
         write!(o, "[from]")?;
         o.reset()?;
 
         writeln!(o, " {:03}: {}", c.number, book.dir.display())?;
 
-        let comic_info = config_info(opts, &name, c, book).context("ComicInfo.xml generation")?;
+        if matches!(self.settings.output, Output::Auto) {
+            o.set_color(self.ok)?;
+            write!(o, "  [format] ")?;
+            o.reset()?;
+            writeln!(o, "{format} ({} pages)", book.pages.len())?;
+        }
 
-        if opts.verbose {
-            o.set_color(&ok)?;
-            write!(o, "  [info] ")?;
+        if let Some(cover) = &book.cover {
+            o.set_color(self.ok)?;
+            write!(o, "  [cover] ")?;
             o.reset()?;
-            writeln!(o, "ComicInfo.xml:")?;
+            writeln!(o, "{}", cover.display())?;
+        }
 
-            for line in comic_info.lines() {
-                writeln!(o, "    {line}")?;
+        let mut parts = self
+            .opts
+            .path_layout
+            .as_ref()
+            .map(|layout| layout.parts(&book.dir, &book.name))
+            .unwrap_or_default();
+
+        if let Some(author) = &self.opts.author {
+            parts.author = Some(author.clone());
+        }
+
+        if let Some(series) = &self.opts.series {
+            parts.series = Some(series.clone());
+        }
+
+        let title = parts.volume.as_deref().unwrap_or(&book.name);
+
+        if !self.opts.force
+            && let Ok(metadata) = fs::metadata(&target)
+        {
+            if !self.opts.skip_existing {
+                o.set_color(self.warn)?;
+                write!(o, "  [exists] ")?;
+                o.reset()?;
+                writeln!(o, "{} (--force to overwrite)", target.display())?;
+                return Ok(());
             }
+
+            // Outputs are only up to date if they were written after every
+            // page was last modified.
+            let up_to_date = match (metadata.modified().ok(), book.modified()) {
+                (Some(output), Some(pages)) => output >= pages,
+                _ => false,
+            };
+
+            if up_to_date {
+                o.set_color(self.ok)?;
+                write!(o, "  [up-to-date] ")?;
+                o.reset()?;
+                writeln!(o, "{}", target.display())?;
+                return Ok(());
+            }
+
+            o.set_color(self.warn)?;
+            write!(o, "  [outdated] ")?;
+            o.reset()?;
+            writeln!(o, "{} is older than its pages", target.display())?;
         }
 
-        if target.exists() && !opts.force {
-            o.set_color(&warn)?;
-            write!(o, "  [exists] ")?;
+        let mut report = Report::default();
+        let contents = self.transform.apply(book, &mut report)?;
+
+        for Resized { path, from, to } in &report.resized {
+            o.set_color(self.warn)?;
+            write!(o, "  [resize] ")?;
             o.reset()?;
-            writeln!(o, "{} (--force to overwrite)", target.display())?;
-            continue;
+            writeln!(
+                o,
+                "{} ({}x{} -> {}x{})",
+                path.display(),
+                from.0,
+                from.1,
+                to.0,
+                to.1
+            )?;
+        }
+
+        if self.opts.grayscale.is_some() {
+            o.set_color(self.ok)?;
+            write!(o, "  [grayscale] ")?;
+            o.reset()?;
+            writeln!(
+                o,
+                "{} pages converted, {} colored pages kept",
+                report.grayscale, report.colored
+            )?;
+        }
+
+        if self.opts.trim {
+            let average = if report.trimmed > 0 {
+                report.trimmed_share / report.trimmed as f64 * 100.0
+            } else {
+                0.0
+            };
+
+            o.set_color(self.ok)?;
+            write!(o, "  [trim] ")?;
+            o.reset()?;
+            write!(
+                o,
+                "{} pages trimmed by {average:.1}% on average",
+                report.trimmed
+            )?;
+
+            if report.trim_misdetected > 0 {
+                write!(
+                    o,
+                    ", {} pages kept since almost everything would be trimmed",
+                    report.trim_misdetected
+                )?;
+            }
+
+            writeln!(o)?;
         }
 
-        let mut w = ZipWriter::new(Cursor::new(Vec::new()));
+        if self.opts.split_spreads {
+            o.set_color(self.ok)?;
+            write!(o, "  [split] ")?;
+            o.reset()?;
+            writeln!(o, "{} spreads split into two pages", report.split)?;
+        }
 
-        let options = SimpleFileOptions::default()
-            .compression_method(CompressionMethod::Stored)
-            .unix_permissions(0o755);
+        if self.opts.webp {
+            let before = book.bytes();
+            let after = contents.iter().map(|c| c.data.len() as u64).sum::<u64>();
 
-        w.start_file("ComicInfo.xml", options)?;
-        w.write_all(comic_info.as_bytes())?;
+            o.set_color(self.ok)?;
+            write!(o, "  [webp] ")?;
+            o.reset()?;
+            writeln!(
+                o,
+                "{before} -> {after} bytes ({} bytes saved)",
+                before.saturating_sub(after)
+            )?;
+        }
+
+        if self.opts.optimize_png {
+            o.set_color(self.ok)?;
+            write!(o, "  [optimize-png] ")?;
+            o.reset()?;
+            writeln!(
+                o,
+                "{} pages optimized ({} bytes saved)",
+                report.optimized, report.optimized_saved
+            )?;
+        }
 
-        for page in book.pages.iter() {
-            let content = fs::read(&page.path)
-                .with_context(|| anyhow!("Failed to read file {}", page.path.display()))?;
+        let comic_info = if self.opts.no_comic_info {
+            None
+        } else {
+            Some(
+                config_info(self.opts, self.name, c, contents.len(), &parts)
+                    .context("ComicInfo.xml generation")?,
+            )
+        };
+
+        if self.opts.verbose
+            && let Some(comic_info) = &comic_info
+        {
+            o.set_color(self.ok)?;
+            write!(o, "  [info] ")?;
+            o.reset()?;
+            writeln!(o, "ComicInfo.xml:")?;
 
-            w.start_file(&page.name, options)?;
-            w.write_all(&content)?;
+            for line in comic_info.lines() {
+                writeln!(o, "    {line}")?;
+            }
         }
 
-        let out = w.finish()?.into_inner();
+        let out = match format {
+            Format::Cbz => {
+                let mut extras = Vec::new();
+
+                if self.opts.bundle_extras {
+                    for extra in &book.extras {
+                        if is_bundled(&extra.path) {
+                            let name = extra.path.file_name().unwrap_or_default();
+                            extras.push((name.to_string_lossy(), extra.read()?));
+                        }
+                    }
+                }
 
-        if opts.dry_run {
-            o.set_color(&warn)?;
+                write_cbz(
+                    &contents,
+                    comic_info.as_deref(),
+                    &extras,
+                    self.opts.keep_page_names,
+                )?
+            }
+            Format::Pdf => pdf::write(
+                &contents,
+                title,
+                &parts,
+                self.opts.rtl,
+                self.opts.jpeg_quality,
+                &mut report.skipped,
+            )?,
+            Format::Epub => epub::write(
+                &contents,
+                title,
+                &parts,
+                self.opts.language.as_ref(),
+                self.opts.rtl,
+                &mut report.skipped,
+            )?,
+        };
+
+        if format == Format::Pdf
+            && let Some(quality) = self.opts.jpeg_quality
+        {
+            let before = book.bytes();
+            let after = out.len() as u64;
+
+            o.set_color(self.ok)?;
+            write!(o, "  [jpeg] ")?;
+            o.reset()?;
+            writeln!(
+                o,
+                "{before} bytes of pages -> {after} bytes of pdf at quality {quality}"
+            )?;
+        }
+
+        for message in report.skipped {
+            o.set_color(self.warn)?;
+            write!(o, "  [skip] ")?;
+            o.reset()?;
+            writeln!(o, "{message}")?;
+        }
+
+        if self.opts.dry_run {
+            o.set_color(self.warn)?;
             write!(o, "  [dry-run] ")?;
             o.reset()?;
         } else {
-            o.set_color(&ok)?;
+            o.set_color(self.ok)?;
             write!(o, "  [file] ")?;
             o.reset()?;
         }
 
         writeln!(o, "{} ({} bytes)", target.display(), out.len())?;
 
-        if opts.dry_run {
-            continue;
+        if self.opts.dry_run {
+            return Ok(());
         }
 
         if let Some(parent) = target.parent() {
-            fs::create_dir_all(parent).with_context(|| {
-                anyhow!("Failed to create parent directory {}", parent.display())
-            })?;
+            // Books which are converted in parallel can share a directory.
+            let mut dirs = self.dirs.lock().unwrap_or_else(PoisonError::into_inner);
+
+            if !dirs.contains(parent) {
+                fs::create_dir_all(parent).with_context(|| {
+                    anyhow!("Failed to create parent directory {}", parent.display())
+                })?;
+
+                dirs.insert(parent.to_owned());
+            }
         }
 
         fs::write(&target, out)
             .with_context(|| anyhow!("Failed to write file {}", target.display()))?;
+
+        Ok(())
+    }
+}
+
+/// Package the pages of a book into a `.cbz` archive.
+///
+/// Pages are named sequentially from `1`, zero-padded to the width of the
+/// largest number so that they sort the same in every reader, unless
+/// `keep_names` is set.
+fn write_cbz(
+    contents: &[Content<'_>],
+    comic_info: Option<&str>,
+    extras: &[(Cow<'_, str>, Vec<u8>)],
+    keep_names: bool,
+) -> Result<Vec<u8>> {
+    let mut w = ZipWriter::new(Cursor::new(Vec::new()));
+
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Stored)
+        .unix_permissions(0o755);
+
+    // Readers look for ComicInfo.xml before any pages.
+    if let Some(comic_info) = comic_info {
+        w.start_file("ComicInfo.xml", options)?;
+        w.write_all(comic_info.as_bytes())?;
     }
 
-    Ok(())
+    let width = contents.len().to_string().len();
+
+    for (n, content) in contents.iter().enumerate() {
+        let name = if keep_names {
+            let page = content.page;
+            let stem = page.path.file_stem().unwrap_or_default().to_string_lossy();
+            format!("{stem}{}.{}", content.half, content.ext())
+        } else {
+            format!("{:0width$}.{}", n + 1, content.ext())
+        };
+
+        w.start_file(name, options)?;
+        w.write_all(&content.data)?;
+    }
+
+    for (name, data) in extras {
+        w.start_file(name.as_ref(), options)?;
+        w.write_all(data)?;
+    }
+
+    Ok(w.finish()?.into_inner())
 }
 
 /// Extracts all numbers from the input string as an iterator.
@@ -662,8 +1747,227 @@ fn numbers(mut input: &str) -> impl Iterator<Item = u32> {
     })
 }
 
-/// Generates ComicInfo.xml content if any metadata options are provided.
-fn config_info(opts: &Bookvert, name: &str, catalog: &Catalog, book: &Book) -> Result<String> {
+/// Build a book out of the image entries of an archive, without extracting
+/// it.
+fn archive_book(path: &Path, name: &str, kind: ArchiveKind) -> Result<Book> {
+    let metadata = fs::metadata(path)?;
+    let modified = metadata.modified().ok();
+
+    let archive_path = Arc::<Path>::from(path);
+
+    let files = match kind {
+        ArchiveKind::Zip => zip_files(&archive_path)?,
+        ArchiveKind::Tar { gzip } => tar_files(&archive_path, gzip)?,
+    };
+
+    let mut entries = Vec::new();
+    let mut extras = Vec::new();
+
+    for (name, source, size) in files {
+        let entry = Path::new(&name);
+
+        // Hidden files and resource forks added by macOS are skipped, like
+        // hidden files are when scanning directories.
+        let hidden = entry.components().any(|c| {
+            let c = c.as_os_str().to_string_lossy();
+            c.starts_with('.') || c == "__MACOSX"
+        });
+
+        if hidden {
+            continue;
+        }
+
+        let ext = match extension(entry) {
+            Some(ext) if matches!(ext.as_str(), ext!()) => ext,
+            // Metadata from an archive which was packaged before is expected.
+            _ if name == "ComicInfo.xml" => continue,
+            _ => {
+                extras.push(Extra {
+                    source,
+                    path: path.join(&name),
+                });
+
+                continue;
+            }
+        };
+
+        entries.push((name, ext, size, source));
+    }
+    entries.sort_by(|(a, ..), (b, ..)| natural::compare_paths(Path::new(a), Path::new(b)));
+
+    let mut pages = Vec::with_capacity(entries.len());
+
+    for (n, (entry, ext, size, source)) in entries.into_iter().enumerate() {
+        let number = Path::new(&entry)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| numbers(s).last());
+
+        pages.push(Page {
+            source,
+            path: path.join(&entry),
+            name: format!("p{n:03}.{ext}"),
+            size,
+            modified,
+            number,
+            dimensions: None,
+        });
+    }
+
+    Ok(Book {
+        dir: path.to_path_buf(),
+        name: name.to_string(),
+        pages,
+        numbers: numbers(name).collect(),
+        duplicates: Vec::new(),
+        cover: None,
+        extras,
+    })
+}
+
+/// The kind of an archive which is read as a book.
+#[derive(Debug, Clone, Copy)]
+enum ArchiveKind {
+    /// A zip archive, like `.cbz`.
+    Zip,
+    /// A tar archive, like `.cbt`, which might be compressed with gzip.
+    Tar { gzip: bool },
+}
+
+/// Get the kind of archive a file is from its extension, if it is one.
+fn archive_kind(path: &Path, ext: Option<&str>) -> Option<ArchiveKind> {
+    match ext? {
+        "zip" | "cbz" => Some(ArchiveKind::Zip),
+        "tar" | "cbt" => Some(ArchiveKind::Tar { gzip: false }),
+        "tgz" => Some(ArchiveKind::Tar { gzip: true }),
+        "gz" if is_tar_gz(path) => Some(ArchiveKind::Tar { gzip: true }),
+        _ => None,
+    }
+}
+
+/// Test if a path looks like a `cbz` book written by an earlier run, which is
+/// stored directly in the output directory and named like `{name}{NNN}.cbz`.
+///
+/// If no name is given, any name ending in a catalog number matches.
+fn is_output(path: &Path, out: Option<&Path>, name: Option<&str>) -> bool {
+    let Some(out) = out else {
+        return false;
+    };
+
+    if extension(path).as_deref() != Some("cbz") {
+        return false;
+    }
+
+    let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+        return false;
+    };
+
+    let number = match name {
+        Some(name) => {
+            let Some(number) = stem.strip_prefix(name) else {
+                return false;
+            };
+
+            number
+        }
+        None => stem.trim_start_matches(|c: char| !c.is_ascii_digit()),
+    };
+
+    if number.len() < 3 || !number.bytes().all(|b| b.is_ascii_digit()) {
+        return false;
+    }
+
+    path.parent()
+        .and_then(|dir| dir.canonicalize().ok())
+        .is_some_and(|dir| dir == out)
+}
+
+/// List the files in a zip archive with their sizes.
+fn zip_files(archive_path: &Arc<Path>) -> Result<Vec<(String, Source, u64)>> {
+    let mut archive = ZipArchive::new(File::open(archive_path)?)?;
+    let mut files = Vec::with_capacity(archive.len());
+
+    for index in 0..archive.len() {
+        let file = archive.by_index_raw(index)?;
+
+        if !file.is_file() {
+            continue;
+        }
+
+        let source = Source::Archive {
+            archive: archive_path.clone(),
+            entry: file.name().to_owned(),
+        };
+
+        files.push((file.name().to_owned(), source, file.size()));
+    }
+
+    Ok(files)
+}
+
+/// List the files in a tar archive with their sizes, recording where they
+/// are stored so that they can be read without scanning the archive again.
+fn tar_files(archive_path: &Arc<Path>, gzip: bool) -> Result<Vec<(String, Source, u64)>> {
+    let mut archive = tar::Archive::new(state::tar_reader(archive_path, gzip)?);
+    let mut files = Vec::new();
+
+    for entry in archive.entries()? {
+        let entry = entry?;
+
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let path = entry.path()?;
+
+        let Some(name) = path.to_str() else {
+            continue;
+        };
+
+        // Archives created from the current directory prefix every entry.
+        let name = name.strip_prefix("./").unwrap_or(name).to_owned();
+
+        let source = Source::Tar {
+            archive: archive_path.clone(),
+            entry: name.clone(),
+            offset: entry.raw_file_position(),
+            size: entry.size(),
+            gzip,
+        };
+
+        files.push((name, source, entry.size()));
+    }
+
+    Ok(files)
+}
+
+/// Get the file names of paths for display.
+pub(crate) fn file_names<'a>(paths: impl IntoIterator<Item = &'a PathBuf>) -> Vec<Cow<'a, str>> {
+    paths
+        .into_iter()
+        .map(|path| {
+            path.file_name()
+                .unwrap_or(path.as_os_str())
+                .to_string_lossy()
+        })
+        .collect()
+}
+
+/// Test if a file stored with a book is bundled into `cbz` output by
+/// `--bundle-extras`.
+fn is_bundled(path: &Path) -> bool {
+    matches!(extension(path).as_deref(), Some("nfo" | "txt"))
+}
+
+/// Generates ComicInfo.xml content, with elements in the order of the
+/// ComicInfo schema.
+fn config_info(
+    opts: &Bookvert,
+    name: &str,
+    catalog: &Catalog,
+    pages: usize,
+    parts: &Parts,
+) -> Result<String> {
     let mut o = String::new();
 
     writeln!(o, "<?xml version=\"1.0\" encoding=\"utf-8\"?>")?;
@@ -678,12 +1982,20 @@ fn config_info(opts: &Bookvert, name: &str, catalog: &Catalog, book: &Book) -> R
         xml_escape(&format!("{name}{}", catalog.number))
     )?;
 
-    let series = opts.series.as_deref().unwrap_or(name);
+    let series = parts.series.as_deref().unwrap_or(name);
     writeln!(o, "  <Series>{}</Series>", xml_escape(series))?;
     writeln!(o, "  <Number>{}</Number>", catalog.number)?;
-    writeln!(o, "  <PageCount>{}</PageCount>", book.pages.len())?;
 
-    if let Some(author) = &opts.author {
+    // ComicInfo.xml only accepts a volume number.
+    if let Some(volume) = parts.volume.as_deref().and_then(|v| numbers(v).next()) {
+        writeln!(o, "  <Volume>{volume}</Volume>")?;
+    }
+
+    if let Some(summary) = &opts.summary {
+        writeln!(o, "  <Summary>{}</Summary>", xml_escape(summary))?;
+    }
+
+    if let Some(author) = &parts.author {
         writeln!(o, "  <Writer>{}</Writer>", xml_escape(author))?;
     }
 
@@ -699,16 +2011,16 @@ fn config_info(opts: &Bookvert, name: &str, catalog: &Catalog, book: &Book) -> R
         writeln!(o, "  <Genre>{}</Genre>", xml_escape(genre))?;
     }
 
+    writeln!(o, "  <PageCount>{pages}</PageCount>")?;
+
     if let Some(language) = &opts.language {
         writeln!(o, "  <LanguageISO>{language}</LanguageISO>")?;
     }
 
-    if let Some(manga) = &opts.manga {
-        writeln!(o, "  <Manga>{manga}</Manga>")?;
-    }
+    let manga = opts.manga.or(opts.rtl.then_some(Manga::YesAndRightToLeft));
 
-    if let Some(summary) = &opts.summary {
-        writeln!(o, "  <Summary>{}</Summary>", xml_escape(summary))?;
+    if let Some(manga) = manga {
+        writeln!(o, "  <Manga>{manga}</Manga>")?;
     }
 
     writeln!(o, "</ComicInfo>")?;
@@ -750,7 +2062,7 @@ fn escape(input: &str) -> Cow<'_, str> {
 }
 
 /// Escapes special XML characters.
-fn xml_escape(input: &str) -> Cow<'_, str> {
+pub(crate) fn xml_escape(input: &str) -> Cow<'_, str> {
     let mut escaped = String::new();
 
     let n = 'escape: {
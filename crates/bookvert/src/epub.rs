@@ -0,0 +1,315 @@
+use core::fmt::Write as _;
+
+use std::io::{Cursor, Write};
+
+use anyhow::{Result, anyhow};
+use jiff::Timestamp;
+use language_tags::LanguageTag;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::cli::xml_escape;
+use crate::layout::Parts;
+use crate::transform::Content;
+
+const CONTAINER: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+/// Get the media type of an image page by its extension, or `None` if it's
+/// not a core media type of EPUB 3 that readers are required to support.
+fn media_type(ext: &str) -> Option<&'static str> {
+    match ext {
+        "jpg" => Some("image/jpeg"),
+        "png" => Some("image/png"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+/// Package the pages of a book into an EPUB 3 `.epub`, where each page image
+/// is wrapped in an XHTML document in page order.
+///
+/// Pages which aren't in a format supported by EPUB readers are skipped and
+/// described in `skipped`. With `rtl` pages progress from right to left.
+pub(crate) fn write(
+    contents: &[Content<'_>],
+    title: &str,
+    parts: &Parts,
+    language: Option<&LanguageTag>,
+    rtl: bool,
+    skipped: &mut Vec<String>,
+) -> Result<Vec<u8>> {
+    let mut w = ZipWriter::new(Cursor::new(Vec::new()));
+
+    // The mimetype must be the first entry and must not be compressed.
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    w.start_file("mimetype", stored)?;
+    w.write_all(b"application/epub+zip")?;
+
+    w.start_file("META-INF/container.xml", deflated)?;
+    w.write_all(CONTAINER.as_bytes())?;
+
+    let title = xml_escape(title);
+    let mut manifest = String::new();
+    let mut spine = String::new();
+    let mut first = None;
+
+    for content in contents {
+        let Some((stem, ext)) = content.name.rsplit_once('.') else {
+            continue;
+        };
+
+        let Some(media_type) = media_type(ext) else {
+            skipped.push(format!(
+                "{}: {ext} images are not supported by EPUB readers",
+                content.page.path.display()
+            ));
+            continue;
+        };
+
+        // Images are already compressed, so there's no point in deflating
+        // them again.
+        w.start_file(format!("OEBPS/images/{}", content.name), stored)?;
+        w.write_all(&content.data)?;
+
+        w.start_file(format!("OEBPS/pages/{stem}.xhtml"), deflated)?;
+        write!(
+            w,
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+  <title>{title}</title>
+  <style>body {{ margin: 0; text-align: center; }} img {{ max-width: 100%; max-height: 100vh; }}</style>
+</head>
+<body>
+  <img src="../images/{name}" alt=""/>
+</body>
+</html>
+"#,
+            name = content.name
+        )?;
+
+        // Books are arranged so that the cover comes first, see
+        // `Book::find_cover`.
+        let properties = if first.is_none() {
+            r#" properties="cover-image""#
+        } else {
+            ""
+        };
+
+        _ = writeln!(
+            manifest,
+            r#"    <item id="img-{stem}" href="images/{name}" media-type="{media_type}"{properties}/>"#,
+            name = content.name
+        );
+        _ = writeln!(
+            manifest,
+            r#"    <item id="page-{stem}" href="pages/{stem}.xhtml" media-type="application/xhtml+xml"/>"#
+        );
+        _ = writeln!(spine, r#"    <itemref idref="page-{stem}"/>"#);
+
+        first.get_or_insert(stem);
+    }
+
+    let Some(first) = first else {
+        return Err(anyhow!("No pages supported by EPUB readers"));
+    };
+
+    w.start_file("OEBPS/nav.xhtml", deflated)?;
+    write!(
+        w,
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+  <title>{title}</title>
+</head>
+<body>
+  <nav epub:type="toc" id="toc">
+    <ol>
+      <li><a href="pages/{first}.xhtml">{title}</a></li>
+    </ol>
+  </nav>
+</body>
+</html>
+"#
+    )?;
+
+    let language = language.map_or("und", LanguageTag::as_str);
+    let direction = if rtl { "rtl" } else { "ltr" };
+    let mut extra = String::new();
+
+    if let Some(author) = &parts.author {
+        _ = writeln!(extra, "    <dc:creator>{}</dc:creator>", xml_escape(author));
+    }
+
+    if let Some(series) = &parts.series {
+        _ = writeln!(
+            extra,
+            r#"    <meta property="belongs-to-collection" id="series">{}</meta>"#,
+            xml_escape(series)
+        );
+        _ = writeln!(
+            extra,
+            r##"    <meta refines="#series" property="collection-type">series</meta>"##
+        );
+    }
+    let modified = Timestamp::now().strftime("%Y-%m-%dT%H:%M:%SZ");
+
+    w.start_file("OEBPS/content.opf", deflated)?;
+    write!(
+        w,
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="id">urn:bookvert:{title}</dc:identifier>
+    <dc:title>{title}</dc:title>
+    <dc:language>{language}</dc:language>
+{extra}    <meta property="dcterms:modified">{modified}</meta>
+    <meta name="cover" content="img-{first}"/>
+  </metadata>
+  <manifest>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+{manifest}  </manifest>
+  <spine page-progression-direction="{direction}">
+{spine}  </spine>
+</package>
+"#
+    )?;
+
+    Ok(w.finish()?.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+    use std::path::PathBuf;
+
+    use zip::{CompressionMethod, ZipArchive};
+
+    use crate::layout::Parts;
+    use crate::state::{Page, Source};
+    use crate::transform::Content;
+
+    fn page(name: &str) -> Page {
+        Page {
+            source: Source::File,
+            path: PathBuf::from(name),
+            name: name.to_string(),
+            size: 0,
+            modified: None,
+            number: None,
+            dimensions: None,
+        }
+    }
+
+    fn read(zip: &mut ZipArchive<Cursor<Vec<u8>>>, name: &str) -> String {
+        let mut file = zip.by_name(name).unwrap();
+        let mut out = String::new();
+        file.read_to_string(&mut out).unwrap();
+        out
+    }
+
+    /// Get the positions of each needle in the haystack, which must all be
+    /// present.
+    fn positions(haystack: &str, needles: &[&str]) -> Vec<usize> {
+        needles
+            .iter()
+            .map(|n| haystack.find(n).unwrap_or_else(|| panic!("missing {n}")))
+            .collect()
+    }
+
+    #[test]
+    fn structure() {
+        let pages = ["cover.jpg", "002.png", "010.jpg", "011.bmp", "100.webp"].map(page);
+
+        let contents = pages
+            .iter()
+            .map(|page| Content {
+                page,
+                name: page.name.clone(),
+                data: page.name.as_bytes().to_vec(),
+                half: "",
+            })
+            .collect::<Vec<_>>();
+
+        let mut skipped = Vec::new();
+        let data = super::write(
+            &contents,
+            "Tom & Jerry",
+            &Parts::default(),
+            None,
+            true,
+            &mut skipped,
+        )
+        .unwrap();
+
+        assert_eq!(skipped.len(), 1);
+        assert!(skipped[0].starts_with("011.bmp: "));
+
+        let mut zip = ZipArchive::new(Cursor::new(data)).unwrap();
+
+        // The mimetype comes first, uncompressed, so that it can be sniffed.
+        let mimetype = zip.by_index(0).unwrap();
+        assert_eq!(mimetype.name(), "mimetype");
+        assert_eq!(mimetype.compression(), CompressionMethod::Stored);
+        drop(mimetype);
+        assert_eq!(read(&mut zip, "mimetype"), "application/epub+zip");
+
+        let container = read(&mut zip, "META-INF/container.xml");
+        assert!(container.contains(
+            r#"<rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>"#
+        ));
+
+        let stems = ["cover", "002", "010", "100"];
+
+        for (stem, name) in stems
+            .iter()
+            .zip(["cover.jpg", "002.png", "010.jpg", "100.webp"])
+        {
+            assert_eq!(read(&mut zip, &format!("OEBPS/images/{name}")), name);
+
+            let xhtml = read(&mut zip, &format!("OEBPS/pages/{stem}.xhtml"));
+            assert!(xhtml.contains("<title>Tom &amp; Jerry</title>"));
+            assert!(xhtml.contains(&format!(r#"<img src="../images/{name}" alt=""/>"#)));
+        }
+
+        assert!(zip.by_name("OEBPS/images/011.bmp").is_err());
+        assert!(zip.by_name("OEBPS/pages/011.xhtml").is_err());
+
+        let opf = read(&mut zip, "OEBPS/content.opf");
+        assert!(opf.contains("<dc:title>Tom &amp; Jerry</dc:title>"));
+        assert!(opf.contains("<dc:language>und</dc:language>"));
+        assert!(opf.contains(r#"<meta name="cover" content="img-cover"/>"#));
+        assert!(opf.contains(
+            r#"<item id="img-cover" href="images/cover.jpg" media-type="image/jpeg" properties="cover-image"/>"#
+        ));
+        assert!(
+            opf.contains(r#"<item id="img-100" href="images/100.webp" media-type="image/webp"/>"#)
+        );
+        assert!(opf.contains(
+            r#"<item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>"#
+        ));
+        assert!(opf.contains(r#"<spine page-progression-direction="rtl">"#));
+
+        // The spine lists the pages in page order.
+        let itemrefs = stems.map(|stem| format!(r#"<itemref idref="page-{stem}"/>"#));
+        let itemrefs = itemrefs.iter().map(String::as_str).collect::<Vec<_>>();
+        let spine = positions(&opf, &itemrefs);
+        assert!(spine.is_sorted());
+        assert_eq!(opf.matches("<itemref ").count(), stems.len());
+
+        let nav = read(&mut zip, "OEBPS/nav.xhtml");
+        assert!(nav.contains(r#"<nav epub:type="toc" id="toc">"#));
+        assert!(nav.contains(r#"<li><a href="pages/cover.xhtml">Tom &amp; Jerry</a></li>"#));
+    }
+}
@@ -0,0 +1,44 @@
+use core::fmt;
+use core::str::FromStr;
+
+use anyhow::{Result, anyhow};
+
+/// The container format to package a book into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    Cbz,
+    Pdf,
+    Epub,
+}
+
+impl Format {
+    /// The file extension used for the format.
+    pub(crate) fn ext(&self) -> &'static str {
+        match self {
+            Format::Cbz => "cbz",
+            Format::Pdf => "pdf",
+            Format::Epub => "epub",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "cbz" => Ok(Format::Cbz),
+            "pdf" => Ok(Format::Pdf),
+            "epub" => Ok(Format::Epub),
+            _ => Err(anyhow!("Invalid output format '{s}'")),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.ext().fmt(f)
+    }
+}
@@ -0,0 +1,75 @@
+//! An in-memory cache of decompressed gzip-compressed tar archives.
+//!
+//! Compressed archives can't be seeked, so reading a page from one means
+//! decompressing everything before it. Since every page of a book is read,
+//! and some are read more than once to find their dimensions or duplicates,
+//! archives are instead decompressed once and kept in memory.
+
+use std::io::Read;
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use anyhow::Result;
+
+use crate::state;
+
+/// The number of bytes of decompressed archives to keep in memory.
+const CAPACITY: usize = 256 * 1024 * 1024;
+
+struct Entry {
+    archive: Arc<Path>,
+    data: Arc<[u8]>,
+}
+
+/// Decompressed archives, from the least to the most recently used.
+static CACHE: Mutex<Vec<Entry>> = Mutex::new(Vec::new());
+
+/// Get the decompressed contents of a gzip-compressed archive, or `None` if
+/// it's too large to keep in memory.
+pub(crate) fn decompressed(archive: &Arc<Path>) -> Result<Option<Arc<[u8]>>> {
+    if let Some(data) = get(archive) {
+        return Ok(Some(data));
+    }
+
+    let mut data = Vec::new();
+    state::tar_reader(archive, true)?
+        .take(CAPACITY as u64 + 1)
+        .read_to_end(&mut data)?;
+
+    if data.len() > CAPACITY {
+        return Ok(None);
+    }
+
+    let data = Arc::<[u8]>::from(data);
+    insert(archive, data.clone());
+    Ok(Some(data))
+}
+
+fn get(archive: &Path) -> Option<Arc<[u8]>> {
+    let mut entries = CACHE.lock().unwrap_or_else(PoisonError::into_inner);
+    let index = entries.iter().position(|e| *e.archive == *archive)?;
+    let entry = entries.remove(index);
+    let data = entry.data.clone();
+    entries.push(entry);
+    Some(data)
+}
+
+fn insert(archive: &Arc<Path>, data: Arc<[u8]>) {
+    let mut entries = CACHE.lock().unwrap_or_else(PoisonError::into_inner);
+
+    // Another thread might have decompressed the same archive in the meantime.
+    if entries.iter().any(|e| *e.archive == **archive) {
+        return;
+    }
+
+    let mut size = entries.iter().map(|e| e.data.len()).sum::<usize>();
+
+    while size + data.len() > CAPACITY && !entries.is_empty() {
+        size -= entries.remove(0).data.len();
+    }
+
+    entries.push(Entry {
+        archive: archive.clone(),
+        data,
+    });
+}
@@ -1,18 +1,42 @@
+use core::cmp::Reverse;
+use core::fmt::Write;
+use core::mem;
+
+use std::io;
 use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
+use jiff::Zoned;
 use ratatui::Frame;
-use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
-use ratatui::layout::{Constraint, Layout};
+use ratatui::crossterm::event::{
+    self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind,
+    KeyModifiers, MouseButton, MouseEventKind,
+};
+use ratatui::crossterm::execute;
+use ratatui::layout::{Constraint, Flex, Layout, Position, Rect};
 use ratatui::text::{Line, Span};
 use ratatui::widgets::{
-    List, ListItem, ListState, Paragraph, Scrollbar, ScrollbarOrientation, ScrollbarState,
+    Block, Clear, List, ListItem, ListState, Paragraph, Scrollbar, ScrollbarOrientation,
+    ScrollbarState,
 };
 use tui_input::Input;
 use tui_input::backend::crossterm::EventHandler;
 
-use crate::state::State;
-use crate::styles::STYLES;
+use crate::bindings::{self, Binding};
+use crate::cli;
+use crate::size::Size;
+use crate::state::{self, State};
+use crate::styles::Styles;
+use crate::theme::Theme;
+
+/// How long to wait between digits before a new catalog number is started.
+const JUMP_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The number of pages with inconsistent dimensions listed for a book.
+const MAX_OUTLIERS: usize = 3;
 
 enum ViewEvent {
     PushView(View),
@@ -25,6 +49,24 @@ enum ViewEvent {
 struct CatalogsView {
     index: usize,
     list_state: ListState,
+    /// Filter applied to catalog numbers.
+    filter: Option<String>,
+    /// Input for the filter while it is being edited.
+    search: Option<Input>,
+    /// Whether only catalogs which have not been picked are shown.
+    unpicked_only: bool,
+    /// Whether we are asking for confirmation to clear all picks.
+    confirm_clear: bool,
+    /// Digits typed to jump to a catalog, and when the last one was typed.
+    jump: Option<(String, Instant)>,
+    /// A message to show in the header until the next key is pressed.
+    message: Option<String>,
+    /// Whether `g` has been pressed once, waiting for a second `g`.
+    pending_g: bool,
+    /// Number of items visible in the list when it was last drawn.
+    page: usize,
+    /// Areas of the run line, the name line and the list when last drawn.
+    areas: [Rect; 3],
 }
 
 impl Default for CatalogsView {
@@ -32,22 +74,212 @@ impl Default for CatalogsView {
         Self {
             index: 1,
             list_state: ListState::default(),
+            filter: None,
+            search: None,
+            unpicked_only: false,
+            confirm_clear: false,
+            jump: None,
+            message: None,
+            pending_g: false,
+            page: 1,
+            areas: [Rect::default(); 3],
         }
     }
 }
 
 impl CatalogsView {
-    fn update(&mut self, key: KeyEvent, state: &mut State) -> ViewEvent {
-        use KeyCode::{Backspace, Char, Down, Enter, Esc, Right, Up};
+    /// The currently effective filter, including one being edited.
+    fn filter(&self) -> Option<&str> {
+        let filter = match &self.search {
+            Some(input) => input.value(),
+            None => self.filter.as_deref()?,
+        };
+
+        (!filter.is_empty()).then_some(filter)
+    }
+
+    /// Indexes of the catalogs which are visible with the current filters.
+    fn visible(&self, state: &State) -> Vec<usize> {
+        let filter = self.filter();
+
+        state
+            .catalogs
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| !self.unpicked_only || c.picked.is_none())
+            .filter(|(_, c)| {
+                filter.is_none_or(|f| {
+                    c.number.to_string().contains(f) || format!("{:03}", c.number).contains(f)
+                })
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// The index of the selected catalog, if any.
+    fn catalog(&self, visible: &[usize]) -> Option<usize> {
+        visible.get(self.index.checked_sub(2)?).copied()
+    }
+
+    /// Select the catalog with the given index, clearing the filters if it is
+    /// not visible.
+    fn select_catalog(&mut self, state: &State, category: usize) {
+        if !self.visible(state).contains(&category) {
+            self.filter = None;
+            self.unpicked_only = false;
+        }
+
+        if let Some(n) = self.visible(state).iter().position(|&c| c == category) {
+            self.index = n.saturating_add(2);
+        }
+    }
+
+    /// Select the first visible catalog which has not been picked, returning
+    /// its index.
+    fn select_next_unpicked(&mut self, state: &State) -> Option<usize> {
+        let visible = self.visible(state);
+
+        let (n, &category) = visible
+            .iter()
+            .enumerate()
+            .find(|&(_, &c)| state.catalogs[c].picked.is_none())?;
+
+        self.index = n.saturating_add(2);
+        Some(category)
+    }
+
+    /// Select the next visible catalog after the selected one which has not
+    /// been picked, wrapping around to the start.
+    fn select_following_unpicked(&mut self, state: &State) {
+        let visible = self.visible(state);
+        // Position in the visible list following the selected catalog.
+        let start = self.index.saturating_sub(1);
+
+        let found = (0..visible.len())
+            .map(|n| start.wrapping_add(n) % visible.len())
+            .find(|&n| state.catalogs[visible[n]].picked.is_none());
+
+        if let Some(n) = found {
+            self.index = n.saturating_add(2);
+        }
+    }
+
+    /// Append a digit to the catalog number to jump to, and select the visible
+    /// catalog with that number if there is one.
+    fn update_jump(&mut self, digit: char, state: &State) {
+        let now = Instant::now();
+
+        let buf = match &mut self.jump {
+            Some((buf, last)) if now.duration_since(*last) < JUMP_TIMEOUT => {
+                *last = now;
+                buf
+            }
+            jump => &mut jump.insert((String::new(), now)).0,
+        };
+
+        buf.push(digit);
+
+        let number = buf.parse::<u32>().ok();
+        let buf = buf.clone();
+        let visible = self.visible(state);
+
+        let found = visible
+            .iter()
+            .position(|&c| Some(state.catalogs[c].number) == number);
+
+        match found {
+            Some(n) => {
+                self.index = n.saturating_add(2);
+            }
+            None => {
+                self.message = Some(format!("No catalog numbered {buf}"));
+            }
+        }
+    }
+
+    fn update_search(&mut self, key: KeyEvent, state: &State) {
+        use KeyCode::{Enter, Esc};
+
+        let Some(input) = &mut self.search else {
+            return;
+        };
+
+        match key.code {
+            Enter => {
+                let value = input.value().trim();
+                self.filter = (!value.is_empty()).then(|| value.to_string());
+                self.search = None;
+
+                if !self.visible(state).is_empty() {
+                    self.index = 2;
+                }
+            }
+            Esc => {
+                self.filter = None;
+                self.search = None;
+            }
+            _ => {
+                input.handle_event(&Event::Key(key));
+            }
+        }
+
+        let max_index = self.visible(state).len().saturating_add(1);
+        self.index = self.index.min(max_index);
+    }
+
+    fn update(&mut self, key: KeyEvent, state: &mut State, wrap: bool) -> ViewEvent {
+        use KeyCode::{Backspace, Char, Delete, Down, Enter, Esc, Right, Tab, Up};
+
+        if self.search.is_some() {
+            self.update_search(key, state);
+            return ViewEvent::None;
+        }
+
+        if mem::take(&mut self.confirm_clear) {
+            if let Char('y' | 'Y') = key.code {
+                for c in &mut state.catalogs {
+                    c.picked = None;
+                }
+            }
+
+            return ViewEvent::None;
+        }
 
-        let max_index = state.catalogs.len().saturating_add(1);
+        self.message = None;
+
+        if let Char(c @ '0'..='9') = key.code {
+            self.update_jump(c, state);
+            return ViewEvent::None;
+        }
+
+        if self.jump.take().is_some() && key.code == Enter {
+            return ViewEvent::None;
+        }
+
+        let visible = self.visible(state);
+        let max_index = visible.len().saturating_add(1);
+        let pending_g = mem::take(&mut self.pending_g);
+
+        if let Some(step) = page_step(key, self.page) {
+            self.index = self.index.saturating_add_signed(step).min(max_index);
+            return ViewEvent::None;
+        }
 
         match key.code {
             Up | Char('k') => {
-                self.index = self.index.saturating_sub(1);
+                self.index = step_up(self.index, max_index, wrap);
             }
             Down | Char('j') => {
-                self.index = self.index.saturating_add(1).min(max_index);
+                self.index = step_down(self.index, max_index, wrap);
+            }
+            Char('g') if pending_g => {
+                self.index = max_index.min(2);
+            }
+            Char('g') => {
+                self.pending_g = true;
+            }
+            Char('G') => {
+                self.index = max_index;
             }
             Right | Enter | Char('l' | 'o' | ' ') => {
                 if self.index == 0 {
@@ -62,8 +294,7 @@ impl CatalogsView {
                     }
                 } else if self.index == 1 {
                     return ViewEvent::PushView(View::Name(NameView::new(state.name.as_deref())));
-                } else {
-                    let category = self.index.saturating_sub(2);
+                } else if let Some(category) = self.catalog(&visible) {
                     let index = state
                         .catalogs
                         .get(category)
@@ -72,16 +303,75 @@ impl CatalogsView {
                     return ViewEvent::PushView(View::Books(BooksView::new(category, index)));
                 }
             }
+            Tab => {
+                self.select_following_unpicked(state);
+            }
+            Char('f') => {
+                let selected = self.catalog(&visible);
+                self.unpicked_only = !self.unpicked_only;
+
+                let visible = self.visible(state);
+
+                match selected.and_then(|c| visible.iter().position(|&v| v == c)) {
+                    Some(n) => {
+                        self.index = n.saturating_add(2);
+                    }
+                    None => {
+                        self.index = self.index.min(visible.len().saturating_add(1));
+                    }
+                }
+            }
+            Char('/') => {
+                self.search = Some(Input::new(self.filter.clone().unwrap_or_default()));
+            }
+            Esc if self.filter.is_some() => {
+                self.filter = None;
+                self.index = self.index.min(self.visible(state).len().saturating_add(1));
+            }
             Esc | Char('q') => {
                 return ViewEvent::PopView;
             }
             Char('x') => {
                 return ViewEvent::Finish;
             }
-            Backspace | Char('c') if self.index >= 2 => {
-                let category = self.index.saturating_sub(2);
+            Char('a') => {
+                if let Some(c) = self
+                    .catalog(&visible)
+                    .and_then(|category| state.catalogs.get_mut(category))
+                {
+                    c.picked = c.largest();
+                }
+            }
+            Char('A') => {
+                let mut count = 0usize;
+
+                for c in &mut state.catalogs {
+                    if c.picked.is_none() {
+                        c.picked = c.largest();
+                        count += usize::from(c.picked.is_some());
+                    }
+                }
 
-                if let Some(c) = state.catalogs.get_mut(category) {
+                self.message = Some(format!(
+                    "Picked the largest book in {count} {}",
+                    pluralize(count, "catalog", "catalogs")
+                ));
+            }
+            Char('e') => {
+                return ViewEvent::PushView(View::Problems(ProblemsView::default()));
+            }
+            Char('c') => {
+                if state.picked() > 0 {
+                    self.confirm_clear = true;
+                } else {
+                    self.message = Some(String::from("No picks to clear"));
+                }
+            }
+            Backspace | Delete => {
+                if let Some(c) = self
+                    .catalog(&visible)
+                    .and_then(|category| state.catalogs.get_mut(category))
+                {
                     c.picked = None;
                 }
             }
@@ -91,7 +381,38 @@ impl CatalogsView {
         ViewEvent::None
     }
 
-    fn draw(&mut self, state: &State, frame: &mut Frame) {
+    /// Select the clicked row, or activate it if it is already selected.
+    fn click(&mut self, position: Position, state: &mut State) -> ViewEvent {
+        let [run, name, list] = self.areas;
+
+        let index = if run.contains(position) {
+            0
+        } else if name.contains(position) {
+            1
+        } else if list.contains(position) {
+            let n = self
+                .list_state
+                .offset()
+                .saturating_add(usize::from(position.y - list.y));
+
+            if n >= self.visible(state).len() {
+                return ViewEvent::None;
+            }
+
+            n.saturating_add(2)
+        } else {
+            return ViewEvent::None;
+        };
+
+        if index == self.index {
+            return self.update(KeyEvent::from(KeyCode::Enter), state, false);
+        }
+
+        self.index = index;
+        ViewEvent::None
+    }
+
+    fn draw(&mut self, state: &State, styles: &Styles, frame: &mut Frame) {
         let mut selected = None;
 
         let sub_header = {
@@ -100,30 +421,53 @@ impl CatalogsView {
             let total_count = state.catalogs.len();
             let all_picked = picked_count == total_count;
 
-            let marker = STYLES.selected(is_selected);
-            let style = STYLES.normal_item_style(is_selected, all_picked);
+            let marker = styles.selected(is_selected);
+            let style = styles.normal_item_style(is_selected, all_picked);
+            let (pages, bytes) = state.selected_totals();
 
-            Line::from(vec![
+            let mut line = Line::from(vec![
                 Span::styled(format!("{marker} "), style),
                 Span::styled(
                     format!("Run bookvert with {picked_count}/{total_count} selected"),
                     style,
                 ),
-            ])
+                Span::styled(
+                    format!(
+                        " ({pages} {}, {})",
+                        pluralize(pages, "page", "pages"),
+                        Size(bytes)
+                    ),
+                    styles.dim_style(),
+                ),
+            ]);
+
+            let problems = state.problems.len();
+
+            if problems > 0 {
+                line.push_span(Span::styled(
+                    format!(
+                        " ({problems} {} with problems, e to show)",
+                        pluralize(problems, "book", "books")
+                    ),
+                    styles.warning_text_style(),
+                ));
+            }
+
+            line
         };
 
         let name_line = {
             let is_selected = self.index == 1;
             let has_name = state.name.is_some();
 
-            let marker = STYLES.selected(is_selected);
-            let style = STYLES.item_style(is_selected, has_name);
+            let marker = styles.selected(is_selected);
+            let style = styles.item_style(is_selected, has_name);
 
             let name_display = state
                 .name
                 .as_deref()
                 .map(|n| format!("Name: {}", n))
-                .unwrap_or_else(|| format!("Name: {}", STYLES.no_name()));
+                .unwrap_or_else(|| format!("Name: {}", styles.no_name()));
 
             Line::from(vec![
                 Span::styled(format!("{marker} "), style),
@@ -132,17 +476,19 @@ impl CatalogsView {
         };
 
         let mut items = Vec::new();
+        let visible = self.visible(state);
 
-        for (i, catalog) in state.catalogs.iter().enumerate() {
-            let is_selected = i.saturating_add(2) == self.index;
+        for (n, &i) in visible.iter().enumerate() {
+            let catalog = &state.catalogs[i];
+            let is_selected = n.saturating_add(2) == self.index;
             let is_picked = catalog.picked.is_some();
 
             if is_selected {
                 selected = Some(items.len());
             }
 
-            let marker = STYLES.selected(is_selected);
-            let style = STYLES.item_style(is_selected, is_picked);
+            let marker = styles.selected(is_selected);
+            let style = styles.item_style(is_selected, is_picked);
 
             let picked_info = if let Some(picked) = catalog.picked {
                 if let Some(book) = catalog.books.get(picked) {
@@ -160,7 +506,22 @@ impl CatalogsView {
             )]);
 
             if is_picked {
-                line.push_span(format!(" {}", STYLES.done()));
+                line.push_span(format!(" {}", styles.done()));
+            }
+
+            let duplicates = state.duplicates(i);
+
+            if !duplicates.is_empty() {
+                let numbers = duplicates
+                    .iter()
+                    .map(|n| n.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                line.push_span(Span::styled(
+                    format!(" (duplicate in {numbers})"),
+                    styles.warning_text_style(),
+                ));
             }
 
             line.push_span(Span::styled(
@@ -169,7 +530,7 @@ impl CatalogsView {
                     catalog.books.len(),
                     pluralize(catalog.books.len(), "book", "books")
                 ),
-                STYLES.dim_style(),
+                styles.dim_style(),
             ));
 
             items.push(ListItem::new(line));
@@ -184,20 +545,66 @@ impl CatalogsView {
         let mut scrollbar_state = ScrollbarState::new(items.len())
             .position(self.list_state.selected().unwrap_or_default());
 
-        let header = Line::from(vec![
-            Span::styled("Catalogs", STYLES.header_style()),
+        let title = if self.unpicked_only {
+            "Catalogs (unpicked only)"
+        } else {
+            "Catalogs"
+        };
+
+        let mut header = Line::from(vec![
+            Span::styled(title, styles.header_style()),
             Span::styled(
-                " (Enter/o/→ to select, Delete/c to clear, Esc/q to quit)",
-                STYLES.header_hint_style(),
+                bindings::hints(bindings::CATALOGS),
+                styles.header_hint_style(),
             ),
         ]);
 
+        if let Some(message) = &self.message {
+            header.push_span(Span::styled(
+                format!(" {message}"),
+                styles.warning_text_style(),
+            ));
+        } else if let Some((buf, _)) = &self.jump {
+            header.push_span(Span::styled(format!(" #{buf}"), styles.header_style()));
+        }
+
+        let footer = if let Some(input) = &self.search {
+            Some(Line::from(vec![
+                Span::styled(
+                    format!("/{}", input.value()),
+                    styles.input_style(true, true),
+                ),
+                Span::styled(
+                    format!(
+                        " ({}/{} matching, Enter to apply, Esc to clear)",
+                        visible.len(),
+                        state.catalogs.len()
+                    ),
+                    styles.dim_style(),
+                ),
+            ]))
+        } else {
+            self.filter.as_deref().map(|filter| {
+                Line::from(vec![
+                    Span::styled(format!("Filter: {filter}"), styles.footer_style()),
+                    Span::styled(
+                        format!(
+                            " ({}/{} matching, Esc to clear)",
+                            visible.len(),
+                            state.catalogs.len()
+                        ),
+                        styles.dim_style(),
+                    ),
+                ])
+            })
+        };
+
         let list = List::new(items);
         let scrollbar = Scrollbar::new(ScrollbarOrientation::VerticalRight);
 
         let separator = Line::from(Span::styled(
             "─".repeat(frame.area().width as usize),
-            STYLES.dim_style(),
+            styles.dim_style(),
         ));
 
         let area = frame.area();
@@ -208,6 +615,7 @@ impl CatalogsView {
             Constraint::Length(1),
             Constraint::Length(1),
             Constraint::Min(1),
+            Constraint::Length(u16::from(footer.is_some())),
         ])
         .split(area);
 
@@ -215,15 +623,93 @@ impl CatalogsView {
         frame.render_widget(Paragraph::new(sub_header), layout[1]);
         frame.render_widget(name_line, layout[2]);
         frame.render_widget(separator, layout[3]);
+        self.page = usize::from(layout[4].height).max(1);
+        self.areas = [layout[1], layout[2], layout[4]];
         frame.render_stateful_widget(list, layout[4], &mut self.list_state);
         frame.render_stateful_widget(scrollbar, layout[4], &mut scrollbar_state);
+
+        if let Some(footer) = footer {
+            frame.render_widget(Paragraph::new(footer), layout[5]);
+
+            if let Some(input) = &self.search {
+                let cursor_x = layout[5].x + 1 + input.visual_cursor() as u16;
+                frame.set_cursor_position((cursor_x, layout[5].y));
+            }
+        }
+
+        if self.confirm_clear {
+            let n = state.picked();
+
+            let line = Line::from(vec![
+                Span::styled(
+                    format!("Clear all {n} {}?", pluralize(n, "pick", "picks")),
+                    styles.warning_style(),
+                ),
+                Span::styled(" (y/n)", styles.dim_style()),
+            ]);
+
+            let width = u16::try_from(line.width().saturating_add(4)).unwrap_or(u16::MAX);
+            let area = centered(frame.area(), width, 3);
+
+            frame.render_widget(Clear, area);
+            frame.render_widget(Paragraph::new(line).block(Block::bordered()), area);
+        }
+    }
+}
+
+/// The order in which books are listed.
+#[derive(Default, Clone, Copy)]
+enum Sort {
+    /// In the order of the catalog, which is by name.
+    #[default]
+    Name,
+    /// By total size, largest first.
+    Bytes,
+    /// By number of pages, most first.
+    Pages,
+}
+
+impl Sort {
+    /// The next sort order to cycle to.
+    fn next(self) -> Self {
+        match self {
+            Sort::Name => Sort::Bytes,
+            Sort::Bytes => Sort::Pages,
+            Sort::Pages => Sort::Name,
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Sort::Name => "name",
+            Sort::Bytes => "size",
+            Sort::Pages => "pages",
+        }
     }
 }
 
 struct BooksView {
     category: usize,
+    /// Index of the selected book in the catalog.
     index: usize,
     list_state: ListState,
+    /// Filter applied to book names.
+    filter: Option<String>,
+    /// Input for the filter while it is being edited.
+    search: Option<Input>,
+    /// The order in which books are listed.
+    sort: Sort,
+    /// Whether `g` has been pressed once, waiting for a second `g`.
+    pending_g: bool,
+    /// Number of books visible in the list when it was last drawn.
+    page: usize,
+    /// Area of the list when last drawn.
+    area: Rect,
+    /// The first list row of each visible book and its index when last drawn,
+    /// since books use a varying number of rows.
+    starts: Vec<(usize, usize)>,
+    /// The total number of list rows when last drawn.
+    rows: usize,
 }
 
 impl BooksView {
@@ -232,28 +718,171 @@ impl BooksView {
             category,
             index,
             list_state: ListState::default(),
+            filter: None,
+            search: None,
+            sort: Sort::default(),
+            pending_g: false,
+            page: 1,
+            area: Rect::default(),
+            starts: Vec::new(),
+            rows: 0,
         }
     }
 
-    fn update(&mut self, key: KeyEvent, state: &mut State) -> ViewEvent {
+    /// The currently effective filter, including one being edited.
+    fn filter(&self) -> Option<&str> {
+        let filter = match &self.search {
+            Some(input) => input.value(),
+            None => self.filter.as_deref()?,
+        };
+
+        (!filter.is_empty()).then_some(filter)
+    }
+
+    /// Indexes of the books which are visible with the current filter, in
+    /// the current sort order.
+    fn visible(&self, state: &State) -> Vec<usize> {
+        let Some(catalog) = state.catalogs.get(self.category) else {
+            return Vec::new();
+        };
+
+        let filter = self.filter();
+
+        let mut visible = catalog
+            .books
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| filter.is_none_or(|f| fuzzy_match(&b.name, f)))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+
+        let books = &catalog.books;
+
+        match self.sort {
+            Sort::Name => {}
+            Sort::Bytes => {
+                visible.sort_by_key(|&i| Reverse(books[i].bytes()));
+            }
+            Sort::Pages => {
+                visible.sort_by_key(|&i| Reverse(books[i].pages.len()));
+            }
+        }
+
+        visible
+    }
+
+    /// Make sure the selected book is visible, selecting the first visible
+    /// book otherwise.
+    fn select_visible(&mut self, state: &State) {
+        let visible = self.visible(state);
+
+        if !visible.contains(&self.index)
+            && let Some(&first) = visible.first()
+        {
+            self.index = first;
+        }
+    }
+
+    fn update_search(&mut self, key: KeyEvent, state: &State) {
+        use KeyCode::{Enter, Esc};
+
+        let Some(input) = &mut self.search else {
+            return;
+        };
+
+        match key.code {
+            Enter => {
+                let value = input.value().trim();
+                self.filter = (!value.is_empty()).then(|| value.to_string());
+                self.search = None;
+            }
+            Esc => {
+                self.filter = None;
+                self.search = None;
+            }
+            _ => {
+                input.handle_event(&Event::Key(key));
+            }
+        }
+
+        self.select_visible(state);
+    }
+
+    fn update(&mut self, key: KeyEvent, state: &mut State, wrap: bool) -> ViewEvent {
         use KeyCode::{Char, Down, Enter, Esc, Left, Up};
 
+        if self.search.is_some() {
+            self.update_search(key, state);
+            return ViewEvent::None;
+        }
+
+        let visible = self.visible(state);
+        let pos = visible.iter().position(|&i| i == self.index);
+        let last = visible.len().saturating_sub(1);
+        let pending_g = mem::take(&mut self.pending_g);
+
+        let mut select = |pos: usize| {
+            if let Some(&index) = visible.get(pos) {
+                self.index = index;
+            }
+        };
+
+        if let Some(step) = page_step(key, self.page) {
+            select(
+                pos.unwrap_or_default()
+                    .saturating_add_signed(step)
+                    .min(last),
+            );
+            return ViewEvent::None;
+        }
+
         match key.code {
             Up | Char('k') => {
-                self.index = self.index.saturating_sub(1);
+                select(step_up(pos.unwrap_or_default(), last, wrap));
             }
             Down | Char('j') => {
-                if let Some(catalog) = state.catalogs.get(self.category) {
-                    self.index = self
-                        .index
-                        .saturating_add(1)
-                        .min(catalog.books.len().saturating_sub(1));
-                }
+                select(pos.map_or(0, |p| step_down(p, last, wrap)));
+            }
+            Char('g') if pending_g => {
+                select(0);
+            }
+            Char('g') => {
+                self.pending_g = true;
+            }
+            Char('G') => {
+                select(last);
+            }
+            Char('/') => {
+                self.search = Some(Input::new(self.filter.clone().unwrap_or_default()));
+            }
+            Char('s') => {
+                self.sort = self.sort.next();
+            }
+            Char('p') => {
+                state.relative_paths = !state.relative_paths;
+            }
+            Esc if self.filter.is_some() => {
+                self.filter = None;
             }
             Left | Char('h') | Esc | Char('q') => {
                 return ViewEvent::PopView;
             }
+            Char('o') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                if let Some(book) = state
+                    .catalogs
+                    .get(self.category)
+                    .and_then(|c| c.books.get(self.index))
+                    .cloned()
+                    && let Err(e) = open_dir(&book.dir)
+                {
+                    state.problem(&book.dir, format!("Failed to open file manager: {e}"));
+                }
+            }
             Enter | Char('o') => {
+                if pos.is_none() {
+                    return ViewEvent::None;
+                }
+
                 if let Some(c) = state.catalogs.get_mut(self.category) {
                     c.picked = Some(self.index);
                 }
@@ -266,7 +895,36 @@ impl BooksView {
         ViewEvent::None
     }
 
-    fn draw(&mut self, state: &State, frame: &mut Frame) {
+    /// Select the clicked book, or pick it if it is already selected.
+    fn click(&mut self, position: Position, state: &mut State) -> ViewEvent {
+        if !self.area.contains(position) {
+            return ViewEvent::None;
+        }
+
+        let row = self
+            .list_state
+            .offset()
+            .saturating_add(usize::from(position.y - self.area.y));
+
+        if row >= self.rows {
+            return ViewEvent::None;
+        }
+
+        let n = self.starts.partition_point(|&(start, _)| start <= row);
+
+        let Some(&(_, index)) = n.checked_sub(1).and_then(|n| self.starts.get(n)) else {
+            return ViewEvent::None;
+        };
+
+        if index == self.index {
+            return self.update(KeyEvent::from(KeyCode::Enter), state, false);
+        }
+
+        self.index = index;
+        ViewEvent::None
+    }
+
+    fn draw(&mut self, state: &State, styles: &Styles, frame: &mut Frame) {
         let Some(catalog) = state.catalogs.get(self.category) else {
             return;
         };
@@ -274,16 +932,22 @@ impl BooksView {
         let mut items = Vec::new();
         let mut selected = None;
 
-        for (i, book) in catalog.books.iter().enumerate() {
+        let visible = self.visible(state);
+        self.starts.clear();
+
+        for &i in &visible {
+            let book = &catalog.books[i];
             let is_selected = i == self.index;
             let is_picked = catalog.picked == Some(i);
 
+            self.starts.push((items.len(), i));
+
             if is_selected {
                 selected = Some(items.len());
             }
 
-            let marker = STYLES.selected(is_selected);
-            let style = STYLES.normal_item_style(is_selected, is_picked);
+            let marker = styles.selected(is_selected);
+            let style = styles.normal_item_style(is_selected, is_picked);
 
             let dir = book.dir.parent().unwrap_or(Path::new("."));
 
@@ -292,45 +956,160 @@ impl BooksView {
                 style,
             )));
 
-            items.push(ListItem::new(Span::styled(
-                format!("    pages: {}", book.pages.len()),
-                STYLES.dim_style(),
-            )));
+            let mut pages = format!("    pages: {}", book.pages.len());
+            let missing = book.missing_pages();
+
+            if !missing.is_empty() {
+                _ = write!(pages, " (missing {})", state::format_ranges(&missing));
+            }
+
+            if !book.duplicates.is_empty() {
+                _ = write!(pages, " ({} duplicate)", book.duplicates.len());
+            }
+
+            items.push(ListItem::new(Span::styled(pages, styles.dim_style())));
+
+            if let Some(dimensions) = book.dimensions() {
+                let (width, height) = dimensions.typical;
+                let mut line = format!("    dimensions: {width}x{height}");
+
+                if !dimensions.outliers.is_empty() {
+                    let outliers = dimensions
+                        .outliers
+                        .iter()
+                        .take(MAX_OUTLIERS)
+                        .map(|(page, (w, h))| {
+                            let name = page.path.file_name().unwrap_or_default();
+                            format!("{} {w}x{h}", name.to_string_lossy())
+                        })
+                        .collect::<Vec<_>>();
+
+                    _ = write!(line, " (inconsistent: {}", outliers.join(", "));
+
+                    if let Some(more) = dimensions.outliers.len().checked_sub(MAX_OUTLIERS)
+                        && more > 0
+                    {
+                        _ = write!(line, " and {more} more");
+                    }
+
+                    line.push(')');
+                }
+
+                let style = if dimensions.outliers.is_empty() {
+                    styles.dim_style()
+                } else {
+                    styles.warning_text_style()
+                };
+
+                items.push(ListItem::new(Span::styled(line, style)));
+            }
+
+            if !book.extras.is_empty() {
+                let names = cli::file_names(book.extras.iter().map(|e| &e.path));
+
+                items.push(ListItem::new(Span::styled(
+                    format!("    other files: {}", names.join(", ")),
+                    styles.dim_style(),
+                )));
+            }
 
             items.push(ListItem::new(Span::styled(
                 format!("    bytes: {}", book.bytes()),
-                STYLES.dim_style(),
+                styles.dim_style(),
             )));
 
-            items.push(ListItem::new(Span::styled(
-                format!("    from {}", dir.display()),
-                STYLES.dim_style(),
-            )));
+            let mut from = format!("    from {}", state.display_path(dir).display());
+
+            if let Some(modified) = book.modified().and_then(|m| Zoned::try_from(m).ok()) {
+                _ = write!(from, " (modified {})", modified.strftime("%Y-%m-%d %H:%M"));
+            }
+
+            items.push(ListItem::new(Span::styled(from, styles.dim_style())));
         }
 
         self.list_state.select(selected);
+        self.rows = items.len();
 
         let mut scrollbar_state = ScrollbarState::new(items.len())
             .position(self.list_state.selected().unwrap_or_default());
 
-        let line = format!("Catalog {:03} - Select book", catalog.number);
+        let line = format!(
+            "Catalog {:03} - Select book (by {})",
+            catalog.number,
+            self.sort.describe()
+        );
         let line = Line::from(vec![
-            Span::styled(line, STYLES.header_style()),
-            Span::styled(
-                " (Enter/o to pick, Esc/q/← to go back, i/I to show paths)",
-                STYLES.header_hint_style(),
-            ),
+            Span::styled(line, styles.header_style()),
+            Span::styled(bindings::hints(bindings::BOOKS), styles.header_hint_style()),
         ]);
 
+        let footer = if let Some(input) = &self.search {
+            Some(Line::from(vec![
+                Span::styled(
+                    format!("/{}", input.value()),
+                    styles.input_style(true, true),
+                ),
+                Span::styled(
+                    format!(
+                        " ({}/{} matching, Enter to apply, Esc to clear)",
+                        visible.len(),
+                        catalog.books.len()
+                    ),
+                    styles.dim_style(),
+                ),
+            ]))
+        } else {
+            self.filter.as_deref().map(|filter| {
+                Line::from(vec![
+                    Span::styled(format!("Filter: {filter}"), styles.footer_style()),
+                    Span::styled(
+                        format!(
+                            " ({}/{} matching, Esc to clear)",
+                            visible.len(),
+                            catalog.books.len()
+                        ),
+                        styles.dim_style(),
+                    ),
+                ])
+            })
+        };
+
         let list = List::new(items);
         let scrollbar = Scrollbar::new(ScrollbarOrientation::VerticalRight);
 
         let area = frame.area();
-        let layout = Layout::vertical([Constraint::Length(1), Constraint::Min(1)]).split(area);
+        let layout = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(1),
+            Constraint::Length(u16::from(footer.is_some())),
+        ])
+        .split(area);
 
+        self.area = layout[1];
         frame.render_widget(line, layout[0]);
         frame.render_stateful_widget(list, layout[1], &mut self.list_state);
         frame.render_stateful_widget(scrollbar, layout[1], &mut scrollbar_state);
+
+        // A page is the number of books which start in the rows shown after
+        // scrolling.
+        let offset = self.list_state.offset();
+        let end = offset.saturating_add(usize::from(layout[1].height));
+
+        self.page = self
+            .starts
+            .iter()
+            .filter(|&&(start, _)| (offset..end).contains(&start))
+            .count()
+            .max(1);
+
+        if let Some(footer) = footer {
+            frame.render_widget(Paragraph::new(footer), layout[2]);
+
+            if let Some(input) = &self.search {
+                let cursor_x = layout[2].x + 1 + input.visual_cursor() as u16;
+                frame.set_cursor_position((cursor_x, layout[2].y));
+            }
+        }
     }
 }
 
@@ -351,23 +1130,23 @@ impl NameView {
         }
     }
 
-    fn update(&mut self, key: KeyEvent, state: &mut State) -> ViewEvent {
+    fn update(&mut self, key: KeyEvent, state: &mut State, wrap: bool) -> ViewEvent {
         use KeyCode::{Char, Down, Enter, Esc, Left, Up};
 
         let editing = self.editing && self.index == 0;
 
         match key.code {
             Up if !editing => {
-                self.index = self.index.saturating_sub(1);
+                self.index = step_up(self.index, state.names.len(), wrap);
             }
             Char('k') if !editing => {
-                self.index = self.index.saturating_sub(1);
+                self.index = step_up(self.index, state.names.len(), wrap);
             }
             Down if !editing => {
-                self.index = self.index.saturating_add(1).min(state.names.len());
+                self.index = step_down(self.index, state.names.len(), wrap);
             }
             Char('j') if !editing => {
-                self.index = self.index.saturating_add(1).min(state.names.len());
+                self.index = step_down(self.index, state.names.len(), wrap);
             }
             Left if !editing => {
                 return ViewEvent::PopView;
@@ -414,20 +1193,17 @@ impl NameView {
         ViewEvent::None
     }
 
-    fn draw(&mut self, state: &State, frame: &mut Frame) {
+    fn draw(&mut self, state: &State, styles: &Styles, frame: &mut Frame) {
         let editing = self.editing && self.index == 0;
 
         let header = Line::from(vec![
-            Span::styled("Set Name", STYLES.header_style()),
-            Span::styled(
-                " (Enter to select, Esc/q/← to go back)",
-                STYLES.header_hint_style(),
-            ),
+            Span::styled("Set Name", styles.header_style()),
+            Span::styled(bindings::hints(bindings::NAME), styles.header_hint_style()),
         ]);
 
         let is_custom_selected = self.index == 0;
-        let input_marker = STYLES.input_marker(is_custom_selected, editing);
-        let input_style = STYLES.input_style(is_custom_selected, editing);
+        let input_marker = styles.input_marker(is_custom_selected, editing);
+        let input_style = styles.input_style(is_custom_selected, editing);
 
         let input_text = if self.input.value().is_empty() && !editing {
             "(enter custom name)".to_string()
@@ -442,7 +1218,7 @@ impl NameView {
 
         let separator = Line::from(Span::styled(
             "─".repeat(frame.area().width as usize),
-            STYLES.dim_style(),
+            styles.dim_style(),
         ));
 
         let mut items = Vec::new();
@@ -456,8 +1232,8 @@ impl NameView {
                 selected = Some(items.len());
             }
 
-            let marker = STYLES.selected(is_selected);
-            let style = STYLES.normal_item_style(is_selected, is_current);
+            let marker = styles.selected(is_selected);
+            let style = styles.normal_item_style(is_selected, is_current);
 
             items.push(ListItem::new(Span::styled(
                 format!("{marker} {name}"),
@@ -532,7 +1308,7 @@ impl ConfirmView {
         ViewEvent::None
     }
 
-    fn draw(&mut self, state: &State, frame: &mut Frame) {
+    fn draw(&mut self, state: &State, styles: &Styles, frame: &mut Frame) {
         let picked_count = state.picked();
         let total_count = state.catalogs.len();
         let missing = total_count.saturating_sub(picked_count);
@@ -546,17 +1322,17 @@ impl ConfirmView {
         ])
         .split(area);
 
-        let header = Line::from(vec![Span::styled("⚠ Warning", STYLES.warning_style())]);
+        let header = Line::from(vec![Span::styled("⚠ Warning", styles.warning_style())]);
 
         let message = Line::from(vec![Span::styled(
             format!("Selection incomplete: {missing} catalog(s) not selected."),
-            STYLES.warning_text_style(),
+            styles.warning_text_style(),
         )]);
 
         let prompt = Line::from("Continue anyway? ");
 
-        let no_style = STYLES.button_style(!self.selected, false);
-        let yes_style = STYLES.button_style(self.selected, true);
+        let no_style = styles.button_style(!self.selected, false);
+        let yes_style = styles.button_style(self.selected, true);
 
         let buttons = Line::from(vec![
             Span::styled("[No/n]", no_style),
@@ -571,56 +1347,275 @@ impl ConfirmView {
     }
 }
 
+#[derive(Default)]
+struct ProblemsView {
+    /// Number of lines scrolled.
+    scroll: usize,
+    /// Number of lines visible when last drawn.
+    page: usize,
+}
+
+impl ProblemsView {
+    /// The number of lines used to draw all problems.
+    fn lines(state: &State) -> usize {
+        state
+            .problems
+            .values()
+            .map(|problems| problems.len().saturating_add(1))
+            .sum()
+    }
+
+    fn update(&mut self, key: KeyEvent, state: &mut State) -> ViewEvent {
+        use KeyCode::{Char, Down, Esc, Left, Up};
+
+        let last = Self::lines(state).saturating_sub(self.page);
+
+        if let Some(step) = page_step(key, self.page) {
+            self.scroll = self.scroll.saturating_add_signed(step).min(last);
+            return ViewEvent::None;
+        }
+
+        match key.code {
+            Up | Char('k') => {
+                self.scroll = self.scroll.saturating_sub(1);
+            }
+            Down | Char('j') => {
+                self.scroll = self.scroll.saturating_add(1).min(last);
+            }
+            Left | Char('h' | 'e' | 'q') | Esc => {
+                return ViewEvent::PopView;
+            }
+            _ => {}
+        }
+
+        ViewEvent::None
+    }
+
+    fn draw(&mut self, state: &State, styles: &Styles, frame: &mut Frame) {
+        let mut lines = Vec::new();
+
+        for (dir, problems) in &state.problems {
+            lines.push(Line::from(Span::styled(
+                dir.display().to_string(),
+                styles.header_style(),
+            )));
+
+            for problem in problems {
+                lines.push(Line::from(Span::styled(
+                    format!("  {problem}"),
+                    styles.warning_text_style(),
+                )));
+            }
+        }
+
+        if lines.is_empty() {
+            lines.push(Line::from(Span::styled(
+                "No problems found",
+                styles.dim_style(),
+            )));
+        }
+
+        let mut scrollbar_state = ScrollbarState::new(lines.len()).position(self.scroll);
+
+        let header = Line::from(vec![
+            Span::styled(
+                format!(
+                    "Problems in {} {}",
+                    state.problems.len(),
+                    pluralize(state.problems.len(), "book", "books")
+                ),
+                styles.header_style(),
+            ),
+            Span::styled(
+                bindings::hints(bindings::PROBLEMS),
+                styles.header_hint_style(),
+            ),
+        ]);
+
+        let scroll = u16::try_from(self.scroll).unwrap_or(u16::MAX);
+        let paragraph = Paragraph::new(lines).scroll((scroll, 0));
+        let scrollbar = Scrollbar::new(ScrollbarOrientation::VerticalRight);
+
+        let area = frame.area();
+        let layout = Layout::vertical([Constraint::Length(1), Constraint::Min(1)]).split(area);
+
+        self.page = usize::from(layout[1].height).max(1);
+        frame.render_widget(header, layout[0]);
+        frame.render_widget(paragraph, layout[1]);
+        frame.render_stateful_widget(scrollbar, layout[1], &mut scrollbar_state);
+    }
+}
+
 enum View {
     Catalogs(CatalogsView),
     Books(BooksView),
     Name(NameView),
     Confirm(ConfirmView),
+    Problems(ProblemsView),
+}
+
+impl View {
+    /// The key bindings of the view.
+    fn bindings(&self) -> &'static [Binding] {
+        match self {
+            View::Catalogs(..) => bindings::CATALOGS,
+            View::Books(..) => bindings::BOOKS,
+            View::Name(..) => bindings::NAME,
+            View::Confirm(..) => bindings::CONFIRM,
+            View::Problems(..) => bindings::PROBLEMS,
+        }
+    }
+
+    /// Handle a key press, where `wrap` indicates that moving past the end of
+    /// a list wraps around.
+    fn update(&mut self, key: KeyEvent, state: &mut State, wrap: bool) -> ViewEvent {
+        match self {
+            View::Catalogs(v) => v.update(key, state, wrap),
+            View::Books(v) => v.update(key, state, wrap),
+            View::Name(v) => v.update(key, state, wrap),
+            View::Confirm(v) => v.update(key, state),
+            View::Problems(v) => v.update(key, state),
+        }
+    }
+
+    /// Handle a mouse click at the given position.
+    fn click(&mut self, position: Position, state: &mut State) -> ViewEvent {
+        match self {
+            View::Catalogs(v) => v.click(position, state),
+            View::Books(v) => v.click(position, state),
+            _ => ViewEvent::None,
+        }
+    }
+
+    /// Whether the view is currently capturing text input.
+    fn is_editing(&self) -> bool {
+        match self {
+            View::Catalogs(v) => v.search.is_some() || v.confirm_clear,
+            View::Books(v) => v.search.is_some(),
+            View::Name(v) => v.editing && v.index == 0,
+            _ => false,
+        }
+    }
 }
 
 /// The interactive application of bookvert.
 #[derive(Default)]
 pub struct App {
     views: Vec<View>,
+    /// Whether the help overlay is shown.
+    help: bool,
+    /// Stack of changes to picks which can be undone, each recording the
+    /// catalog index and its previous pick.
+    undo: Vec<Vec<(usize, Option<usize>)>>,
+    /// Styles used to draw views.
+    styles: Styles,
+    /// Whether moving past the end of a list wraps around.
+    wrap: bool,
 }
 
 impl App {
+    /// Construct a new application drawn using the given theme.
+    pub(crate) fn new(theme: Theme, wrap: bool) -> Self {
+        Self {
+            styles: Styles::new(theme),
+            wrap,
+            ..Self::default()
+        }
+    }
+
     /// Run the interactive application.
     pub fn run(&mut self, state: &mut State) -> Result<bool> {
         self.views.clear();
         self.views.push(View::Catalogs(CatalogsView::default()));
 
         let mut terminal = ratatui::init();
+        execute!(io::stdout(), EnableMouseCapture)?;
 
         let outcome = loop {
             let Some(view) = self.views.last_mut() else {
                 break false;
             };
 
-            terminal.draw(|frame| match view {
-                View::Catalogs(v) => v.draw(state, frame),
-                View::Books(v) => v.draw(state, frame),
-                View::Name(v) => v.draw(state, frame),
-                View::Confirm(v) => v.draw(state, frame),
+            let help = self.help.then(|| view.bindings());
+
+            terminal.draw(|frame| {
+                match view {
+                    View::Catalogs(v) => v.draw(state, &self.styles, frame),
+                    View::Books(v) => v.draw(state, &self.styles, frame),
+                    View::Name(v) => v.draw(state, &self.styles, frame),
+                    View::Confirm(v) => v.draw(state, &self.styles, frame),
+                    View::Problems(v) => v.draw(state, &self.styles, frame),
+                }
+
+                if let Some(bindings) = help {
+                    draw_help(bindings, &self.styles, frame);
+                }
             })?;
 
-            let e = event::read()?;
+            let before = state.catalogs.iter().map(|c| c.picked).collect::<Vec<_>>();
 
-            let Event::Key(key) = e else {
-                continue;
-            };
+            let ev = match event::read()? {
+                Event::Key(key) => {
+                    if key.kind != KeyEventKind::Press {
+                        continue;
+                    }
 
-            if key.kind != KeyEventKind::Press {
-                continue;
-            }
+                    if self.help {
+                        self.help = false;
+                        continue;
+                    }
+
+                    if key.code == KeyCode::Char('?') && !view.is_editing() {
+                        self.help = true;
+                        continue;
+                    }
+
+                    // Plain `u` undoes, Ctrl-u is left for half-page scrolling in
+                    // `page_step`.
+                    if key.code == KeyCode::Char('u')
+                        && !key.modifiers.contains(KeyModifiers::CONTROL)
+                        && !view.is_editing()
+                    {
+                        self.undo(state);
+                        continue;
+                    }
 
-            let ev = match view {
-                View::Catalogs(v) => v.update(key, state),
-                View::Books(v) => v.update(key, state),
-                View::Name(v) => v.update(key, state),
-                View::Confirm(v) => v.update(key, state),
+                    if key.code == KeyCode::Char('w') && !view.is_editing() {
+                        self.wrap = !self.wrap;
+                        continue;
+                    }
+
+                    view.update(key, state, self.wrap)
+                }
+                Event::Mouse(mouse) if !self.help && !view.is_editing() => match mouse.kind {
+                    MouseEventKind::Down(MouseButton::Left) => {
+                        view.click(Position::new(mouse.column, mouse.row), state)
+                    }
+                    // Scrolling never wraps around, since it's easy to
+                    // overshoot with a wheel.
+                    MouseEventKind::ScrollUp => {
+                        view.update(KeyEvent::from(KeyCode::Up), state, false)
+                    }
+                    MouseEventKind::ScrollDown => {
+                        view.update(KeyEvent::from(KeyCode::Down), state, false)
+                    }
+                    _ => continue,
+                },
+                _ => continue,
             };
 
+            let changes = before
+                .into_iter()
+                .zip(&state.catalogs)
+                .enumerate()
+                .filter(|(_, (before, c))| *before != c.picked)
+                .map(|(i, (before, _))| (i, before))
+                .collect::<Vec<_>>();
+
+            if !changes.is_empty() {
+                self.undo.push(changes);
+            }
+
             match ev {
                 ViewEvent::PushView(view) => {
                     self.views.push(view);
@@ -632,10 +1627,8 @@ impl App {
                     self.views.pop();
 
                     if let Some(View::Catalogs(v)) = self.views.last_mut()
-                        && let Some(category) =
-                            state.catalogs.iter().position(|c| c.picked.is_none())
+                        && let Some(category) = v.select_next_unpicked(state)
                     {
-                        v.index = category.saturating_add(2);
                         self.views.push(View::Books(BooksView::new(category, 0)));
                     }
                 }
@@ -646,9 +1639,148 @@ impl App {
             }
         };
 
+        execute!(io::stdout(), DisableMouseCapture)?;
         ratatui::restore();
         Ok(outcome)
     }
+
+    /// Undo the most recent change to picks, returning to the catalogs view
+    /// with the affected catalog selected.
+    fn undo(&mut self, state: &mut State) {
+        let Some(changes) = self.undo.pop() else {
+            return;
+        };
+
+        for &(category, picked) in &changes {
+            if let Some(c) = state.catalogs.get_mut(category) {
+                c.picked = picked;
+            }
+        }
+
+        self.views.truncate(1);
+
+        if let Some(View::Catalogs(v)) = self.views.last_mut()
+            && let Some(&(category, _)) = changes.first()
+        {
+            v.select_catalog(state, category);
+        }
+    }
+}
+
+/// Draw the help overlay listing the given key bindings.
+fn draw_help(bindings: &[Binding], styles: &Styles, frame: &mut Frame) {
+    let width = bindings
+        .iter()
+        .map(|b| b.keys.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    let mut lines = Vec::new();
+
+    for b in bindings {
+        lines.push(Line::from(vec![
+            Span::styled(format!("{:width$}", b.keys), styles.header_style()),
+            Span::raw(format!("  {}", b.action)),
+        ]));
+    }
+
+    lines.push(Line::default());
+    lines.push(Line::from(Span::styled(
+        "Press any key to close",
+        styles.dim_style(),
+    )));
+
+    let content_width = lines.iter().map(Line::width).max().unwrap_or(0);
+    let height = u16::try_from(lines.len().saturating_add(2)).unwrap_or(u16::MAX);
+    let width = u16::try_from(content_width.saturating_add(4)).unwrap_or(u16::MAX);
+
+    let area = centered(frame.area(), width, height);
+    let block = Block::bordered().title(Span::styled(" Help ", styles.header_style()));
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+/// Compute a centered area of the given size.
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let [area] = Layout::horizontal([Constraint::Length(width)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [area] = Layout::vertical([Constraint::Length(height)])
+        .flex(Flex::Center)
+        .areas(area);
+    area
+}
+
+/// Open a directory in the file manager of the system without waiting for it
+/// to exit.
+fn open_dir(dir: &Path) -> io::Result<()> {
+    let program = if cfg!(target_os = "macos") {
+        "open"
+    } else if cfg!(windows) {
+        "explorer"
+    } else {
+        "xdg-open"
+    };
+
+    let mut child = Command::new(program)
+        .arg(dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    // Reap the process in the background once it exits.
+    thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// Move an index up by one, wrapping around to `last` if `wrap` is set.
+fn step_up(index: usize, last: usize, wrap: bool) -> usize {
+    if wrap && index == 0 {
+        last
+    } else {
+        index.saturating_sub(1)
+    }
+}
+
+/// Move an index down by one up to `last`, wrapping around to the start if
+/// `wrap` is set.
+fn step_down(index: usize, last: usize, wrap: bool) -> usize {
+    if wrap && index >= last {
+        0
+    } else {
+        index.saturating_add(1).min(last)
+    }
+}
+
+/// Returns the number of items to move for a paging key, where `page` is the
+/// number of items that fit in the list.
+///
+/// PageUp/PageDown moves a full page, while Ctrl-u/Ctrl-d moves half a page.
+fn page_step(key: KeyEvent, page: usize) -> Option<isize> {
+    let page = isize::try_from(page).unwrap_or(isize::MAX);
+    let half = (page / 2).max(1);
+
+    match key.code {
+        KeyCode::PageUp => Some(-page),
+        KeyCode::PageDown => Some(page),
+        KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => Some(-half),
+        KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => Some(half),
+        _ => None,
+    }
+}
+
+/// Test if all characters in the needle appear in order in the haystack,
+/// ignoring case.
+fn fuzzy_match(haystack: &str, needle: &str) -> bool {
+    let mut haystack = haystack.chars().flat_map(char::to_lowercase);
+
+    needle
+        .chars()
+        .flat_map(char::to_lowercase)
+        .filter(|c| !c.is_whitespace())
+        .all(|n| haystack.any(|h| h == n))
 }
 
 fn pluralize<'a>(count: usize, singular: &'a str, plural: &'a str) -> &'a str {
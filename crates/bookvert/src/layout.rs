@@ -0,0 +1,106 @@
+//! Deriving metadata from the directories a book is stored in, as configured
+//! with `--path-layout`.
+
+use core::str::FromStr;
+
+use std::path::{Component, Path};
+
+use anyhow::{Result, anyhow};
+
+/// A field which is extracted from a path component.
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    Author,
+    Series,
+    Volume,
+}
+
+/// A pattern like `{author}/{series}/{volume}` which is matched against the
+/// trailing components of the path of a book, where the last component is the
+/// book itself. Components which are not of interest are matched with `*`.
+#[derive(Debug, Clone)]
+pub(crate) struct Layout {
+    fields: Vec<Option<Field>>,
+}
+
+impl FromStr for Layout {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut fields = Vec::new();
+
+        for component in s.split('/') {
+            let field = match component.trim() {
+                "*" => None,
+                "{author}" => Some(Field::Author),
+                "{series}" => Some(Field::Series),
+                "{volume}" => Some(Field::Volume),
+                other => {
+                    return Err(anyhow!(
+                        "Invalid path layout component '{other}', expected one of {{author}}, {{series}}, {{volume}} or *"
+                    ));
+                }
+            };
+
+            fields.push(field);
+        }
+
+        Ok(Self { fields })
+    }
+}
+
+impl Layout {
+    /// Extract parts from the path of a book, where `name` is the name of the
+    /// book which takes the place of the last component.
+    ///
+    /// Books which are stored fewer directories deep than the layout only get
+    /// the fields which line up with the end of their path.
+    pub(crate) fn parts(&self, dir: &Path, name: &str) -> Parts {
+        let mut components = dir
+            .parent()
+            .into_iter()
+            .flat_map(|p| p.components())
+            .filter_map(|c| match c {
+                Component::Normal(c) => c.to_str(),
+                _ => None,
+            })
+            .rev();
+
+        let mut parts = Parts::default();
+        let mut fields = self.fields.iter().rev();
+
+        if let Some(field) = fields.next() {
+            parts.set(*field, name);
+        }
+
+        for (field, component) in fields.zip(components.by_ref()) {
+            parts.set(*field, component);
+        }
+
+        parts
+    }
+}
+
+/// Metadata about a book derived from its path.
+#[derive(Debug, Default)]
+pub(crate) struct Parts {
+    /// The author of the book.
+    pub(crate) author: Option<String>,
+    /// The series the book is part of.
+    pub(crate) series: Option<String>,
+    /// The volume of the series, which is used as the title of the book.
+    pub(crate) volume: Option<String>,
+}
+
+impl Parts {
+    fn set(&mut self, field: Option<Field>, value: &str) {
+        let value = Some(value.to_string());
+
+        match field {
+            Some(Field::Author) => self.author = value,
+            Some(Field::Series) => self.series = value,
+            Some(Field::Volume) => self.volume = value,
+            None => {}
+        }
+    }
+}
@@ -5,7 +5,10 @@
 //! A tool to perform batch conversion of books.
 //!
 //! This is a .cbz batch conversion tool which scans directories for image
-//! files, groups them by their directory and creates books out of them.
+//! files, groups them by their directory and creates books out of them. Zip,
+//! .cbz, tar and .cbt archives, which may be compressed with gzip, are
+//! treated as books of their own, where pages are read directly from the
+//! archive.
 //!
 //! You can install bookvert with cargo:
 //!
@@ -18,7 +21,7 @@
 //! ## Usage
 //!
 //! The idea is that you have a group of semi-structured directories containing
-//! lexically sorted image files and you run bookvert against it. Like this:
+//! numbered image files and you run bookvert against it. Like this:
 //!
 //! * `That time I sorted books/Chapter 1/`
 //! * `That time I sorted books/Chapter 1 - Fix/`
@@ -80,8 +83,22 @@
 mod interactive;
 use self::interactive::App;
 
+mod natural;
+mod pdf;
+mod picks;
+mod plan;
+mod settings;
+mod size;
+
 mod state;
-use self::state::{Book, Catalog, Page, State};
+use self::state::{Book, Catalog, Extra, Page, Source, State};
 
+mod bindings;
 pub mod cli;
+mod epub;
+mod format;
+mod gzip;
+mod layout;
 mod styles;
+mod theme;
+mod transform;
@@ -0,0 +1,152 @@
+use core::cmp::Ordering;
+
+use std::path::Path;
+
+/// Compare two paths component by component using [`compare`].
+pub(crate) fn compare_paths(a: &Path, b: &Path) -> Ordering {
+    let mut a = a.components();
+    let mut b = b.components();
+
+    loop {
+        let (a, b) = match (a.next(), b.next()) {
+            (Some(a), Some(b)) => (a, b),
+            (a, b) => return a.is_some().cmp(&b.is_some()),
+        };
+
+        let a = a.as_os_str().to_string_lossy();
+        let b = b.as_os_str().to_string_lossy();
+
+        match compare(&a, &b) {
+            Ordering::Equal => {}
+            ordering => return ordering,
+        }
+    }
+}
+
+/// Compare two strings so that runs of digits are ordered by their numerical
+/// value, so that `page2.jpg` comes before `page10.jpg`.
+///
+/// Strings which only differ in leading zeros are ordered by the width of the
+/// first number which differs, so that the order is total.
+pub(crate) fn compare(mut a: &str, mut b: &str) -> Ordering {
+    let mut width = Ordering::Equal;
+
+    loop {
+        let (Some(c), Some(d)) = (a.chars().next(), b.chars().next()) else {
+            return a.len().cmp(&b.len()).then(width);
+        };
+
+        if c.is_ascii_digit() && d.is_ascii_digit() {
+            let (n, rest_a) = split_digits(a);
+            let (m, rest_b) = split_digits(b);
+
+            let n_value = n.trim_start_matches('0');
+            let m_value = m.trim_start_matches('0');
+
+            let ordering = n_value
+                .len()
+                .cmp(&m_value.len())
+                .then_with(|| n_value.cmp(m_value));
+
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+
+            width = width.then(n.len().cmp(&m.len()));
+            a = rest_a;
+            b = rest_b;
+            continue;
+        }
+
+        if c != d {
+            return c.cmp(&d);
+        }
+
+        a = &a[c.len_utf8()..];
+        b = &b[d.len_utf8()..];
+    }
+}
+
+/// Split a string into its leading run of ASCII digits and the rest.
+fn split_digits(s: &str) -> (&str, &str) {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    s.split_at(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cmp::Ordering;
+
+    use std::path::Path;
+
+    use super::{compare, compare_paths};
+
+    fn sorted(names: &[&'static str]) -> Vec<&'static str> {
+        let mut names = names.to_vec();
+        names.sort_by(|a, b| compare(a, b));
+        names
+    }
+
+    #[test]
+    fn mixed_width_numbers() {
+        assert_eq!(
+            sorted(&[
+                "page10.jpg",
+                "page2.jpg",
+                "page1.jpg",
+                "page100.jpg",
+                "page20.jpg"
+            ]),
+            [
+                "page1.jpg",
+                "page2.jpg",
+                "page10.jpg",
+                "page20.jpg",
+                "page100.jpg"
+            ]
+        );
+
+        assert_eq!(
+            sorted(&["10.png", "009.png", "1.png", "02.png", "0100.png"]),
+            ["1.png", "02.png", "009.png", "10.png", "0100.png"]
+        );
+
+        assert_eq!(
+            sorted(&["v2c10p3.jpg", "v2c9p12.jpg", "v10c1p1.jpg", "v2c10p10.jpg"]),
+            ["v2c9p12.jpg", "v2c10p3.jpg", "v2c10p10.jpg", "v10c1p1.jpg"]
+        );
+    }
+
+    #[test]
+    fn leading_zeros() {
+        // Numbers are equal in value, so the narrowest comes first.
+        assert_eq!(compare("1.jpg", "001.jpg"), Ordering::Less);
+        assert_eq!(compare("001.jpg", "01.jpg"), Ordering::Greater);
+        assert_eq!(compare("001.jpg", "001.jpg"), Ordering::Equal);
+
+        // The value of a later number takes precedence over the width of an
+        // earlier one.
+        assert_eq!(compare("01-2.jpg", "1-1.jpg"), Ordering::Greater);
+        assert_eq!(compare("0.jpg", "00.jpg"), Ordering::Less);
+    }
+
+    #[test]
+    fn text() {
+        assert_eq!(compare("a.jpg", "b.jpg"), Ordering::Less);
+        assert_eq!(compare("page", "page1"), Ordering::Less);
+        assert_eq!(compare("page1", "page"), Ordering::Greater);
+        assert_eq!(compare("cover.jpg", "1.jpg"), Ordering::Greater);
+    }
+
+    #[test]
+    fn paths() {
+        let cmp = |a: &str, b: &str| compare_paths(Path::new(a), Path::new(b));
+
+        assert_eq!(cmp("ch2/p10.jpg", "ch10/p1.jpg"), Ordering::Less);
+        assert_eq!(cmp("ch10/p1.jpg", "ch2/p10.jpg"), Ordering::Greater);
+        assert_eq!(cmp("ch2/p2.jpg", "ch2/p10.jpg"), Ordering::Less);
+        assert_eq!(cmp("ch2", "ch2/p1.jpg"), Ordering::Less);
+        // Separators aren't compared as characters.
+        assert_eq!(cmp("a/b", "a-b"), compare("a", "a-b"));
+    }
+}
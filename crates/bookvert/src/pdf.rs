@@ -0,0 +1,341 @@
+use core::fmt::Write as _;
+
+use std::io::{Cursor, Write};
+
+use anyhow::{Result, anyhow};
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::metadata::Orientation;
+use image::{DynamicImage, ExtendedColorType, ImageDecoder, ImageFormat, ImageReader};
+
+use crate::layout::Parts;
+use crate::transform::Content;
+
+/// The object number of the document catalog.
+const CATALOG: usize = 1;
+/// The object number of the page tree.
+const PAGES: usize = 2;
+/// The object number of the document information dictionary.
+const INFO: usize = 3;
+
+/// The sum of the standard JPEG luminance quantization table, which tables of
+/// other qualities are scaled from.
+const STANDARD_LUMINANCE_SUM: u32 = 3688;
+
+/// An image prepared for embedding in a PDF.
+struct Image {
+    width: u32,
+    height: u32,
+    color_space: &'static str,
+    filter: &'static str,
+    data: Vec<u8>,
+}
+
+/// Assemble the pages of a book into a `.pdf` document with one image per
+/// page, where each page is sized to its image.
+///
+/// Pages which can't be decoded as images are skipped and described in
+/// `skipped`. With `rtl` the document is marked as read from right to left.
+///
+/// With `jpeg_quality` pages are embedded as JPEGs of at most the given
+/// quality, rather than losslessly.
+pub(crate) fn write(
+    contents: &[Content<'_>],
+    title: &str,
+    parts: &Parts,
+    rtl: bool,
+    jpeg_quality: Option<u8>,
+    skipped: &mut Vec<String>,
+) -> Result<Vec<u8>> {
+    let mut w = Writer::default();
+    let mut kids = Vec::new();
+
+    w.out.extend_from_slice(b"%PDF-1.7\n%\xe2\xe3\xcf\xd3\n");
+
+    for content in contents {
+        let image = match image(&content.data, jpeg_quality) {
+            Ok(image) => image,
+            Err(e) => {
+                skipped.push(format!("{}: {e}", content.page.path.display()));
+                continue;
+            }
+        };
+
+        let image_id = w.next_id();
+        w.stream(
+            image_id,
+            &format!(
+                "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /{} /BitsPerComponent 8 /Filter /{}",
+                image.width, image.height, image.color_space, image.filter
+            ),
+            &image.data,
+        );
+
+        let content = format!("q {} 0 0 {} 0 0 cm /Im0 Do Q", image.width, image.height);
+
+        let content_id = w.next_id();
+        w.stream(content_id, "", content.as_bytes());
+
+        let page_id = w.next_id();
+        w.object(
+            page_id,
+            &format!(
+                "<< /Type /Page /Parent {PAGES} 0 R /MediaBox [0 0 {} {}] /Resources << /XObject << /Im0 {image_id} 0 R >> >> /Contents {content_id} 0 R >>",
+                image.width, image.height
+            ),
+        );
+
+        kids.push(page_id);
+    }
+
+    let mut kids_list = String::new();
+
+    for id in &kids {
+        _ = write!(kids_list, "{id} 0 R ");
+    }
+
+    w.object(
+        PAGES,
+        &format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids_list.trim_end(),
+            kids.len()
+        ),
+    );
+    // Viewers which honor the reading direction present spreads from right to
+    // left.
+    let direction = if rtl {
+        " /ViewerPreferences << /Direction /R2L >>"
+    } else {
+        ""
+    };
+
+    w.object(
+        CATALOG,
+        &format!("<< /Type /Catalog /Pages {PAGES} 0 R{direction} >>"),
+    );
+    let mut info = format!("/Title {}", text_string(title));
+
+    if let Some(author) = &parts.author {
+        _ = write!(info, " /Author {}", text_string(author));
+    }
+
+    if let Some(series) = &parts.series {
+        _ = write!(info, " /Subject {}", text_string(series));
+    }
+
+    w.object(INFO, &format!("<< {info} /Producer (bookvert) >>"));
+
+    Ok(w.finish())
+}
+
+/// Prepare an image for embedding.
+///
+/// JPEG images are embedded as-is unless they have to be rotated or exceed
+/// `jpeg_quality`, while other formats are decoded and compressed.
+fn image(data: &[u8], jpeg_quality: Option<u8>) -> Result<Image> {
+    let reader = ImageReader::new(Cursor::new(data)).with_guessed_format()?;
+
+    let Some(format) = reader.format() else {
+        return Err(anyhow!("Unrecognized image format"));
+    };
+
+    let mut decoder = reader.into_decoder()?;
+    let orientation = decoder.orientation()?;
+
+    // JPEGs whose quality can't be estimated are assumed to be efficient
+    // already.
+    let efficient = match jpeg_quality {
+        Some(target) => estimate_quality(data).is_none_or(|quality| quality <= target),
+        None => true,
+    };
+
+    if format == ImageFormat::Jpeg && orientation == Orientation::NoTransforms && efficient {
+        let color_space = match decoder.original_color_type() {
+            ExtendedColorType::L8 => Some("DeviceGray"),
+            ExtendedColorType::Rgb8 => Some("DeviceRGB"),
+            _ => None,
+        };
+
+        if let Some(color_space) = color_space {
+            let (width, height) = decoder.dimensions();
+
+            return Ok(Image {
+                width,
+                height,
+                color_space,
+                filter: "DCTDecode",
+                data: data.to_vec(),
+            });
+        }
+    }
+
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+
+    let (width, height) = (image.width(), image.height());
+
+    if let Some(quality) = jpeg_quality {
+        let mut data = Vec::new();
+        let encoder = JpegEncoder::new_with_quality(&mut data, quality);
+
+        let color_space = if image.color().has_color() {
+            image.to_rgb8().write_with_encoder(encoder)?;
+            "DeviceRGB"
+        } else {
+            image.to_luma8().write_with_encoder(encoder)?;
+            "DeviceGray"
+        };
+
+        return Ok(Image {
+            width,
+            height,
+            color_space,
+            filter: "DCTDecode",
+            data,
+        });
+    }
+
+    let (color_space, pixels) = if image.color().has_color() {
+        ("DeviceRGB", image.into_rgb8().into_raw())
+    } else {
+        ("DeviceGray", image.into_luma8().into_raw())
+    };
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&pixels)?;
+
+    Ok(Image {
+        width,
+        height,
+        color_space,
+        filter: "FlateDecode",
+        data: encoder.finish()?,
+    })
+}
+
+/// Estimate the quality a JPEG was encoded with from its luminance
+/// quantization table, assuming that it's scaled from the standard table like
+/// most encoders do.
+fn estimate_quality(data: &[u8]) -> Option<u8> {
+    let mut rest = data.strip_prefix(&[0xff, 0xd8])?;
+
+    loop {
+        let [0xff, marker, a, b, ..] = *rest else {
+            return None;
+        };
+
+        let len = usize::from(u16::from_be_bytes([a, b]));
+        let segment = rest.get(4..len + 2)?;
+
+        match marker {
+            // Define quantization table.
+            0xdb => {
+                let (&info, table) = segment.split_first()?;
+
+                // Only the luminance table is of interest.
+                if info & 0x0f == 0 {
+                    let sum = if info >> 4 == 0 {
+                        table.get(..64)?.iter().map(|&q| u32::from(q)).sum::<u32>()
+                    } else {
+                        table
+                            .get(..128)?
+                            .chunks_exact(2)
+                            .map(|q| u32::from(u16::from_be_bytes([q[0], q[1]])))
+                            .sum::<u32>()
+                    };
+
+                    let scale = sum * 100 / STANDARD_LUMINANCE_SUM;
+
+                    let quality = if scale <= 100 {
+                        (200 - scale) / 2
+                    } else {
+                        5000 / scale
+                    };
+
+                    return u8::try_from(quality.clamp(1, 100)).ok();
+                }
+            }
+            // Start of scan, after which there are no more tables.
+            0xda => return None,
+            _ => {}
+        }
+
+        rest = rest.get(len + 2..)?;
+    }
+}
+
+/// Encode a PDF text string as UTF-16 so that any title can be represented.
+fn text_string(s: &str) -> String {
+    let mut out = String::from("<FEFF");
+
+    for unit in s.encode_utf16() {
+        _ = write!(out, "{unit:04X}");
+    }
+
+    out.push('>');
+    out
+}
+
+/// Incrementally writes the objects of a PDF and keeps track of their
+/// offsets for the cross-reference table.
+struct Writer {
+    out: Vec<u8>,
+    offsets: Vec<usize>,
+}
+
+impl Default for Writer {
+    fn default() -> Self {
+        Self {
+            out: Vec::new(),
+            // Object numbers of fixed objects are reserved up front.
+            offsets: vec![0; INFO],
+        }
+    }
+}
+
+impl Writer {
+    /// Allocate the number of a new object.
+    fn next_id(&mut self) -> usize {
+        self.offsets.push(0);
+        self.offsets.len()
+    }
+
+    /// Write an object with the given body.
+    fn object(&mut self, id: usize, body: &str) {
+        self.offsets[id - 1] = self.out.len();
+        _ = write!(self.out, "{id} 0 obj\n{body}\nendobj\n");
+    }
+
+    /// Write a stream object with the given extra dictionary entries.
+    fn stream(&mut self, id: usize, dict: &str, data: &[u8]) {
+        self.offsets[id - 1] = self.out.len();
+        _ = write!(
+            self.out,
+            "{id} 0 obj\n<< {dict} /Length {} >>\nstream\n",
+            data.len()
+        );
+        self.out.extend_from_slice(data);
+        self.out.extend_from_slice(b"\nendstream\nendobj\n");
+    }
+
+    /// Write the cross-reference table and trailer.
+    fn finish(mut self) -> Vec<u8> {
+        let xref = self.out.len();
+        let size = self.offsets.len() + 1;
+
+        _ = write!(self.out, "xref\n0 {size}\n0000000000 65535 f \n");
+
+        for offset in &self.offsets {
+            _ = writeln!(self.out, "{offset:010} 00000 n ");
+        }
+
+        _ = write!(
+            self.out,
+            "trailer\n<< /Size {size} /Root {CATALOG} 0 R /Info {INFO} 0 R >>\nstartxref\n{xref}\n%%EOF\n"
+        );
+
+        self.out
+    }
+}
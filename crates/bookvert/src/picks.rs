@@ -0,0 +1,100 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+use crate::State;
+
+/// Picks made in an interactive session which are persisted across runs.
+#[derive(Default, Serialize, Deserialize)]
+pub(crate) struct Picks {
+    /// The canonical paths which were scanned when the picks were made.
+    paths: Vec<PathBuf>,
+    /// Catalog numbers mapped to the name of the picked book.
+    picks: BTreeMap<u32, String>,
+}
+
+impl Picks {
+    /// Construct picks from the current state.
+    pub(crate) fn new(paths: Vec<PathBuf>, state: &State) -> Self {
+        let picks = state
+            .catalogs
+            .iter()
+            .filter_map(|c| Some((c.number, c.selected()?.name.clone())))
+            .collect();
+
+        Self { paths, picks }
+    }
+
+    /// Apply the picks to catalogs which have not been picked yet, returning
+    /// the number of picks restored.
+    ///
+    /// Picks are matched by catalog number and book name, and are only applied
+    /// if the same paths are being scanned.
+    pub(crate) fn apply(&self, paths: &[PathBuf], state: &mut State) -> usize {
+        if self.paths != paths {
+            return 0;
+        }
+
+        let mut restored = 0;
+
+        for c in &mut state.catalogs {
+            if c.picked.is_some() {
+                continue;
+            }
+
+            let Some(name) = self.picks.get(&c.number) else {
+                continue;
+            };
+
+            if let Some(index) = c.books.iter().position(|b| b.name == *name) {
+                c.picked = Some(index);
+                restored += 1;
+            }
+        }
+
+        restored
+    }
+
+    /// Load picks from the given path, if it exists.
+    pub(crate) fn load(path: &Path) -> Result<Option<Self>> {
+        let contents = match fs::read(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| anyhow!("{}: Failed to read", path.display())),
+        };
+
+        let picks = serde_json::from_slice(&contents)
+            .with_context(|| anyhow!("{}: Failed to parse", path.display()))?;
+
+        Ok(Some(picks))
+    }
+
+    /// Save picks to the given path.
+    pub(crate) fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                anyhow!("Failed to create parent directory {}", parent.display())
+            })?;
+        }
+
+        let contents = serde_json::to_vec_pretty(self)?;
+        fs::write(path, contents).with_context(|| anyhow!("{}: Failed to write", path.display()))
+    }
+}
+
+/// The default path where picks are persisted.
+pub(crate) fn default_path() -> Option<PathBuf> {
+    let mut path = match env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => env::home_dir()?.join(".cache"),
+    };
+
+    path.push("bookvert");
+    path.push("picks.json");
+    Some(path)
+}
@@ -0,0 +1,100 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+use crate::State;
+
+/// A plan of which book to convert for each catalog.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Plan {
+    /// The name of the series, if known.
+    name: Option<String>,
+    /// The picked book for each catalog which has one.
+    picks: Vec<PlanPick>,
+}
+
+/// The book picked for a single catalog.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct PlanPick {
+    /// The catalog number.
+    number: u32,
+    /// The name of the picked book.
+    book: String,
+    /// The directory of the picked book.
+    dir: PathBuf,
+    /// The number of pages in the picked book.
+    pages: usize,
+    /// The total size of the pages in the picked book.
+    bytes: u64,
+}
+
+impl Plan {
+    /// Construct a plan from the current state.
+    pub(crate) fn new(state: &State) -> Self {
+        let picks = state
+            .catalogs
+            .iter()
+            .filter_map(|c| {
+                let book = c.selected()?;
+
+                Some(PlanPick {
+                    number: c.number,
+                    book: book.name.clone(),
+                    dir: book.dir.clone(),
+                    pages: book.pages.len(),
+                    bytes: book.bytes(),
+                })
+            })
+            .collect();
+
+        Self {
+            name: state.name.clone(),
+            picks,
+        }
+    }
+
+    /// Load a plan from the given path.
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let contents =
+            fs::read(path).with_context(|| anyhow!("{}: Failed to read", path.display()))?;
+
+        serde_json::from_slice(&contents)
+            .with_context(|| anyhow!("{}: Failed to parse", path.display()))
+    }
+
+    /// Apply the plan to the state by matching catalog numbers and book names,
+    /// returning the picks which did not match any book.
+    ///
+    /// The name of the series is only set if it isn't already known.
+    pub(crate) fn apply(&self, state: &mut State) -> Vec<(u32, &str)> {
+        if state.name.is_none() {
+            state.name = self.name.clone();
+        }
+
+        let mut unmatched = Vec::new();
+
+        for pick in &self.picks {
+            let found = state
+                .catalogs
+                .iter_mut()
+                .find(|c| c.number == pick.number)
+                .and_then(|c| {
+                    let index = c.books.iter().position(|b| b.name == pick.book)?;
+                    Some((c, index))
+                });
+
+            match found {
+                Some((c, index)) => {
+                    c.picked = Some(index);
+                }
+                None => {
+                    unmatched.push((pick.number, pick.book.as_str()));
+                }
+            }
+        }
+
+        unmatched
+    }
+}
@@ -0,0 +1,70 @@
+//! Defaults loaded from a TOML configuration file, which are overridden by
+//! options on the command line.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+
+/// The contents of a configuration file, where every field is optional.
+///
+/// ```toml
+/// output = "auto"
+/// jobs = 4
+/// theme = "dark.toml"
+/// ```
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct File {
+    /// The default for `--output`.
+    pub(crate) output: Option<String>,
+    /// The default for `--jobs`.
+    pub(crate) jobs: Option<NonZeroUsize>,
+    /// The default for `--theme`, relative to the configuration file.
+    pub(crate) theme: Option<PathBuf>,
+}
+
+impl File {
+    /// Load a configuration file. Files which don't exist are only an error
+    /// if `required` is set.
+    pub(crate) fn load(path: &Path, required: bool) -> Result<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => {
+                return Ok(Self::default());
+            }
+            Err(e) => return Err(e).with_context(|| anyhow!("{}: Failed to read", path.display())),
+        };
+
+        let mut file: Self = toml::from_str(&contents)
+            .with_context(|| anyhow!("{}: Failed to parse", path.display()))?;
+
+        if let Some(theme) = &mut file.theme
+            && let Some(dir) = path.parent()
+        {
+            *theme = dir.join(&*theme);
+        }
+
+        Ok(file)
+    }
+}
+
+/// The directory configuration files are loaded from by default.
+pub(crate) fn config_dir() -> Option<PathBuf> {
+    let mut path = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => env::home_dir()?.join(".config"),
+    };
+
+    path.push("bookvert");
+    Some(path)
+}
+
+/// The default path of the configuration file.
+pub(crate) fn default_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("config.toml"))
+}
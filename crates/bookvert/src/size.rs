@@ -0,0 +1,29 @@
+use core::fmt;
+
+/// Helper to display a size in bytes in a human readable form.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Size(pub(crate) u64);
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+        if self.0 < 1024 {
+            return write!(f, "{} B", self.0);
+        }
+
+        let mut value = self.0 as f64 / 1024.0;
+        let mut unit = UNITS[0];
+
+        for u in &UNITS[1..] {
+            if value < 1024.0 {
+                break;
+            }
+
+            value /= 1024.0;
+            unit = u;
+        }
+
+        write!(f, "{value:.1} {unit}")
+    }
+}
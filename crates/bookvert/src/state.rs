@@ -1,7 +1,31 @@
-use std::collections::BTreeSet;
-use std::fs::Metadata;
-use std::path::{Path, PathBuf};
-use std::rc::Rc;
+use core::fmt::Write as _;
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::ops::RangeInclusive;
+use std::path::{self, Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use anyhow::{Context, Result, anyhow};
+use flate2::read::GzDecoder;
+use image::ImageReader;
+use regex::Regex;
+use zip::ZipArchive;
+
+use crate::gzip;
+
+/// The range of ratios between the width of a page and the typical width of
+/// pages in a book which are considered consistent.
+const MIN_WIDTH_RATIO: f64 = 0.4;
+const MAX_WIDTH_RATIO: f64 = 2.5;
+
+/// The range of ratios between the height of a page and the typical height of
+/// pages in a book which are considered consistent.
+const MIN_HEIGHT_RATIO: f64 = 0.67;
+const MAX_HEIGHT_RATIO: f64 = 1.5;
 
 /// The state of a bookvert session.
 #[derive(Default)]
@@ -13,14 +37,85 @@ pub struct State {
     pub names: BTreeSet<String>,
     /// The detected catalogs in the session.
     pub catalogs: Vec<Catalog>,
+    /// Whether books duplicated across catalogs should be detected.
+    pub dedupe: bool,
+    /// Problems found while scanning, grouped by book directory.
+    pub problems: BTreeMap<PathBuf, Vec<String>>,
+    /// The paths which were scanned for books.
+    pub roots: Vec<PathBuf>,
+    /// Whether paths are displayed relative to the scanned path they were
+    /// found in, rather than as absolute paths.
+    pub relative_paths: bool,
 }
 
 impl State {
+    /// Record a problem with the book in the given directory.
+    pub(crate) fn problem(&mut self, dir: &Path, message: impl Into<String>) {
+        self.problems
+            .entry(dir.to_path_buf())
+            .or_default()
+            .push(message.into());
+    }
+
+    /// Get the path to display for the given path.
+    ///
+    /// Falls back to an absolute path if a relative path is requested but the
+    /// path isn't in any of the scanned paths.
+    pub(crate) fn display_path<'a>(&self, path: &'a Path) -> Cow<'a, Path> {
+        if self.relative_paths
+            && let Some(relative) = self.roots.iter().find_map(|r| path.strip_prefix(r).ok())
+        {
+            if relative.as_os_str().is_empty() {
+                return Cow::Borrowed(Path::new("."));
+            }
+
+            return Cow::Borrowed(relative);
+        }
+
+        match path::absolute(path) {
+            Ok(path) => Cow::Owned(path),
+            Err(..) => Cow::Borrowed(path),
+        }
+    }
+
     /// Count the number of catalogs which have a picked book.
     #[inline]
     pub(crate) fn picked(&self) -> usize {
         self.catalogs.iter().filter(|c| c.picked.is_some()).count()
     }
+
+    /// Returns the total number of pages and bytes across all selected books.
+    pub(crate) fn selected_totals(&self) -> (usize, u64) {
+        self.catalogs
+            .iter()
+            .filter_map(|c| c.selected())
+            .fold((0, 0), |(pages, bytes), book| {
+                (pages + book.pages.len(), bytes + book.bytes())
+            })
+    }
+
+    /// Returns the numbers of other catalogs whose selected book is a duplicate
+    /// of the book selected in the catalog at `index`.
+    ///
+    /// This is always empty unless duplicate detection is enabled.
+    pub(crate) fn duplicates(&self, index: usize) -> Vec<u32> {
+        if !self.dedupe {
+            return Vec::new();
+        }
+
+        let Some(book) = self.catalogs.get(index).and_then(|c| c.selected()) else {
+            return Vec::new();
+        };
+
+        let key = book.dedupe_key();
+
+        self.catalogs
+            .iter()
+            .enumerate()
+            .filter(|&(i, c)| i != index && c.selected().is_some_and(|b| b.dedupe_key() == key))
+            .map(|(_, c)| c.number)
+            .collect()
+    }
 }
 
 /// The state for a single catalog.
@@ -28,7 +123,7 @@ pub struct Catalog {
     /// The catalog number.
     pub number: u32,
     /// The books in the catalog.
-    pub books: Vec<Rc<Book>>,
+    pub books: Vec<Arc<Book>>,
     /// The picked book.
     pub picked: Option<usize>,
 }
@@ -39,28 +134,214 @@ impl Catalog {
     pub fn selected(&self) -> Option<&Book> {
         Some(self.books.get(self.picked?)?.as_ref())
     }
+
+    /// Returns the index of the book with the most pages, with ties broken by
+    /// the largest total size.
+    pub fn largest(&self) -> Option<usize> {
+        self.books
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, b)| (b.pages.len(), b.bytes()))
+            .map(|(i, _)| i)
+    }
+}
+
+/// Where the content of a page is stored.
+pub enum Source {
+    /// An image file in a book directory.
+    File,
+    /// An image entry in a zip archive, which is read without extracting the
+    /// archive to disk.
+    Archive {
+        /// The path of the archive.
+        archive: Arc<Path>,
+        /// The name of the entry in the archive.
+        entry: String,
+    },
+    /// A file in a tar archive, possibly compressed with gzip. Tar archives
+    /// have no central directory, so the file is read from the offset it was
+    /// found at when the archive was scanned.
+    Tar {
+        /// The path of the archive.
+        archive: Arc<Path>,
+        /// The name of the entry in the archive.
+        entry: String,
+        /// The offset of the file in the uncompressed archive.
+        offset: u64,
+        /// The size of the file.
+        size: u64,
+        /// Whether the archive is compressed with gzip.
+        gzip: bool,
+    },
 }
 
 /// Data about a page.
 pub struct Page {
-    /// The filesystem name of the page.
+    /// Where the content of the page is stored.
+    pub source: Source,
+    /// The filesystem name of the page. For pages in archives this is the path
+    /// of the archive joined with the name of the entry, which is only used
+    /// for display.
     pub path: PathBuf,
     /// The name of the page.
     pub name: String,
-    /// The filesystem metadata of the page.
-    pub metadata: Metadata,
+    /// The size of the page in bytes.
+    pub size: u64,
+    /// The modification time of the page, or of the archive it's stored in.
+    pub modified: Option<SystemTime>,
+    /// The page number, which is the last number in the file name.
+    pub number: Option<u32>,
+    /// The dimensions of the page image, if they could be read.
+    pub dimensions: Option<(u32, u32)>,
+}
+
+impl Source {
+    /// Read the content of a file from this source, where `path` is the path
+    /// of the file on disk.
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        match self {
+            Source::File => {
+                fs::read(path).with_context(|| anyhow!("Failed to read file {}", path.display()))
+            }
+            Source::Archive { archive, entry } => read_entry(archive, entry)
+                .with_context(|| anyhow!("Failed to read {entry} in {}", archive.display())),
+            Source::Tar {
+                archive,
+                entry,
+                offset,
+                size,
+                gzip,
+            } => read_tar(archive, *offset, *size, *gzip)
+                .with_context(|| anyhow!("Failed to read {entry} in {}", archive.display())),
+        }
+    }
+}
+
+impl Page {
+    /// Read the content of the page.
+    pub fn read(&self) -> Result<Vec<u8>> {
+        self.source.read(&self.path)
+    }
+
+    /// Read the dimensions of the page image from its header.
+    pub fn read_dimensions(&self) -> Result<(u32, u32)> {
+        let dimensions = match &self.source {
+            Source::File => ImageReader::open(&self.path)?
+                .with_guessed_format()?
+                .into_dimensions()?,
+            Source::Archive { .. } | Source::Tar { .. } => {
+                ImageReader::new(Cursor::new(self.read()?))
+                    .with_guessed_format()?
+                    .into_dimensions()?
+            }
+        };
+
+        Ok(dimensions)
+    }
+}
+
+/// A file stored with a book which isn't a page, like a `.nfo` file or
+/// `Thumbs.db`.
+pub struct Extra {
+    /// Where the content of the file is stored.
+    pub source: Source,
+    /// The filesystem name of the file, like [`Page::path`].
+    pub path: PathBuf,
+}
+
+impl Extra {
+    /// Read the content of the file.
+    pub fn read(&self) -> Result<Vec<u8>> {
+        self.source.read(&self.path)
+    }
+}
+
+/// Read a single entry from a zip archive.
+fn read_entry(archive: &Path, entry: &str) -> Result<Vec<u8>> {
+    let mut archive = ZipArchive::new(File::open(archive)?)?;
+    let mut file = archive.by_name(entry)?;
+    let mut contents = Vec::with_capacity(usize::try_from(file.size()).unwrap_or_default());
+    file.read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+/// Read a file at the given offset in a tar archive.
+fn read_tar(archive: &Arc<Path>, offset: u64, size: u64, gzip: bool) -> Result<Vec<u8>> {
+    let mut reader = if gzip {
+        if let Some(data) = gzip::decompressed(archive)? {
+            let contents = usize::try_from(offset)
+                .ok()
+                .zip(usize::try_from(offset + size).ok())
+                .and_then(|(start, end)| data.get(start..end));
+
+            let Some(contents) = contents else {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            };
+
+            return Ok(contents.to_vec());
+        }
+
+        // Archives which are too large to keep decompressed in memory are
+        // decompressed up to the file.
+        let mut reader = tar_reader(archive, gzip)?;
+        io::copy(&mut reader.by_ref().take(offset), &mut io::sink())?;
+        reader
+    } else {
+        let mut file = File::open(archive)?;
+        file.seek(SeekFrom::Start(offset))?;
+        Box::new(file)
+    };
+
+    let mut contents = Vec::with_capacity(usize::try_from(size).unwrap_or_default());
+    reader.by_ref().take(size).read_to_end(&mut contents)?;
+
+    if contents.len() as u64 != size {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+
+    Ok(contents)
+}
+
+/// Open a tar archive for reading, decompressing it if it's compressed with
+/// gzip.
+pub(crate) fn tar_reader(archive: &Path, gzip: bool) -> Result<Box<dyn Read>> {
+    let file = BufReader::new(File::open(archive)?);
+
+    if gzip {
+        Ok(Box::new(GzDecoder::new(file)))
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+/// The most common dimensions of the pages in a book, and pages which stand
+/// out from them.
+pub struct Dimensions<'a> {
+    /// The median width and height of pages.
+    pub typical: (u32, u32),
+    /// Pages whose dimensions are far from the typical ones.
+    pub outliers: Vec<(&'a Page, (u32, u32))>,
 }
 
 /// Data about a book.
 pub struct Book {
-    /// The directory where the book is located.
+    /// The directory where the book is located, or the archive its pages are
+    /// read from.
     pub dir: PathBuf,
     /// The name of the book.
     pub name: String,
-    /// The pages in the book.
+    /// The pages in the book, in the natural order of their file names.
     pub pages: Vec<Page>,
     /// The series numbers associated with the book.
     pub numbers: BTreeSet<u32>,
+    /// Pages which are byte-for-byte identical to the page before them, as
+    /// found by [`Book::find_duplicates`].
+    pub duplicates: Vec<PathBuf>,
+    /// The page which was detected as the front cover by [`Book::find_cover`],
+    /// if any. Otherwise the first page is used as the cover.
+    pub cover: Option<PathBuf>,
+    /// Files stored with the book which aren't pages.
+    pub extras: Vec<Extra>,
 }
 
 impl Book {
@@ -73,6 +354,184 @@ impl Book {
     /// Returns the total size of all pages in bytes.
     #[inline]
     pub fn bytes(&self) -> u64 {
-        self.pages.iter().map(|page| page.metadata.len()).sum()
+        self.pages.iter().map(|page| page.size).sum()
+    }
+
+    /// Returns the most recent modification time of any page, if known.
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.pages.iter().filter_map(|page| page.modified).max()
+    }
+
+    /// Returns the gaps in the numbering of pages, between the lowest and the
+    /// highest page number.
+    ///
+    /// Pages without a number in their file name, like `cover.jpg`, are
+    /// ignored.
+    pub fn missing_pages(&self) -> Vec<RangeInclusive<u32>> {
+        let numbers = self
+            .pages
+            .iter()
+            .filter_map(|page| page.number)
+            .collect::<BTreeSet<_>>();
+
+        let mut missing = Vec::new();
+        let mut it = numbers.into_iter();
+
+        let Some(mut prev) = it.next() else {
+            return missing;
+        };
+
+        for n in it {
+            if n > prev + 1 {
+                missing.push(prev + 1..=n - 1);
+            }
+
+            prev = n;
+        }
+
+        missing
+    }
+
+    /// Returns the typical dimensions of pages and pages which stand out from
+    /// them, which suggests that pages come from mixed sources or that an
+    /// image is misplaced.
+    ///
+    /// Widths are allowed to vary more than heights, since double-page spreads
+    /// are twice as wide as other pages.
+    pub fn dimensions(&self) -> Option<Dimensions<'_>> {
+        let pages = self
+            .pages
+            .iter()
+            .filter_map(|page| Some((page, page.dimensions?)))
+            .collect::<Vec<_>>();
+
+        let mut widths = pages.iter().map(|(_, (w, _))| *w).collect::<Vec<_>>();
+        let mut heights = pages.iter().map(|(_, (_, h))| *h).collect::<Vec<_>>();
+        widths.sort();
+        heights.sort();
+
+        let typical = (
+            *widths.get(widths.len() / 2)?,
+            *heights.get(heights.len() / 2)?,
+        );
+
+        let outliers = pages
+            .into_iter()
+            .filter(|(_, (w, h))| {
+                let width = f64::from(*w) / f64::from(typical.0.max(1));
+                let height = f64::from(*h) / f64::from(typical.1.max(1));
+                !(MIN_WIDTH_RATIO..=MAX_WIDTH_RATIO).contains(&width)
+                    || !(MIN_HEIGHT_RATIO..=MAX_HEIGHT_RATIO).contains(&height)
+            })
+            .collect();
+
+        Some(Dimensions { typical, outliers })
+    }
+
+    /// Find pages which are exact duplicates of the page before them, which
+    /// happens when a page is accidentally saved twice.
+    ///
+    /// Only consecutive pages of the same size are read and compared, so books
+    /// without duplicates are cheap to check.
+    pub fn find_duplicates(&mut self) -> Result<()> {
+        let mut previous = None;
+
+        for pair in self.pages.windows(2) {
+            let [a, b] = pair else {
+                continue;
+            };
+
+            if a.size != b.size {
+                previous = None;
+                continue;
+            }
+
+            let a_data = match previous.take() {
+                Some(data) => data,
+                None => a.read()?,
+            };
+
+            let b_data = b.read()?;
+
+            if a_data == b_data {
+                self.duplicates.push(b.path.clone());
+            }
+
+            previous = Some(b_data);
+        }
+
+        Ok(())
+    }
+
+    /// Remove pages found by [`Book::find_duplicates`], renumbering the pages
+    /// which are left.
+    pub fn remove_duplicates(&mut self) {
+        if self.duplicates.is_empty() {
+            return;
+        }
+
+        let duplicates = self.duplicates.iter().collect::<HashSet<_>>();
+        self.pages.retain(|page| !duplicates.contains(&page.path));
+        self.renumber();
+    }
+
+    /// Find the front cover among the pages, which is the first page whose
+    /// file stem matches `pattern`, and move it to the front of the book.
+    ///
+    /// Covers are commonly named something like `cover.jpg`, which sorts
+    /// after numbered pages in natural order.
+    pub fn find_cover(&mut self, pattern: &Regex) {
+        let index = self.pages.iter().position(|page| {
+            page.path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .is_some_and(|s| pattern.is_match(s))
+        });
+
+        let Some(index) = index else {
+            return;
+        };
+
+        self.cover = Some(self.pages[index].path.clone());
+
+        if index > 0 {
+            let page = self.pages.remove(index);
+            self.pages.insert(0, page);
+            self.renumber();
+        }
+    }
+
+    /// Name pages after their position in the book.
+    fn renumber(&mut self) {
+        for (n, page) in self.pages.iter_mut().enumerate() {
+            let ext = page.name.rsplit_once('.').map_or("", |(_, ext)| ext);
+            page.name = format!("p{n:03}.{ext}");
+        }
+    }
+
+    /// Returns a cheap key used to detect the same book appearing in multiple
+    /// catalogs, consisting of its name, page count and total size.
+    #[inline]
+    pub fn dedupe_key(&self) -> (&str, usize, u64) {
+        (&self.name, self.pages.len(), self.bytes())
     }
 }
+
+/// Format ranges of page numbers like `3, 7-9`.
+pub(crate) fn format_ranges(ranges: &[RangeInclusive<u32>]) -> String {
+    let mut out = String::new();
+
+    for (n, range) in ranges.iter().enumerate() {
+        if n > 0 {
+            out.push_str(", ");
+        }
+
+        if range.start() == range.end() {
+            _ = write!(out, "{}", range.start());
+        } else {
+            _ = write!(out, "{}-{}", range.start(), range.end());
+        }
+    }
+
+    out
+}
@@ -1,20 +1,32 @@
-use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::style::{Modifier, Style, Stylize};
+
+use crate::theme::Theme;
 
 /// Centralized styling configuration for the TUI.
 pub(crate) struct Styles {
     selected_marker: &'static str,
     done_marker: &'static str,
     editing_marker: &'static str,
-    color_done: Color,
-    color_normal: Color,
-    color_not_done: Color,
-    color_dim: Color,
-    color_header: Color,
-    color_editing: Color,
-    color_warning: Color,
+    theme: Theme,
+}
+
+impl Default for Styles {
+    fn default() -> Self {
+        Self::new(Theme::default())
+    }
 }
 
 impl Styles {
+    /// Construct styles using the colors of the given theme.
+    pub(crate) fn new(theme: Theme) -> Self {
+        Self {
+            selected_marker: "*",
+            done_marker: "✓",
+            editing_marker: ">",
+            theme,
+        }
+    }
+
     pub(crate) fn selected(&self, selected: bool) -> &'static str {
         if selected { self.selected_marker } else { " " }
     }
@@ -31,9 +43,9 @@ impl Styles {
         let mut s = Style::default();
 
         if done {
-            s = s.fg(self.color_done);
+            s = s.fg(self.theme.picked);
         } else {
-            s = s.fg(self.color_not_done);
+            s = s.fg(self.theme.unpicked);
         };
 
         if selected {
@@ -47,9 +59,9 @@ impl Styles {
         let mut s = Style::default();
 
         if done {
-            s = s.fg(self.color_done);
+            s = s.fg(self.theme.picked);
         } else {
-            s = s.fg(self.color_normal);
+            s = s.fg(self.theme.normal);
         };
 
         if selected {
@@ -60,32 +72,36 @@ impl Styles {
     }
 
     pub(crate) fn header_style(&self) -> Style {
-        Style::default().fg(self.color_header).bold()
+        Style::default().fg(self.theme.header).bold()
     }
 
     pub(crate) fn header_hint_style(&self) -> Style {
-        Style::default().fg(self.color_header)
+        Style::default().fg(self.theme.header)
+    }
+
+    pub(crate) fn footer_style(&self) -> Style {
+        Style::default().fg(self.theme.footer).bold()
     }
 
     pub(crate) fn dim_style(&self) -> Style {
-        Style::default().fg(self.color_dim)
+        Style::default().fg(self.theme.dim)
     }
 
     pub(crate) fn warning_style(&self) -> Style {
-        Style::default().fg(self.color_warning).bold()
+        Style::default().fg(self.theme.warning).bold()
     }
 
     pub(crate) fn warning_text_style(&self) -> Style {
-        Style::default().fg(self.color_warning)
+        Style::default().fg(self.theme.warning)
     }
 
     pub(crate) fn button_style(&self, selected: bool, positive: bool) -> Style {
         let mut s = Style::default();
 
         if positive {
-            s = s.fg(self.color_done);
+            s = s.fg(self.theme.picked);
         } else {
-            s = s.fg(self.color_not_done);
+            s = s.fg(self.theme.unpicked);
         }
 
         if selected {
@@ -99,11 +115,11 @@ impl Styles {
         let mut s = Style::default();
 
         if editing {
-            s = s.fg(self.color_editing).add_modifier(Modifier::BOLD);
+            s = s.fg(self.theme.editing).add_modifier(Modifier::BOLD);
         } else if selected {
-            s = s.fg(self.color_normal).add_modifier(Modifier::BOLD);
+            s = s.fg(self.theme.normal).add_modifier(Modifier::BOLD);
         } else {
-            s = s.fg(self.color_normal);
+            s = s.fg(self.theme.normal);
         }
 
         s
@@ -119,17 +135,3 @@ impl Styles {
         }
     }
 }
-
-/// Global styles instance.
-pub(crate) const STYLES: Styles = Styles {
-    selected_marker: "*",
-    done_marker: "✓",
-    editing_marker: ">",
-    color_done: Color::Green,
-    color_normal: Color::Reset,
-    color_not_done: Color::Red,
-    color_dim: Color::DarkGray,
-    color_header: Color::Cyan,
-    color_editing: Color::Cyan,
-    color_warning: Color::Yellow,
-};
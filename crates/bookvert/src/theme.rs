@@ -0,0 +1,71 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+use ratatui::style::Color;
+use serde::Deserialize;
+
+use crate::settings;
+
+/// Colors used by the interactive interface.
+///
+/// Colors can be overridden from a TOML file, where each field is optional and
+/// can be a color name like `light-blue`, an ANSI index like `8`, or a hex
+/// color like `#ff8800`.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct Theme {
+    /// Color of catalogs and books which have been picked.
+    pub(crate) picked: Color,
+    /// Color of catalogs which have not been picked.
+    pub(crate) unpicked: Color,
+    /// Color of regular items.
+    pub(crate) normal: Color,
+    /// Color of secondary information.
+    pub(crate) dim: Color,
+    /// Color of headers and key hints.
+    pub(crate) header: Color,
+    /// Color of the footer, like the active filter.
+    pub(crate) footer: Color,
+    /// Color of text being edited.
+    pub(crate) editing: Color,
+    /// Color of warnings.
+    pub(crate) warning: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            picked: Color::Green,
+            unpicked: Color::Red,
+            normal: Color::Reset,
+            dim: Color::DarkGray,
+            header: Color::Cyan,
+            footer: Color::Cyan,
+            editing: Color::Cyan,
+            warning: Color::Yellow,
+        }
+    }
+}
+
+impl Theme {
+    /// Load a theme from the given path, if it exists.
+    pub(crate) fn load(path: &Path) -> Result<Option<Self>> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| anyhow!("{}: Failed to read", path.display())),
+        };
+
+        let theme = toml::from_str(&contents)
+            .with_context(|| anyhow!("{}: Failed to parse", path.display()))?;
+
+        Ok(Some(theme))
+    }
+}
+
+/// The default path where the theme is loaded from.
+pub(crate) fn default_path() -> Option<PathBuf> {
+    Some(settings::config_dir()?.join("theme.toml"))
+}
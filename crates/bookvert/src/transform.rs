@@ -0,0 +1,529 @@
+use core::str::FromStr;
+
+use std::io::Cursor;
+use std::path::PathBuf;
+
+use anyhow::{Result, anyhow};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::{CompressionType, FilterType as PngFilterType, PngEncoder};
+use image::imageops::FilterType;
+use image::metadata::Orientation;
+use image::{
+    DynamicImage, GenericImageView, GrayImage, ImageDecoder, ImageFormat, ImageReader, Luma,
+};
+
+use crate::{Book, Page};
+
+/// The quality used when pages which were JPEGs are encoded again.
+const JPEG_QUALITY: u8 = 90;
+
+/// The difference between the largest and smallest color channel at which a
+/// pixel is considered colored.
+const CHROMA_THRESHOLD: u8 = 32;
+
+/// The share of colored pixels at which a page is considered colored by
+/// `--grayscale auto`.
+const COLORED_SHARE: f64 = 0.05;
+
+/// The share of a page which has to remain after trimming, below which the
+/// margins are assumed to be misdetected and the page is left as it is.
+const TRIM_MIN_REMAINING: f64 = 0.25;
+
+/// How pages are converted to grayscale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Grayscale {
+    /// Convert pages unless they are substantially colored, like covers.
+    Auto,
+    /// Convert all pages.
+    Always,
+}
+
+impl FromStr for Grayscale {
+    type Err = anyhow::Error;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(Grayscale::Auto),
+            "always" => Ok(Grayscale::Always),
+            _ => Err(anyhow!("Invalid grayscale mode '{s}'")),
+        }
+    }
+}
+
+/// The content of a page prepared for packaging.
+pub(crate) struct Content<'a> {
+    /// The page the content was read from.
+    pub(crate) page: &'a Page,
+    /// The name of the page in the output, which has the extension of its
+    /// possibly transformed content.
+    pub(crate) name: String,
+    /// The content of the page.
+    pub(crate) data: Vec<u8>,
+    /// A suffix which tells the halves of a split spread apart, otherwise
+    /// empty.
+    pub(crate) half: &'static str,
+}
+
+impl Content<'_> {
+    /// Get the extension of the content.
+    pub(crate) fn ext(&self) -> &str {
+        self.name.rsplit_once('.').map_or("", |(_, ext)| ext)
+    }
+}
+
+/// A page which was resized by `--max-dimension`.
+pub(crate) struct Resized {
+    pub(crate) path: PathBuf,
+    pub(crate) from: (u32, u32),
+    pub(crate) to: (u32, u32),
+}
+
+/// How double-page spreads are split by `--split-spreads`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Split {
+    /// The ratio of width to height above which a page is considered a spread.
+    pub(crate) ratio: f32,
+    /// Put the right half first, for books read from right to left.
+    pub(crate) rtl: bool,
+    /// Don't split the cover, which is commonly a wraparound of the front and
+    /// back cover.
+    pub(crate) skip_cover: bool,
+}
+
+/// What happened to pages while they were transformed.
+#[derive(Default)]
+pub(crate) struct Report {
+    /// Pages which couldn't be transformed or packaged.
+    pub(crate) skipped: Vec<String>,
+    /// Pages which were resized.
+    pub(crate) resized: Vec<Resized>,
+    /// The number of spreads which were split into two pages.
+    pub(crate) split: usize,
+    /// The number of pages whose margins were trimmed.
+    pub(crate) trimmed: usize,
+    /// The sum of the share of each trimmed page which was removed.
+    pub(crate) trimmed_share: f64,
+    /// The number of pages which weren't trimmed because almost everything
+    /// would have been removed.
+    pub(crate) trim_misdetected: usize,
+    /// The number of pages converted to grayscale.
+    pub(crate) grayscale: usize,
+    /// The number of colored pages kept by `--grayscale auto`.
+    pub(crate) colored: usize,
+    /// The number of PNG pages which were losslessly optimized.
+    pub(crate) optimized: usize,
+    /// The number of bytes saved by optimizing PNG pages.
+    pub(crate) optimized_saved: u64,
+}
+
+/// Transformations applied to page images before they are packaged.
+#[derive(Default)]
+pub(crate) struct Transform {
+    /// Recompress pages to WebP at the given quality from 0 to 100.
+    pub(crate) webp: Option<f32>,
+    /// Downscale pages whose longest side exceeds this number of pixels.
+    pub(crate) max_dimension: Option<u32>,
+    /// Convert pages to grayscale.
+    pub(crate) grayscale: Option<Grayscale>,
+    /// Split double-page spreads into two pages.
+    pub(crate) split: Option<Split>,
+    /// Trim uniform white or black margins, where pixels whose brightness is
+    /// within the given tolerance of the margin are considered part of it.
+    pub(crate) trim: Option<u8>,
+    /// Losslessly recompress PNG pages, keeping them if they shrink.
+    pub(crate) optimize_png: bool,
+}
+
+impl Transform {
+    /// Read the pages of a book and apply transformations to them.
+    ///
+    /// Pages which can't be transformed are kept as they are and described in
+    /// the report.
+    pub(crate) fn apply<'a>(
+        &self,
+        book: &'a Book,
+        report: &mut Report,
+    ) -> Result<Vec<Content<'a>>> {
+        let mut contents = Vec::with_capacity(book.pages.len());
+
+        for (index, page) in book.pages.iter().enumerate() {
+            let data = page.read()?;
+
+            let mut content = Content {
+                page,
+                name: page.name.clone(),
+                data,
+                half: "",
+            };
+
+            let split = self.split.filter(|split| !(split.skip_cover && index == 0));
+
+            match self.transform(&mut content, split, report) {
+                Ok(second) => {
+                    contents.push(content);
+                    contents.extend(second);
+                }
+                Err(e) => {
+                    report
+                        .skipped
+                        .push(format!("{}: {e}, kept as-is", page.path.display()));
+                    contents.push(content);
+                }
+            }
+        }
+
+        // This also covers pages which were encoded as PNG above.
+        if self.optimize_png {
+            for content in &mut contents {
+                if let Err(e) = optimize_png(content, report) {
+                    report.skipped.push(format!(
+                        "{}: {e}, not optimized",
+                        content.page.path.display()
+                    ));
+                }
+            }
+        }
+
+        Ok(contents)
+    }
+
+    /// Transform the content of a page, returning the second half of the page
+    /// if it was split.
+    fn transform<'a>(
+        &self,
+        content: &mut Content<'a>,
+        split: Option<Split>,
+        report: &mut Report,
+    ) -> Result<Option<Content<'a>>> {
+        if self.webp.is_none()
+            && self.max_dimension.is_none()
+            && self.grayscale.is_none()
+            && split.is_none()
+            && self.trim.is_none()
+        {
+            return Ok(None);
+        }
+
+        let reader = ImageReader::new(Cursor::new(&content.data[..])).with_guessed_format()?;
+
+        let Some(format) = reader.format() else {
+            return Err(anyhow!("Unrecognized image format"));
+        };
+
+        let mut decoder = reader.into_decoder()?;
+        let orientation = decoder.orientation()?;
+        let dimensions = oriented(decoder.dimensions(), orientation);
+
+        let exceeds = self
+            .max_dimension
+            .is_some_and(|max| longest_side(dimensions) > max);
+
+        // Pages which are already grayscale don't have to be converted.
+        let colors = self.grayscale.is_some() && decoder.color_type().has_color();
+
+        let spread = split.is_some_and(|split| is_spread(dimensions, split.ratio));
+
+        // Pages which are small enough, have no colors to remove and aren't
+        // spreads are left untouched unless they are recompressed or trimmed
+        // anyway.
+        if self.webp.is_none() && self.trim.is_none() && !exceeds && !colors && !spread {
+            return Ok(None);
+        }
+
+        let mut image = DynamicImage::from_decoder(decoder)?;
+        image.apply_orientation(orientation);
+
+        let mut modified = false;
+
+        if let Some(tolerance) = self.trim
+            && let Some((x, y, width, height)) = margins(&image, tolerance)
+        {
+            let total = f64::from(image.width()) * f64::from(image.height());
+            let remaining = f64::from(width) * f64::from(height) / total;
+
+            if remaining < TRIM_MIN_REMAINING {
+                report.trim_misdetected += 1;
+            } else {
+                image = image.crop_imm(x, y, width, height);
+                report.trimmed += 1;
+                report.trimmed_share += 1.0 - remaining;
+                modified = true;
+            }
+        }
+
+        if let Some(mode) = self.grayscale
+            && colors
+        {
+            if mode == Grayscale::Auto && is_colored(&image) {
+                report.colored += 1;
+            } else {
+                image = if image.color().has_alpha() {
+                    DynamicImage::ImageLumaA8(image.to_luma_alpha8())
+                } else {
+                    DynamicImage::ImageLuma8(image.to_luma8())
+                };
+
+                report.grayscale += 1;
+                modified = true;
+            }
+        }
+
+        // Trimming might have changed the shape of the page.
+        if let Some(split) = split
+            && is_spread(image.dimensions(), split.ratio)
+        {
+            let (width, height) = image.dimensions();
+            let half = width / 2;
+
+            let left = image.crop_imm(0, 0, half, height);
+            let right = image.crop_imm(half, 0, width - half, height);
+
+            let (first, second) = if split.rtl {
+                (right, left)
+            } else {
+                (left, right)
+            };
+
+            // The halves are encoded below, which gives them their extension.
+            let stem = content
+                .name
+                .rsplit_once('.')
+                .map_or(content.name.as_str(), |(stem, _)| stem);
+
+            let mut other = Content {
+                page: content.page,
+                name: format!("{stem}b"),
+                data: Vec::new(),
+                half: "b",
+            };
+
+            content.name = format!("{stem}a");
+            content.half = "a";
+
+            self.finish(content, first, format, true, report)?;
+            self.finish(&mut other, second, format, true, report)?;
+            report.split += 1;
+            return Ok(Some(other));
+        }
+
+        self.finish(content, image, format, modified, report)?;
+        Ok(None)
+    }
+
+    /// Resize and encode a decoded page, unless it's left untouched.
+    fn finish(
+        &self,
+        content: &mut Content<'_>,
+        mut image: DynamicImage,
+        format: ImageFormat,
+        mut modified: bool,
+        report: &mut Report,
+    ) -> Result<()> {
+        if let Some(max) = self.max_dimension
+            && longest_side(image.dimensions()) > max
+        {
+            let from = image.dimensions();
+            image = image.resize(max, max, FilterType::Lanczos3);
+
+            report.resized.push(Resized {
+                path: content.page.path.clone(),
+                from,
+                to: image.dimensions(),
+            });
+
+            modified = true;
+        }
+
+        if let Some(quality) = self.webp {
+            let data = encode_webp(&image, quality)?;
+
+            // Pages which are already well compressed might grow, in which
+            // case the original is kept unless it had to be modified.
+            if modified || data.len() < content.data.len() {
+                content.data = data;
+                content.name = with_ext(&content.name, "webp");
+                return Ok(());
+            }
+        }
+
+        if modified {
+            let (data, ext) = encode(&image, format)?;
+            content.data = data;
+            content.name = with_ext(&content.name, ext);
+        }
+
+        Ok(())
+    }
+}
+
+/// Test if an image is substantially colored, as opposed to a grayscale page
+/// which is stored in color.
+fn is_colored(image: &DynamicImage) -> bool {
+    let rgb = image.to_rgb8();
+    let total = rgb.pixels().len();
+
+    if total == 0 {
+        return false;
+    }
+
+    let colored = rgb
+        .pixels()
+        .filter(|p| {
+            let [r, g, b] = p.0;
+            r.max(g).max(b) - r.min(g).min(b) >= CHROMA_THRESHOLD
+        })
+        .count();
+
+    colored as f64 / total as f64 >= COLORED_SHARE
+}
+
+/// Find the bounds of the content of an image inside uniform margins as
+/// `(x, y, width, height)`, or `None` if there are no margins to trim.
+///
+/// The margin is white or black depending on the top left pixel, and pixels
+/// whose brightness is within `tolerance` of it are considered part of it.
+fn margins(image: &DynamicImage, tolerance: u8) -> Option<(u32, u32, u32, u32)> {
+    let luma = image.to_luma8();
+    let (width, height) = luma.dimensions();
+
+    let corner = luma.get_pixel_checked(0, 0)?.0[0];
+    let margin = if corner >= 128 { u8::MAX } else { 0 };
+    let blank = |x: u32, y: u32| luma.get_pixel(x, y).0[0].abs_diff(margin) <= tolerance;
+
+    let row = |y: u32| (0..width).all(|x| blank(x, y));
+    let column = |x: u32, top: u32, bottom: u32| (top..bottom).all(|y| blank(x, y));
+
+    let top = (0..height).find(|&y| !row(y))?;
+    let bottom = (top..height).rfind(|&y| !row(y))? + 1;
+    let left = (0..width).find(|&x| !column(x, top, bottom))?;
+    let right = (left..width).rfind(|&x| !column(x, top, bottom))? + 1;
+
+    if (left, top, right, bottom) == (0, 0, width, height) {
+        return None;
+    }
+
+    Some((left, top, right - left, bottom - top))
+}
+
+/// Test if an image with the given dimensions is a spread, whose ratio of
+/// width to height exceeds `ratio`.
+fn is_spread((width, height): (u32, u32), ratio: f32) -> bool {
+    height > 0 && width as f32 / height as f32 > ratio
+}
+
+/// Get the dimensions of an image once the given orientation is applied.
+fn oriented((width, height): (u32, u32), orientation: Orientation) -> (u32, u32) {
+    match orientation {
+        Orientation::Rotate90
+        | Orientation::Rotate270
+        | Orientation::Rotate90FlipH
+        | Orientation::Rotate270FlipH => (height, width),
+        _ => (width, height),
+    }
+}
+
+/// Get the longest side of an image with the given dimensions.
+fn longest_side((width, height): (u32, u32)) -> u32 {
+    width.max(height)
+}
+
+/// Encode an image in the format it was read from if possible, or as PNG
+/// otherwise, returning the data and its extension.
+fn encode(image: &DynamicImage, format: ImageFormat) -> Result<(Vec<u8>, &'static str)> {
+    let mut data = Vec::new();
+
+    if format == ImageFormat::Jpeg {
+        let encoder = JpegEncoder::new_with_quality(&mut data, JPEG_QUALITY);
+
+        if image.color().has_color() {
+            image.to_rgb8().write_with_encoder(encoder)?;
+        } else {
+            image.to_luma8().write_with_encoder(encoder)?;
+        }
+
+        return Ok((data, "jpg"));
+    }
+
+    image.write_to(&mut Cursor::new(&mut data), ImageFormat::Png)?;
+    Ok((data, "png"))
+}
+
+/// Losslessly recompress a PNG page with the strongest compression and the
+/// smallest color type which can hold its pixels, keeping the result if it's
+/// smaller. Other formats are left untouched.
+fn optimize_png(content: &mut Content<'_>, report: &mut Report) -> Result<()> {
+    let reader = ImageReader::new(Cursor::new(&content.data[..])).with_guessed_format()?;
+
+    if reader.format() != Some(ImageFormat::Png) {
+        return Ok(());
+    }
+
+    let mut decoder = reader.into_decoder()?;
+
+    // Metadata is lost when encoding, which would change how pages with an
+    // orientation or a color profile are displayed.
+    if decoder.orientation()? != Orientation::NoTransforms || decoder.icc_profile()?.is_some() {
+        return Ok(());
+    }
+
+    let image = reduce(DynamicImage::from_decoder(decoder)?);
+
+    let mut data = Vec::new();
+    let encoder =
+        PngEncoder::new_with_quality(&mut data, CompressionType::Best, PngFilterType::Adaptive);
+    image.write_with_encoder(encoder)?;
+
+    if data.len() < content.data.len() {
+        report.optimized += 1;
+        report.optimized_saved += (content.data.len() - data.len()) as u64;
+        content.data = data;
+    }
+
+    Ok(())
+}
+
+/// Drop alpha and color channels from an 8-bit image if that doesn't change
+/// any of its pixels.
+fn reduce(image: DynamicImage) -> DynamicImage {
+    let image = match image {
+        DynamicImage::ImageRgba8(rgba) if rgba.pixels().all(|p| p[3] == u8::MAX) => {
+            DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(rgba).to_rgb8())
+        }
+        DynamicImage::ImageLumaA8(luma) if luma.pixels().all(|p| p[1] == u8::MAX) => {
+            DynamicImage::ImageLuma8(DynamicImage::ImageLumaA8(luma).to_luma8())
+        }
+        image => image,
+    };
+
+    match image {
+        DynamicImage::ImageRgb8(rgb) if rgb.pixels().all(|p| p[0] == p[1] && p[1] == p[2]) => {
+            let (width, height) = rgb.dimensions();
+            let luma = GrayImage::from_fn(width, height, |x, y| Luma([rgb.get_pixel(x, y)[0]]));
+            DynamicImage::ImageLuma8(luma)
+        }
+        image => image,
+    }
+}
+
+/// Encode an image as lossy WebP.
+fn encode_webp(image: &DynamicImage, quality: f32) -> Result<Vec<u8>> {
+    let (width, height) = image.dimensions();
+
+    let memory = if image.color().has_alpha() {
+        let rgba = image.to_rgba8();
+        webp::Encoder::from_rgba(&rgba, width, height).encode_simple(false, quality)
+    } else {
+        let rgb = image.to_rgb8();
+        webp::Encoder::from_rgb(&rgb, width, height).encode_simple(false, quality)
+    };
+
+    let memory = memory.map_err(|e| anyhow!("Failed to encode WebP: {e:?}"))?;
+    Ok(memory.to_vec())
+}
+
+/// Replace the extension of a page name.
+fn with_ext(name: &str, ext: &str) -> String {
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    format!("{stem}.{ext}")
+}
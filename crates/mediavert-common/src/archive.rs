@@ -0,0 +1,62 @@
+//! Naming of archives.
+
+use std::ffi::OsStr;
+use std::path::Path;
+
+/// Test if a path has a `.tar` extension before its last extension, like
+/// `album.tar.gz`. Extensions are compared case-insensitively.
+pub fn is_tar(path: &Path) -> bool {
+    Path::new(path.file_stem().unwrap_or_default())
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("tar"))
+}
+
+/// Test if a path is named like a gzip-compressed tar archive, like
+/// `album.tar.gz`.
+pub fn is_tar_gz(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("gz"))
+        && is_tar(path)
+}
+
+/// Get the name of an archive without its extension, which for compressed
+/// tar archives like `album.tar.gz` also excludes the `.tar` extension.
+pub fn stem(path: &Path) -> Option<&OsStr> {
+    let stem = path.file_stem()?;
+
+    if is_tar_gz(path) {
+        return Path::new(stem).file_stem();
+    }
+
+    Some(stem)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+    use std::path::Path;
+
+    use super::{is_tar, is_tar_gz, stem};
+
+    #[test]
+    fn tar_names() {
+        assert!(is_tar(Path::new("a.tar.gz")));
+        assert!(is_tar(Path::new("a.TAR.gz")));
+        assert!(!is_tar(Path::new("a.gz")));
+        assert!(!is_tar(Path::new("a.tar")));
+
+        assert!(is_tar_gz(Path::new("dir/a.tar.gz")));
+        assert!(is_tar_gz(Path::new("a.Tar.GZ")));
+        assert!(!is_tar_gz(Path::new("a.tar.bz2")));
+        assert!(!is_tar_gz(Path::new("a.gz")));
+    }
+
+    #[test]
+    fn stems() {
+        assert_eq!(stem(Path::new("dir/a.tar.gz")), Some(OsStr::new("a")));
+        assert_eq!(stem(Path::new("a.TAR.GZ")), Some(OsStr::new("a")));
+        assert_eq!(stem(Path::new("a.tar")), Some(OsStr::new("a")));
+        assert_eq!(stem(Path::new("a.b.zip")), Some(OsStr::new("a.b")));
+        assert_eq!(stem(Path::new("a.gz")), Some(OsStr::new("a")));
+    }
+}
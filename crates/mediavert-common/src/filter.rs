@@ -0,0 +1,184 @@
+//! Filtering of the files found while walking input paths, like with
+//! `--include` and `--exclude` in audiovert or `--include-path` and
+//! `--exclude-path` in bookvert.
+
+use core::error::Error;
+use core::fmt;
+use core::str::FromStr;
+
+use std::path::Path;
+
+use globset::{GlobBuilder, GlobMatcher};
+use regex::Regex;
+
+/// The error raised when a [`Pattern`] can't be parsed.
+#[derive(Debug)]
+pub enum PatternErr {
+    Glob(globset::Error),
+    Regex(regex::Error),
+}
+
+impl fmt::Display for PatternErr {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatternErr::Glob(err) => err.fmt(f),
+            PatternErr::Regex(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for PatternErr {}
+
+/// A pattern matched against the path of a file relative to the input path
+/// it was found in.
+///
+/// Patterns prefixed with `re:` are regular expressions which are searched
+/// for anywhere in the path. Other patterns are globs, where globs without a
+/// `/` match any single component of the path, like `*.txt` or `Extras`.
+/// Globs with a `/` match the start of the path, like `Album/Extras`.
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Glob {
+        matcher: GlobMatcher,
+        component: bool,
+    },
+    Regex(Regex),
+}
+
+impl Pattern {
+    fn is_match(&self, path: &Path) -> bool {
+        match self {
+            Pattern::Glob {
+                matcher,
+                component: true,
+            } => path.components().any(|c| matcher.is_match(c.as_os_str())),
+            Pattern::Glob { matcher, .. } => path
+                .ancestors()
+                .filter(|p| !p.as_os_str().is_empty())
+                .any(|p| matcher.is_match(p)),
+            Pattern::Regex(regex) => {
+                let path = path
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+
+                regex.is_match(&path)
+            }
+        }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = PatternErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(regex) = s.strip_prefix("re:") {
+            return Ok(Pattern::Regex(
+                Regex::new(regex).map_err(PatternErr::Regex)?,
+            ));
+        }
+
+        let pattern = s.trim_matches('/');
+
+        let matcher = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(PatternErr::Glob)?
+            .compile_matcher();
+
+        Ok(Pattern::Glob {
+            matcher,
+            component: !pattern.contains('/'),
+        })
+    }
+}
+
+/// Filters which decide which of the files found are processed.
+///
+/// A file is included if it matches any of the include patterns, or if there
+/// are none, and none of the exclude patterns.
+#[derive(Default)]
+pub struct Filter {
+    pub include: Vec<Pattern>,
+    pub exclude: Vec<Pattern>,
+}
+
+impl Filter {
+    /// Test if a file with the given relative path is included.
+    pub fn is_included(&self, path: &Path) -> bool {
+        let included = self.include.is_empty() || self.include.iter().any(|p| p.is_match(path));
+        included && !self.is_excluded(path)
+    }
+
+    /// Test if a file with the given relative path is excluded, regardless of
+    /// the include patterns.
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        self.exclude.iter().any(|p| p.is_match(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{Filter, Pattern};
+
+    fn pattern(s: &str) -> Pattern {
+        s.parse().unwrap()
+    }
+
+    fn is_match(pattern: &str, path: &str) -> bool {
+        self::pattern(pattern).is_match(Path::new(path))
+    }
+
+    #[test]
+    fn component_globs() {
+        assert!(is_match("*.txt", "Album/notes.txt"));
+        assert!(is_match("Extras", "Album/Extras/01.flac"));
+        assert!(!is_match("Extras", "Album/More Extras/01.flac"));
+        assert!(!is_match("*.txt", "Album/notes.txt.flac"));
+    }
+
+    #[test]
+    fn path_globs() {
+        assert!(is_match("Album/Extras", "Album/Extras/01.flac"));
+        assert!(is_match("/Album/Extras/", "Album/Extras/01.flac"));
+        assert!(!is_match("Album/Extras", "Other/Album/Extras/01.flac"));
+        assert!(is_match("*/Extras", "Album/Extras/01.flac"));
+        assert!(!is_match("*/Extras", "Artist/Album/Extras/01.flac"));
+    }
+
+    #[test]
+    fn regexes() {
+        assert!(is_match("re:Disc [0-9]+/", "Album/Disc 2/01.flac"));
+        assert!(!is_match("re:^Disc", "Album/Disc 2/01.flac"));
+        assert!("re:(".parse::<Pattern>().is_err());
+        assert!("[".parse::<Pattern>().is_err());
+    }
+
+    #[test]
+    fn filter() {
+        let path = Path::new("Album/Extras/notes.txt");
+
+        assert!(Filter::default().is_included(path));
+
+        let filter = Filter {
+            include: vec![pattern("*.flac")],
+            exclude: Vec::new(),
+        };
+
+        assert!(!filter.is_included(path));
+        assert!(!filter.is_excluded(path));
+
+        let filter = Filter {
+            include: vec![pattern("*.txt")],
+            exclude: vec![pattern("Extras")],
+        };
+
+        assert!(!filter.is_included(path));
+        assert!(filter.is_excluded(path));
+        assert!(filter.is_included(Path::new("Album/notes.txt")));
+    }
+}
@@ -0,0 +1,146 @@
+//! Glob patterns in input paths, like `music/**/*.flac` or `books/*/*.cbz`.
+
+use std::path::{Path, PathBuf};
+
+use globset::{GlobBuilder, GlobMatcher};
+
+/// An input path containing a glob pattern, which is split into the
+/// directory it's rooted in and a pattern matched against paths relative to
+/// that directory.
+///
+/// `*` and `?` don't match across directories, while `**` matches any number
+/// of directories.
+pub struct Glob {
+    base: PathBuf,
+    matcher: GlobMatcher,
+}
+
+impl Glob {
+    /// Parse an input path as a glob if it contains glob syntax.
+    ///
+    /// Paths which exist are never treated as globs, even if they contain
+    /// characters like `[`. The same goes for the directories leading up to
+    /// the pattern, so `Album [2020]/*.flac` matches the files in `Album
+    /// [2020]`.
+    pub fn parse(path: &Path) -> Result<Option<Self>, globset::Error> {
+        if path.exists() {
+            return Ok(None);
+        }
+
+        let mut base = PathBuf::new();
+        let mut pattern = Vec::new();
+
+        for c in path.components() {
+            let s = c.as_os_str().to_string_lossy();
+
+            if pattern.is_empty() && (!s.contains(['*', '?', '[', '{']) || base.join(c).exists()) {
+                base.push(c);
+            } else {
+                pattern.push(s);
+            }
+        }
+
+        if pattern.is_empty() {
+            return Ok(None);
+        }
+
+        if base.as_os_str().is_empty() {
+            base.push(".");
+        }
+
+        let pattern = pattern.join("/");
+
+        let matcher = GlobBuilder::new(&pattern)
+            .literal_separator(true)
+            .build()?
+            .compile_matcher();
+
+        Ok(Some(Self { base, matcher }))
+    }
+
+    /// The directory the glob is rooted in, which has to be walked to find
+    /// matching files.
+    pub fn base(&self) -> &Path {
+        &self.base
+    }
+
+    /// Test if a path found while walking the base directory matches.
+    pub fn is_match(&self, path: &Path) -> bool {
+        path.strip_prefix(&self.base)
+            .is_ok_and(|relative| self.matcher.is_match(relative))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::Glob;
+
+    fn parse(path: &str) -> Glob {
+        Glob::parse(Path::new(path)).unwrap().expect("a glob")
+    }
+
+    #[test]
+    fn pattern_only() {
+        let glob = parse("*.flac");
+        assert_eq!(glob.base(), Path::new("."));
+        assert!(glob.is_match(Path::new("./a.flac")));
+        assert!(!glob.is_match(Path::new("./a.mp3")));
+        // `*` doesn't match across directories.
+        assert!(!glob.is_match(Path::new("./album/a.flac")));
+    }
+
+    #[test]
+    fn recursive() {
+        let glob = parse("a/**/b");
+        assert_eq!(glob.base(), Path::new("a"));
+        assert!(glob.is_match(Path::new("a/b")));
+        assert!(glob.is_match(Path::new("a/x/b")));
+        assert!(glob.is_match(Path::new("a/x/y/b")));
+        assert!(!glob.is_match(Path::new("a/x/c")));
+        assert!(!glob.is_match(Path::new("c/x/b")));
+
+        let glob = parse("music/**/*.flac");
+        assert_eq!(glob.base(), Path::new("music"));
+        assert!(glob.is_match(Path::new("music/Artist/Album/01.flac")));
+    }
+
+    #[test]
+    fn base_stops_at_first_pattern() {
+        let glob = parse("books/*/vol?/*.cbz");
+        assert_eq!(glob.base(), Path::new("books"));
+        assert!(glob.is_match(Path::new("books/Series/vol1/001.cbz")));
+        assert!(!glob.is_match(Path::new("books/Series/vol10/001.cbz")));
+
+        let glob = parse("books/{a,b}/*.cbz");
+        assert_eq!(glob.base(), Path::new("books"));
+        assert!(glob.is_match(Path::new("books/b/001.cbz")));
+        assert!(!glob.is_match(Path::new("books/c/001.cbz")));
+    }
+
+    #[test]
+    fn not_globs() {
+        assert!(Glob::parse(Path::new("music/Album")).unwrap().is_none());
+        assert!(Glob::parse(Path::new("[")).is_err());
+    }
+
+    #[test]
+    fn existing_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let album = dir.path().join("Album [2020]");
+        let track = album.join("01 [Live].flac");
+        fs::create_dir(&album).unwrap();
+        fs::write(&track, b"").unwrap();
+
+        assert!(Glob::parse(&album).unwrap().is_none());
+        assert!(Glob::parse(&track).unwrap().is_none());
+
+        // Existing directories leading up to the pattern are part of the base.
+        let glob = Glob::parse(&album.join("*.flac")).unwrap().expect("a glob");
+        assert_eq!(glob.base(), album);
+        assert!(glob.is_match(&track));
+        assert!(!glob.is_match(&album.join("cover.jpg")));
+    }
+}
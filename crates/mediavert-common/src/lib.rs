@@ -0,0 +1,13 @@
+//! Utilities shared by [audiovert] and [bookvert], like how archives are
+//! named, how globs in input paths are parsed and how found files are
+//! filtered.
+//!
+//! This crate is an implementation detail of those tools and doesn't have a
+//! stable API.
+//!
+//! [audiovert]: https://docs.rs/audiovert
+//! [bookvert]: https://docs.rs/bookvert
+
+pub mod archive;
+pub mod filter;
+pub mod glob;
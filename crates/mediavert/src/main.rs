@@ -31,8 +31,8 @@ use clap::{Parser, Subcommand};
 
 #[derive(Subcommand)]
 enum Command {
-    Books(bookvert::cli::Bookvert),
-    Audio(audiovert::cli::Audiovert),
+    Books(Box<bookvert::cli::Bookvert>),
+    Audio(Box<audiovert::cli::Audiovert>),
 }
 
 const VERSION: &str = match option_env!("MEDIAVERT_VERSION") {